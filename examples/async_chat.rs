@@ -1,4 +1,6 @@
-use rustglm::{ChatCompletionRequest, ChatMessage, ZhipuClient};
+use std::time::Duration;
+
+use rustglm::{ChatCompletionRequest, ChatMessage, ChatTask, TaskPollPolicy, ZhipuClient};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -8,7 +10,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let task = client.async_chat(&request).await?;
     println!("submitted task={} status={}", task.id, task.task_status);
 
-    let result = client.async_result(&task.id).await?;
-    println!("task={} status={}", result.id, result.task_status);
+    let policy = TaskPollPolicy::default().deadline(Duration::from_secs(600));
+    let response = client.wait_for(ChatTask::from(&task), &policy).await?;
+    println!("{}", response.text().unwrap_or_default());
     Ok(())
 }
@@ -1,5 +1,4 @@
-use nextjson::json;
-use rustglm::ZhipuClient;
+use rustglm::{LayoutParsingRequest, OcrRequest, ZhipuClient};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -8,16 +7,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .ok_or("usage: cargo run --example document_understanding -- <image-url>")?;
     let client = ZhipuClient::new(std::env::var("ZHIPU_API_KEY")?)?;
     let ocr = client
-        .ocr(&json!({
-            "model": "glm-ocr",
-            "file_url": image_url,
-            "tool_type": "hand_write",
-            "language_type": "CHN_ENG"
-        }))
+        .recognize_text(
+            &OcrRequest::new(&image_url)
+                .language_type("CHN_ENG")
+                .probability(true),
+        )
         .await?;
-    println!("ocr: {ocr:#}");
+    for line in ocr.to_layout().blocks().flat_map(|block| &block.lines) {
+        println!("ocr {:?} {:?}: {}", line.bbox, line.confidence, line.text);
+    }
 
-    let layout = client.parse_layout(&json!({"file_url": image_url})).await?;
-    println!("layout: {layout:#}");
+    let layout = client
+        .analyze_layout(&LayoutParsingRequest::new(&image_url))
+        .await?
+        .to_layout();
+    for block in layout.blocks() {
+        println!(
+            "{} {:?} {:?}",
+            block.kind.as_str(),
+            block.bbox,
+            block.confidence
+        );
+    }
+    println!("{}", layout.to_markdown());
     Ok(())
 }
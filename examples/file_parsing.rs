@@ -1,5 +1,4 @@
-use nextjson::json;
-use rustglm::ZhipuClient;
+use rustglm::{FileParseRequest, FileParserTool, TaskPollPolicy, ZhipuClient};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -7,20 +6,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .nth(1)
         .ok_or("usage: cargo run --example file_parsing -- https://example.com/document.pdf")?;
     let client = ZhipuClient::new(std::env::var("ZHIPU_API_KEY")?)?;
+    let request = FileParseRequest::new(&file_url, FileParserTool::Lite);
 
-    let task = client
-        .create_file_parse_task(&json!({"file_url": file_url, "tool_type": "lite"}))
-        .await?;
-    if let Some(task_id) = task.get("task_id").and_then(|value| value.as_str()) {
-        let result = client.file_parse_result(task_id, "text").await?;
-        println!("async parse: {result:#}");
-    } else {
-        println!("parse task: {task:#}");
-    }
+    let task = client.start_file_parse(&request).await?;
+    let parsed = client.wait_for(task, &TaskPollPolicy::default()).await?;
+    println!("async parse:\n{}", parsed.to_layout().to_markdown());
 
-    let parsed = client
-        .parse_file_sync(&json!({"file_url": file_url, "tool_type": "lite"}))
-        .await?;
-    println!("sync parse: {parsed:#}");
+    let parsed = client.parse_file(&request).await?;
+    let layout = parsed.to_layout();
+    println!("sync parse: {} tables", layout.tables().count());
+    println!("{}", layout.to_text());
     Ok(())
 }
@@ -1,5 +1,5 @@
 use nextjson::json;
-use rustglm::ZhipuClient;
+use rustglm::{WebReaderFormat, WebReaderRequest, ZhipuClient};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -8,9 +8,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .unwrap_or_else(|| "https://www.rust-lang.org/".to_owned());
     let client = ZhipuClient::new(std::env::var("ZHIPU_API_KEY")?)?;
     let page = client
-        .read_web_page(&json!({"url": url, "return_type": "text"}))
+        .read_web(&WebReaderRequest::new(url).return_format(WebReaderFormat::Text))
         .await?;
-    println!("reader: {page:#}");
+    println!("reader: {}", page.reader_result.content);
 
     let moderation = client
         .moderate(&json!({"model": "moderation", "input": "A harmless test message"}))
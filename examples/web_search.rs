@@ -1,5 +1,4 @@
-use nextjson::json;
-use rustglm::ZhipuClient;
+use rustglm::{WebSearchEngine, WebSearchRecency, WebSearchRequest, ZhipuClient};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -8,12 +7,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .nth(1)
         .unwrap_or_else(|| "Rust async streams".to_owned());
     let client = ZhipuClient::new(api_key)?;
-    let response = client
-        .web_search(&json!({
-            "search_query": query,
-            "search_engine": "search_std",
-        }))
-        .await?;
-    println!("{response:#}");
+    let request = WebSearchRequest::new(query, WebSearchEngine::SearchStd)
+        .count(5)?
+        .recency(WebSearchRecency::OneMonth)?;
+    let response = client.search_web(&request).await?;
+    println!("{}", response.to_context());
     Ok(())
 }
@@ -1,4 +1,6 @@
 use std::collections::BTreeMap;
+#[cfg(all(feature = "mcp", any(feature = "agents", feature = "rag")))]
+use std::collections::BTreeSet;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -6,10 +8,10 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_stream::try_stream;
 use async_trait::async_trait;
-use futures_util::Stream;
+use futures_util::{Stream, StreamExt};
 use nextjson::{Map, Value};
 use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize};
-use tokio::time::timeout;
+use tokio::time::{Instant, timeout, timeout_at};
 
 use crate::wire_enum;
 
@@ -19,14 +21,22 @@ use crate::security::{
     DEFAULT_MAX_AGENT_STEPS, DEFAULT_MAX_MEMORY_TEXT_BYTES, DEFAULT_MAX_TOOL_EXECUTIONS,
     DEFAULT_MAX_TOOL_OUTPUT_BYTES,
 };
+use crate::telemetry::{agent_span, step_span, tool_span, traced};
+use crate::tool_stream::assemble_tool_stream;
+use crate::transport::StreamResponse;
 use crate::{
-    AgentError, ChatCompletionRequest, ChatCompletionResponse, ChatMessage, ChatProvider,
-    ConversationMemory, ExtraFields, FunctionDefinition, MemoryDocument, MessageContent, Result,
-    SdkError, Tool, ToolError, Usage,
+    AgentError, ChatChoice, ChatCompletionRequest, ChatCompletionResponse, ChatMessage,
+    ChatProvider, ChatResponseMessage, ConversationMemory, ExtraFields, FinishReason, FunctionCall,
+    FunctionDefinition, HistoryBudget, MemoryDocument, MessageContent, MessageRole, MetadataFilter,
+    ResponseContent, Result, SdkError, Tool, ToolCall, ToolError, ToolStreamEvent, Usage,
+    UsageLedger, UsageRecord,
 };
+#[cfg(all(feature = "mcp", any(feature = "agents", feature = "rag")))]
+use crate::{McpAgentTool, McpClient, McpToolDefinition};
 
 pub type OfficialAgentStream = Pin<Box<dyn Stream<Item = Result<OfficialAgentResponse>> + Send>>;
 pub type RetrievalAgentStream = Pin<Box<dyn Stream<Item = Result<RetrievalAgentEvent>> + Send>>;
+pub type AgentRunStream<'a> = Pin<Box<dyn Stream<Item = Result<AgentStreamEvent>> + Send + 'a>>;
 
 /// Monotonic counter that keeps agent memory ids unique even within the same nanosecond.
 static AGENT_MEMORY_SEQUENCE: AtomicU64 = AtomicU64::new(1);
@@ -847,6 +857,46 @@ pub struct AgentRunResult {
     pub tool_executions: Vec<AgentToolExecution>,
 }
 
+/// Step-level progress emitted by [`AgentRuntime::run_stream`].
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum AgentStreamEvent {
+    StepStarted {
+        step: u32,
+    },
+    TextDelta {
+        step: u32,
+        delta: String,
+    },
+    ReasoningDelta {
+        step: u32,
+        delta: String,
+    },
+    ToolCallStarted {
+        step: u32,
+        call_id: String,
+        name: String,
+        arguments: Value,
+    },
+    ToolCallCompleted {
+        step: u32,
+        execution: AgentToolExecution,
+    },
+    StepCompleted {
+        step: u32,
+        finish_reason: Option<FinishReason>,
+        usage: Option<Usage>,
+    },
+    Completed(AgentRunResult),
+}
+
+struct PreparedToolCall {
+    call_id: String,
+    name: String,
+    arguments: Value,
+    tool: Arc<dyn AgentTool>,
+}
+
 pub struct AgentRuntime {
     provider: Arc<dyn ChatProvider>,
     manifest: AgentManifest,
@@ -854,10 +904,14 @@ pub struct AgentRuntime {
     history: Vec<ChatMessage>,
     memory: Option<Arc<dyn ConversationMemory>>,
     recall_limit: usize,
+    memory_scope: BTreeMap<String, String>,
+    history_budget: Option<HistoryBudget>,
     run_timeout: Option<Duration>,
     tool_timeout: Option<Duration>,
     max_tool_executions: usize,
     max_tool_output_bytes: usize,
+    tool_concurrency: usize,
+    ledger: Option<UsageLedger>,
 }
 
 impl AgentRuntime {
@@ -870,10 +924,14 @@ impl AgentRuntime {
             history: Vec::new(),
             memory: None,
             recall_limit: 4,
+            memory_scope: BTreeMap::new(),
+            history_budget: None,
             run_timeout: None,
             tool_timeout: None,
             max_tool_executions: DEFAULT_MAX_TOOL_EXECUTIONS,
             max_tool_output_bytes: DEFAULT_MAX_TOOL_OUTPUT_BYTES,
+            tool_concurrency: 1,
+            ledger: None,
         })
     }
 
@@ -894,9 +952,14 @@ impl AgentRuntime {
         self.history.clear();
     }
 
+    /// Clears semantic memory, or only this agent's scoped memories when a scope is set.
     pub async fn clear_memory(&self) -> Result<()> {
         if let Some(memory) = &self.memory {
-            memory.clear().await?;
+            if self.memory_scope.is_empty() {
+                memory.clear().await?;
+            } else {
+                memory.forget_where(&self.memory_filter()).await?;
+            }
         }
         Ok(())
     }
@@ -916,6 +979,29 @@ impl AgentRuntime {
         Ok(self)
     }
 
+    /// Tags remembered turns with `key = value` and recalls only memories carrying every scope
+    /// entry, so agents for different users or sessions can share one memory store.
+    pub fn memory_scope(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.memory_scope.insert(key.into(), value.into());
+        self
+    }
+
+    /// Also keeps [`AgentHistoryPolicy::Recent`] history under a token budget, on top of the
+    /// message-count limit.
+    pub fn history_budget(mut self, value: HistoryBudget) -> Result<Self> {
+        value.validate()?;
+        self.history_budget = Some(value);
+        Ok(self)
+    }
+
+    fn memory_filter(&self) -> MetadataFilter {
+        self.memory_scope
+            .iter()
+            .fold(MetadataFilter::new(), |filter, (key, value)| {
+                filter.equals(key, value)
+            })
+    }
+
     pub fn run_timeout(mut self, value: Duration) -> Self {
         self.run_timeout = Some(value);
         self
@@ -946,6 +1032,49 @@ impl AgentRuntime {
         Ok(self)
     }
 
+    /// Lets tool calls from one assistant message run concurrently, at most `value` at a time.
+    /// Every call in the message is checked against the registry and execution budget before any
+    /// starts, and results are appended in call order. The default of 1 runs calls one by one,
+    /// stopping at the first that fails.
+    pub fn tool_concurrency(mut self, value: usize) -> Result<Self> {
+        if value == 0 {
+            return Err(SdkError::Configuration(
+                "agent tool concurrency must be greater than zero".into(),
+            ));
+        }
+        self.tool_concurrency = value;
+        Ok(self)
+    }
+
+    /// Records every model step into `value` and fails the run with [`SdkError::Budget`] once
+    /// one of its budgets is spent. Skip it when the provider's `HttpConfig` already feeds the
+    /// same ledger, or steps are counted twice.
+    pub fn usage_ledger(mut self, value: UsageLedger) -> Self {
+        self.ledger = Some(value);
+        self
+    }
+
+    fn check_budget(&self) -> Result<()> {
+        match &self.ledger {
+            Some(ledger) => ledger.check_budget(None),
+            None => Ok(()),
+        }
+    }
+
+    fn record_step(&self, model: &str, usage: Option<&Usage>) {
+        if let Some(ledger) = &self.ledger {
+            let model = if model.is_empty() {
+                &self.manifest.model
+            } else {
+                model
+            };
+            ledger.record(usage.map_or_else(
+                || UsageRecord::new(model),
+                |usage| UsageRecord::from_usage(model, usage),
+            ));
+        }
+    }
+
     pub fn register_tool<T>(&mut self, tool: T) -> Result<()>
     where
         T: AgentTool + 'static,
@@ -970,42 +1099,63 @@ impl AgentRuntime {
         Ok(())
     }
 
-    pub async fn run(&mut self, input: impl Into<String>) -> Result<AgentRunResult> {
-        let input = input.into();
-        if input.trim().is_empty() {
-            return Err(SdkError::Validation("agent input cannot be empty".into()));
+    /// Registers every tool of an MCP server accepted by `filter`. Names are checked up front,
+    /// so a conflict leaves the runtime unchanged. Returns the number of registered tools.
+    #[cfg(all(feature = "mcp", any(feature = "agents", feature = "rag")))]
+    pub async fn register_mcp_tools<F>(
+        &mut self,
+        client: Arc<McpClient>,
+        filter: F,
+    ) -> Result<usize>
+    where
+        F: Fn(&McpToolDefinition) -> bool,
+    {
+        let tools = client
+            .list_tools()
+            .await?
+            .iter()
+            .filter(|tool| filter(tool))
+            .map(|tool| McpAgentTool::new(client.clone(), tool))
+            .collect::<Vec<_>>();
+        let mut names = BTreeSet::new();
+        for tool in &tools {
+            let name = tool.definition().name;
+            if name.trim().is_empty() {
+                return Err(SdkError::Configuration(
+                    "agent tool name cannot be empty".into(),
+                ));
+            }
+            if self.tools.contains_key(name.trim()) || !names.insert(name.trim().to_owned()) {
+                return Err(SdkError::Configuration(
+                    format!("agent tool {name} is already registered").into(),
+                ));
+            }
+        }
+        let count = tools.len();
+        for tool in tools {
+            self.register_tool(tool)?;
         }
+        Ok(count)
+    }
+
+    pub async fn run(&mut self, input: impl Into<String>) -> Result<AgentRunResult> {
+        let input = validate_agent_input(input.into())?;
         let run_timeout = self.run_timeout;
+        let span = agent_span(&self.manifest.persona.name, &self.manifest.model);
         let runtime = self;
         let run = async move {
-            let mut messages = vec![ChatMessage::system(
-                runtime.manifest.persona.system_prompt()?,
-            )];
-            if let Some(memory) = &runtime.memory {
-                let recalled = memory.recall(&input, runtime.recall_limit).await?;
-                if let Some(message) =
-                    memory_context_message(recalled, DEFAULT_MAX_MEMORY_TEXT_BYTES)?
-                {
-                    messages.push(message);
-                }
-            }
-            messages.extend(runtime.history.iter().cloned());
-            messages.push(ChatMessage::user(&input));
-            let definitions = runtime
-                .tools
-                .values()
-                .map(|tool| Tool::function(tool.definition()))
-                .collect::<Vec<_>>();
+            let mut messages = runtime.initial_messages(&input).await?;
+            let definitions = runtime.tool_definitions();
             let mut executions = Vec::new();
             for step in 1..=runtime.manifest.max_steps {
-                let mut request = ChatCompletionRequest::new(&runtime.manifest.model)
-                    .messages(messages.iter().cloned())
-                    .temperature(runtime.manifest.temperature);
-                request.max_tokens = runtime.manifest.max_tokens;
-                if !definitions.is_empty() {
-                    request.tools = Some(definitions.clone());
-                }
-                let response = runtime.provider.complete(request).await?;
+                runtime.check_budget()?;
+                let request = runtime.step_request(&messages, &definitions);
+                let response = traced(
+                    step_span(&runtime.manifest.model, step),
+                    runtime.provider.complete(request),
+                )
+                .await?;
+                runtime.record_step(&response.model, response.usage.as_ref());
                 let message = response
                     .choices
                     .first()
@@ -1025,12 +1175,12 @@ impl AgentRuntime {
                 }
                 let calls = message.tool_calls.clone();
                 messages.push(ChatMessage {
-                    role: crate::MessageRole::Assistant,
+                    role: MessageRole::Assistant,
                     content: message.content.as_ref().and_then(|content| match content {
-                        crate::ResponseContent::Text(value) => {
+                        ResponseContent::Text(value) => {
                             Some(MessageContent::Text(value.to_owned()))
                         }
-                        crate::ResponseContent::Parts(_) => None,
+                        ResponseContent::Parts(_) => None,
                     }),
                     name: None,
                     tool_call_id: None,
@@ -1038,67 +1188,298 @@ impl AgentRuntime {
                     reasoning_content: message.reasoning_content.clone(),
                     extra: message.extra.clone(),
                 });
-                for call in calls {
-                    if executions.len() >= runtime.max_tool_executions {
-                        return Err(SdkError::Agent(AgentError::BudgetExceeded(
-                            "tool execution budget exhausted",
-                        )));
+                let prepared = runtime.prepare_tool_calls(calls, executions.len())?;
+                let mut results = runtime.execute_tool_calls(prepared);
+                while let Some(result) = results.next().await {
+                    append_tool_result(&mut messages, &mut executions, result?);
+                }
+            }
+            Err(SdkError::Agent(AgentError::StepLimit {
+                steps: runtime.manifest.max_steps as usize,
+            }))
+        };
+        traced(span, async move {
+            match run_timeout {
+                Some(duration) => timeout(duration, run)
+                    .await
+                    .map_err(|_| SdkError::Timeout("agent run timed out".into()))?,
+                None => run.await,
+            }
+        })
+        .await
+    }
+
+    /// Runs the same loop as [`AgentRuntime::run`] over `ChatProvider::stream`, yielding text,
+    /// reasoning and tool events as they happen. History and memory are committed only after the
+    /// final step, so dropping the stream early leaves the runtime untouched.
+    pub fn run_stream(&mut self, input: impl Into<String>) -> AgentRunStream<'_> {
+        let input = input.into();
+        let runtime = self;
+        let stream = try_stream! {
+            let input = validate_agent_input(input)?;
+            let deadline = runtime.run_timeout.map(|duration| Instant::now() + duration);
+            let mut messages = within_deadline(deadline, runtime.initial_messages(&input)).await??;
+            let definitions = runtime.tool_definitions();
+            let mut executions = Vec::new();
+            for step in 1..=runtime.manifest.max_steps {
+                runtime.check_budget()?;
+                yield AgentStreamEvent::StepStarted { step };
+                let request = runtime.step_request(&messages, &definitions);
+                let source = within_deadline(
+                    deadline,
+                    traced(step_span(&runtime.manifest.model, step), runtime.provider.stream(request)),
+                )
+                .await??;
+                let mut source = assemble_tool_stream(source);
+                let mut text = String::new();
+                let mut reasoning = String::new();
+                let mut calls = Vec::new();
+                let mut finish_reason = None;
+                let mut usage = None;
+                while let Some(event) = within_deadline(deadline, source.next()).await? {
+                    match event? {
+                        ToolStreamEvent::ContentDelta { choice_index: 0, content } => {
+                            if let Some(delta) = content.joined_text() {
+                                text.push_str(&delta);
+                                yield AgentStreamEvent::TextDelta { step, delta };
+                            }
+                        }
+                        ToolStreamEvent::ReasoningDelta { choice_index: 0, delta } => {
+                            reasoning.push_str(&delta);
+                            yield AgentStreamEvent::ReasoningDelta { step, delta };
+                        }
+                        ToolStreamEvent::ToolCallCompleted(call) if call.choice_index == 0 => {
+                            calls.push(ToolCall {
+                                id: call.id,
+                                kind: "function".into(),
+                                function: Some(FunctionCall {
+                                    name: call.name,
+                                    arguments: call.arguments,
+                                }),
+                                extra: Map::new(),
+                            });
+                        }
+                        ToolStreamEvent::ChoiceCompleted { choice_index: 0, reason } => {
+                            finish_reason = Some(reason);
+                        }
+                        ToolStreamEvent::Usage(value) => usage = Some(value),
+                        _ => {}
                     }
-                    let call_id = call.id;
-                    let function = call.function.ok_or_else(|| {
-                        SdkError::Tool(ToolError::InvalidArguments {
-                            tool: call_id.clone(),
-                            reason: "tool call has no function payload".into(),
-                        })
-                    })?;
-                    let arguments =
-                        nextjson::from_str::<Value>(&function.arguments).map_err(|error| {
-                            SdkError::Tool(ToolError::InvalidArguments {
-                                tool: function.name.clone(),
-                                reason: format!("arguments are not valid JSON: {error}"),
-                            })
-                        })?;
-                    let tool = runtime.tools.get(&function.name).ok_or_else(|| {
-                        SdkError::Tool(ToolError::NotRegistered(function.name.clone()))
-                    })?;
-                    let output = match runtime.tool_timeout {
-                        Some(duration) => timeout(duration, tool.execute(arguments.clone()))
-                            .await
-                            .map_err(|_| {
-                                SdkError::Timeout(
-                                    format!("tool {} timed out", function.name).into(),
-                                )
-                            })??,
-                        None => tool.execute(arguments.clone()).await?,
-                    };
-                    let output_text = nextjson::to_string(&output)
-                        .map_err(|error| SdkError::Tool(ToolError::Message(error.to_string())))?;
-                    if output_text.len() > runtime.max_tool_output_bytes {
-                        return Err(SdkError::Agent(AgentError::BudgetExceeded(
-                            "tool output budget exhausted",
-                        )));
+                }
+                runtime.record_step("", usage.as_ref());
+                yield AgentStreamEvent::StepCompleted {
+                    step,
+                    finish_reason: finish_reason.clone(),
+                    usage: usage.clone(),
+                };
+                let reasoning = (!reasoning.is_empty()).then_some(reasoning);
+                if calls.is_empty() {
+                    if text.is_empty() {
+                        Err(SdkError::Agent(AgentError::NoOutput))?;
                     }
-                    messages.push(ChatMessage::tool_result(&call_id, output_text));
-                    executions.push(AgentToolExecution {
-                        call_id,
-                        name: function.name,
-                        arguments,
-                        output,
+                    runtime.commit_turn(&input, Some(text.clone())).await?;
+                    let response = ChatCompletionResponse {
+                        model: runtime.manifest.model.clone(),
+                        choices: vec![ChatChoice {
+                            index: 0,
+                            message: ChatResponseMessage {
+                                role: Some(MessageRole::Assistant),
+                                content: Some(ResponseContent::Text(text)),
+                                reasoning_content: reasoning,
+                                ..Default::default()
+                            },
+                            finish_reason: finish_reason.map(|reason| reason.as_str().to_owned()),
+                        }],
+                        usage,
+                        ..Default::default()
+                    };
+                    yield AgentStreamEvent::Completed(AgentRunResult {
+                        response,
+                        model_steps: step,
+                        tool_executions: executions,
                     });
+                    return;
+                }
+                messages.push(ChatMessage {
+                    role: MessageRole::Assistant,
+                    content: (!text.is_empty()).then_some(MessageContent::Text(text)),
+                    name: None,
+                    tool_call_id: None,
+                    tool_calls: Some(calls.clone()),
+                    reasoning_content: reasoning,
+                    extra: Map::new(),
+                });
+                let prepared = runtime.prepare_tool_calls(calls, executions.len())?;
+                let mut starts = prepared
+                    .iter()
+                    .map(|call| AgentStreamEvent::ToolCallStarted {
+                        step,
+                        call_id: call.call_id.clone(),
+                        name: call.name.clone(),
+                        arguments: call.arguments.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter();
+                let mut running = 0;
+                let mut results = runtime.execute_tool_calls(prepared);
+                loop {
+                    // `execute_tool_calls` keeps `tool_concurrency` calls in flight, so announce
+                    // each call as it joins them.
+                    while running < runtime.tool_concurrency
+                        && let Some(started) = starts.next()
+                    {
+                        running += 1;
+                        yield started;
+                    }
+                    let Some(result) = within_deadline(deadline, results.next()).await? else {
+                        break;
+                    };
+                    running -= 1;
+                    let execution = append_tool_result(&mut messages, &mut executions, result?);
+                    yield AgentStreamEvent::ToolCallCompleted {
+                        step,
+                        execution: execution.clone(),
+                    };
                 }
             }
             Err(SdkError::Agent(AgentError::StepLimit {
                 steps: runtime.manifest.max_steps as usize,
-            }))
+            }))?;
         };
-        match run_timeout {
-            Some(duration) => timeout(duration, run)
-                .await
-                .map_err(|_| SdkError::Timeout("agent run timed out".into()))?,
-            None => run.await,
+        Box::pin(stream)
+    }
+
+    async fn initial_messages(&self, input: &str) -> Result<Vec<ChatMessage>> {
+        let mut messages = vec![ChatMessage::system(self.manifest.persona.system_prompt()?)];
+        if let Some(memory) = &self.memory {
+            let recalled = memory
+                .recall_filtered(input, self.recall_limit, &self.memory_filter())
+                .await?;
+            if let Some(message) = memory_context_message(recalled, DEFAULT_MAX_MEMORY_TEXT_BYTES)?
+            {
+                messages.push(message);
+            }
         }
+        messages.extend(self.history.iter().cloned());
+        messages.push(ChatMessage::user(input));
+        Ok(messages)
+    }
+
+    fn tool_definitions(&self) -> Vec<Tool> {
+        self.tools
+            .values()
+            .map(|tool| Tool::function(tool.definition()))
+            .collect()
+    }
+
+    fn step_request(
+        &self,
+        messages: &[ChatMessage],
+        definitions: &[Tool],
+    ) -> ChatCompletionRequest {
+        let mut request = ChatCompletionRequest::new(&self.manifest.model)
+            .messages(messages.iter().cloned())
+            .temperature(self.manifest.temperature);
+        request.max_tokens = self.manifest.max_tokens;
+        if !definitions.is_empty() {
+            request.tools = Some(definitions.to_vec());
+        }
+        request
+    }
+
+    /// Prepares every tool call of one assistant message before any of them runs, so a batch
+    /// either starts whole or not at all.
+    fn prepare_tool_calls(
+        &self,
+        calls: Vec<ToolCall>,
+        executed: usize,
+    ) -> Result<Vec<PreparedToolCall>> {
+        calls
+            .into_iter()
+            .enumerate()
+            .map(|(index, call)| self.prepare_tool_call(call, executed + index))
+            .collect()
+    }
+
+    /// Validates one model tool call against the execution budget and the registered tools
+    /// before anything runs.
+    fn prepare_tool_call(&self, call: ToolCall, executed: usize) -> Result<PreparedToolCall> {
+        if executed >= self.max_tool_executions {
+            return Err(SdkError::Agent(AgentError::BudgetExceeded(
+                "tool execution budget exhausted",
+            )));
+        }
+        let call_id = call.id;
+        let function = call.function.ok_or_else(|| {
+            SdkError::Tool(ToolError::InvalidArguments {
+                tool: call_id.clone(),
+                reason: "tool call has no function payload".into(),
+            })
+        })?;
+        let arguments = nextjson::from_str::<Value>(&function.arguments).map_err(|error| {
+            SdkError::Tool(ToolError::InvalidArguments {
+                tool: function.name.clone(),
+                reason: format!("arguments are not valid JSON: {error}"),
+            })
+        })?;
+        let tool = self
+            .tools
+            .get(&function.name)
+            .cloned()
+            .ok_or_else(|| SdkError::Tool(ToolError::NotRegistered(function.name.clone())))?;
+        Ok(PreparedToolCall {
+            call_id,
+            name: function.name,
+            arguments,
+            tool,
+        })
     }
 
+    /// Runs prepared calls with at most `tool_concurrency` in flight and yields their results in
+    /// the original call order, so transcripts stay deterministic.
+    fn execute_tool_calls(
+        &self,
+        calls: Vec<PreparedToolCall>,
+    ) -> impl Stream<Item = Result<(AgentToolExecution, String)>> + Send + '_ {
+        futures_util::stream::iter(calls)
+            .map(|call| self.execute_tool_call(call))
+            .buffered(self.tool_concurrency)
+    }
+
+    /// Executes a prepared call and returns the execution record plus the serialized output
+    /// that goes back to the model.
+    async fn execute_tool_call(
+        &self,
+        call: PreparedToolCall,
+    ) -> Result<(AgentToolExecution, String)> {
+        let span = tool_span(&call.name, &call.call_id);
+        traced(span, self.run_tool_call(call)).await
+    }
+
+    async fn run_tool_call(&self, call: PreparedToolCall) -> Result<(AgentToolExecution, String)> {
+        let output = match self.tool_timeout {
+            Some(duration) => timeout(duration, call.tool.execute(call.arguments.clone()))
+                .await
+                .map_err(|_| SdkError::Timeout(format!("tool {} timed out", call.name).into()))??,
+            None => call.tool.execute(call.arguments.clone()).await?,
+        };
+        let output_text = nextjson::to_string(&output)
+            .map_err(|error| SdkError::Tool(ToolError::Message(error.to_string())))?;
+        if output_text.len() > self.max_tool_output_bytes {
+            return Err(SdkError::Agent(AgentError::BudgetExceeded(
+                "tool output budget exhausted",
+            )));
+        }
+        Ok((
+            AgentToolExecution {
+                call_id: call.call_id,
+                name: call.name,
+                arguments: call.arguments,
+                output,
+            },
+            output_text,
+        ))
+    }
     async fn commit_turn(&mut self, input: &str, output: Option<String>) -> Result<()> {
         let Some(output) = output else {
             return Ok(());
@@ -1110,35 +1491,42 @@ impl AgentRuntime {
             if overflow > 0 {
                 self.history.drain(..overflow);
             }
+            if let Some(budget) = &self.history_budget {
+                // The turn already succeeded; if the budget cannot be measured, the message
+                // limit above still bounds the history.
+                let _ = budget
+                    .compact(
+                        &mut self.history,
+                        self.provider.as_ref(),
+                        &self.manifest.model,
+                    )
+                    .await;
+            }
         }
         if let Some(memory) = &self.memory {
-            memory
-                .remember(
-                    MemoryDocument::new(
-                        agent_memory_id()?,
-                        format!("User: {input}\nAssistant: {output}"),
-                    )
-                    .metadata("source", "agent"),
-                )
-                .await?;
+            let mut document = MemoryDocument::new(
+                agent_memory_id()?,
+                format!("User: {input}\nAssistant: {output}"),
+            )
+            .metadata("source", "agent");
+            document.metadata.extend(self.memory_scope.clone());
+            memory.remember(document).await?;
         }
         Ok(())
     }
 }
 
 #[cfg(feature = "agents")]
-pub(crate) fn official_agent_stream(response: reqwest::Response) -> OfficialAgentStream {
+pub(crate) fn official_agent_stream(response: StreamResponse) -> OfficialAgentStream {
     decode_sse_stream(response)
 }
 
 #[cfg(feature = "rag")]
-pub(crate) fn retrieval_agent_stream(response: reqwest::Response) -> RetrievalAgentStream {
+pub(crate) fn retrieval_agent_stream(response: StreamResponse) -> RetrievalAgentStream {
     decode_sse_stream(response)
 }
 
-fn decode_sse_stream<T>(
-    response: reqwest::Response,
-) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>>
+fn decode_sse_stream<T>(response: StreamResponse) -> Pin<Box<dyn Stream<Item = Result<T>> + Send>>
 where
     T: for<'de> Deserialize<'de> + Send + 'static,
 {
@@ -1162,6 +1550,33 @@ fn null_value() -> Value {
     Value::Null
 }
 
+fn validate_agent_input(input: String) -> Result<String> {
+    if input.trim().is_empty() {
+        return Err(SdkError::Validation("agent input cannot be empty".into()));
+    }
+    Ok(input)
+}
+
+async fn within_deadline<F: Future>(deadline: Option<Instant>, future: F) -> Result<F::Output> {
+    match deadline {
+        Some(deadline) => timeout_at(deadline, future)
+            .await
+            .map_err(|_| SdkError::Timeout("agent run timed out".into())),
+        None => Ok(future.await),
+    }
+}
+
+/// Sends a finished call's output back to the model and records the execution, in call order.
+fn append_tool_result<'a>(
+    messages: &mut Vec<ChatMessage>,
+    executions: &'a mut Vec<AgentToolExecution>,
+    (execution, output_text): (AgentToolExecution, String),
+) -> &'a AgentToolExecution {
+    messages.push(ChatMessage::tool_result(&execution.call_id, output_text));
+    executions.push(execution);
+    &executions[executions.len() - 1]
+}
+
 fn push_prompt(sections: &mut Vec<String>, label: &str, value: &str) {
     if !value.trim().is_empty() {
         sections.push(format!("{label}: {}", value.trim()));
@@ -1229,15 +1644,15 @@ fn is_false(value: &bool) -> bool {
 mod tests {
     use std::collections::VecDeque;
     use std::sync::Mutex;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
     use futures_util::stream;
     use nextjson::json;
 
     use super::*;
     use crate::{
-        ChatChoice, ChatResponseMessage, FunctionCall, MessageRole, ProviderCapabilities,
-        ResponseContent, ResponseContentPart, ToolCall,
+        ChatChunkChoice, ChatCompletionChunk, ChatDelta, FunctionCallDelta, HistoryOverflow,
+        ProviderCapabilities, ResponseContentPart, TokenEstimator, ToolCallDelta,
     };
 
     struct StaticResolver(String);
@@ -1299,8 +1714,72 @@ mod tests {
         }
     }
 
+    struct StreamingProvider {
+        steps: Mutex<VecDeque<Vec<ChatCompletionChunk>>>,
+        requests: Mutex<Vec<ChatCompletionRequest>>,
+    }
+
+    #[async_trait]
+    impl ChatProvider for StreamingProvider {
+        fn name(&self) -> &str {
+            "streaming"
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities::openai_compatible()
+        }
+
+        async fn complete(&self, _: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+            Err(SdkError::Agent("streaming provider only streams".into()))
+        }
+
+        async fn stream(&self, request: ChatCompletionRequest) -> Result<crate::ChatStream> {
+            self.requests.lock().unwrap().push(request);
+            let chunks = self
+                .steps
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| SdkError::Agent("missing mock stream".into()))?;
+            Ok(Box::pin(stream::iter(chunks.into_iter().map(Ok))))
+        }
+    }
+
+    fn chunk(delta: ChatDelta, finish_reason: Option<&str>) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            choices: vec![ChatChunkChoice {
+                index: 0,
+                delta,
+                finish_reason: finish_reason.map(str::to_owned),
+            }],
+            ..Default::default()
+        }
+    }
+
     struct EchoTool;
 
+    #[derive(Default)]
+    struct ConcurrentTool {
+        in_flight: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AgentTool for Arc<ConcurrentTool> {
+        fn definition(&self) -> FunctionDefinition {
+            FunctionDefinition::new("sleep", json!({"type":"object"}))
+        }
+
+        async fn execute(&self, arguments: Value) -> Result<Value> {
+            let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(current, Ordering::SeqCst);
+            let millis = arguments["millis"].as_u64().unwrap_or_default();
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(json!({"slept":millis}))
+        }
+    }
+
     struct EmptyNameTool;
 
     #[async_trait]
@@ -1619,6 +2098,83 @@ mod tests {
         assert_eq!(requests[1].messages.last().unwrap().role, MessageRole::Tool);
     }
 
+    struct MessageCount;
+
+    #[async_trait]
+    impl TokenEstimator for MessageCount {
+        async fn count(&self, messages: &[ChatMessage]) -> Result<usize> {
+            Ok(messages.len())
+        }
+    }
+
+    fn summarizing_budget() -> HistoryBudget {
+        HistoryBudget::new(3)
+            .overflow(HistoryOverflow::Summarize)
+            .estimator(Arc::new(MessageCount))
+    }
+
+    #[tokio::test]
+    async fn token_budget_history_summarizes_old_turns() {
+        let provider = Arc::new(MockProvider {
+            responses: Mutex::new(VecDeque::from([
+                response(Some("first answer"), Vec::new()),
+                response(Some("second answer"), Vec::new()),
+                response(Some("summary of the first turn"), Vec::new()),
+            ])),
+            requests: Mutex::new(Vec::new()),
+        });
+        assert!(
+            AgentRuntime::new(provider.clone(), manifest())
+                .unwrap()
+                .history_budget(HistoryBudget::new(0))
+                .is_err()
+        );
+        let mut runtime = AgentRuntime::new(provider.clone(), manifest())
+            .unwrap()
+            .history_budget(summarizing_budget())
+            .unwrap();
+        runtime.run("first question").await.unwrap();
+        assert_eq!(runtime.history().len(), 2);
+        runtime.run("second question").await.unwrap();
+        let history = runtime.history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].role, MessageRole::System);
+        assert_eq!(history[1], ChatMessage::user("second question"));
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(
+            nextjson::to_string(&requests[2].messages[1])
+                .unwrap()
+                .contains("first question")
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_history_summary_falls_back_to_dropping_turns() {
+        let provider = Arc::new(MockProvider {
+            responses: Mutex::new(VecDeque::from([
+                response(Some("first answer"), Vec::new()),
+                response(Some("second answer"), Vec::new()),
+            ])),
+            requests: Mutex::new(Vec::new()),
+        });
+        let mut runtime = AgentRuntime::new(provider.clone(), manifest())
+            .unwrap()
+            .history_budget(summarizing_budget())
+            .unwrap();
+        runtime.run("first question").await.unwrap();
+        let result = runtime.run("second question").await.unwrap();
+        assert_eq!(result.response.text(), Some("second answer"));
+        assert_eq!(
+            runtime.history(),
+            [
+                ChatMessage::user("second question"),
+                ChatMessage::assistant("second answer")
+            ]
+        );
+        assert_eq!(provider.requests.lock().unwrap().len(), 3);
+    }
+
     #[tokio::test]
     async fn runtime_enforces_step_limit_and_tool_validation() {
         let mut limited = manifest();
@@ -1714,6 +2270,203 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn runtime_records_steps_and_stops_at_the_ledger_budget() {
+        let step = ChatCompletionResponse {
+            model: "glm-test".into(),
+            usage: Some(Usage {
+                prompt_tokens: 8,
+                completion_tokens: 4,
+                total_tokens: 12,
+                ..Usage::default()
+            }),
+            ..response(None, vec![tool_call()])
+        };
+        let provider = Arc::new(MockProvider {
+            responses: Mutex::new(VecDeque::from([step, response(Some("done"), Vec::new())])),
+            requests: Mutex::new(Vec::new()),
+        });
+        let ledger =
+            UsageLedger::new().tag_budget("agent", crate::UsageBudget::new().max_tokens(10));
+        let mut runtime = AgentRuntime::new(provider.clone(), manifest())
+            .unwrap()
+            .usage_ledger(ledger.tagged("agent"));
+        runtime.register_tool(EchoTool).unwrap();
+        assert!(matches!(
+            runtime.run("hello").await,
+            Err(SdkError::Budget(crate::BudgetError::Tokens {
+                spent: 12,
+                ..
+            }))
+        ));
+        assert_eq!(provider.requests.lock().unwrap().len(), 1);
+        let report = ledger.report();
+        assert_eq!(report.by_model["glm-test"].completion_tokens, 4);
+        assert_eq!(report.by_tag["agent"].requests, 1);
+    }
+
+    #[tokio::test]
+    async fn runtime_runs_tool_calls_concurrently_in_call_order() {
+        let sleep_call = |id: &str, millis: u64| ToolCall {
+            id: id.into(),
+            kind: "function".into(),
+            function: Some(FunctionCall {
+                name: "sleep".into(),
+                arguments: format!("{{\"millis\":{millis}}}"),
+            }),
+            extra: Map::new(),
+        };
+        let provider = Arc::new(MockProvider {
+            responses: Mutex::new(VecDeque::from([
+                response(
+                    None,
+                    vec![
+                        sleep_call("call-1", 60),
+                        sleep_call("call-2", 10),
+                        sleep_call("call-3", 30),
+                    ],
+                ),
+                response(Some("done"), Vec::new()),
+            ])),
+            requests: Mutex::new(Vec::new()),
+        });
+        let tool = Arc::new(ConcurrentTool::default());
+        assert!(
+            AgentRuntime::new(provider.clone(), manifest())
+                .unwrap()
+                .tool_concurrency(0)
+                .is_err()
+        );
+        let mut runtime = AgentRuntime::new(provider.clone(), manifest())
+            .unwrap()
+            .tool_concurrency(2)
+            .unwrap();
+        runtime.register_tool(tool.clone()).unwrap();
+        let result = runtime.run("hello").await.unwrap();
+        assert_eq!(tool.peak.load(Ordering::SeqCst), 2);
+        let ids = result
+            .tool_executions
+            .iter()
+            .map(|execution| execution.call_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["call-1", "call-2", "call-3"]);
+        {
+            let requests = provider.requests.lock().unwrap();
+            let tool_ids = requests[1]
+                .messages
+                .iter()
+                .filter_map(|message| message.tool_call_id.as_deref())
+                .collect::<Vec<_>>();
+            assert_eq!(tool_ids, ["call-1", "call-2", "call-3"]);
+        }
+
+        let provider = Arc::new(MockProvider {
+            responses: Mutex::new(VecDeque::from([response(
+                None,
+                vec![sleep_call("call-1", 10), sleep_call("call-2", 500)],
+            )])),
+            requests: Mutex::new(Vec::new()),
+        });
+        let mut runtime = AgentRuntime::new(provider, manifest())
+            .unwrap()
+            .tool_concurrency(2)
+            .unwrap()
+            .tool_timeout(Duration::from_millis(100));
+        runtime
+            .register_tool(Arc::new(ConcurrentTool::default()))
+            .unwrap();
+        assert!(matches!(
+            runtime.run("hello").await,
+            Err(SdkError::Timeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn over_budget_tool_calls_never_start_and_events_follow_execution() {
+        let sleep_call = |id: &str| ToolCall {
+            id: id.into(),
+            kind: "function".into(),
+            function: Some(FunctionCall {
+                name: "sleep".into(),
+                arguments: "{\"millis\":0}".into(),
+            }),
+            extra: Map::new(),
+        };
+        for concurrency in [1, 2] {
+            let provider = Arc::new(MockProvider {
+                responses: Mutex::new(VecDeque::from([response(
+                    None,
+                    vec![sleep_call("call-1"), sleep_call("call-2")],
+                )])),
+                requests: Mutex::new(Vec::new()),
+            });
+            let tool = Arc::new(ConcurrentTool::default());
+            let mut runtime = AgentRuntime::new(provider, manifest())
+                .unwrap()
+                .max_tool_executions(1)
+                .unwrap()
+                .tool_concurrency(concurrency)
+                .unwrap();
+            runtime.register_tool(tool.clone()).unwrap();
+            assert!(matches!(
+                runtime.run("hello").await,
+                Err(SdkError::Agent(AgentError::BudgetExceeded(_)))
+            ));
+            assert_eq!(tool.peak.load(Ordering::SeqCst), 0, "{concurrency}");
+        }
+
+        let call_delta = |index: u32, id: &str| ToolCallDelta {
+            index: Some(index),
+            id: Some(id.into()),
+            kind: Some("function".into()),
+            function: Some(FunctionCallDelta {
+                name: Some("echo".into()),
+                arguments: Some("{\"value\":\"hi\"}".into()),
+            }),
+            ..Default::default()
+        };
+        let provider = Arc::new(StreamingProvider {
+            steps: Mutex::new(VecDeque::from([
+                vec![chunk(
+                    ChatDelta {
+                        tool_calls: vec![call_delta(0, "call-1"), call_delta(1, "call-2")],
+                        ..Default::default()
+                    },
+                    Some("tool_calls"),
+                )],
+                vec![chunk(
+                    ChatDelta {
+                        content: Some(ResponseContent::Text("done".into())),
+                        ..Default::default()
+                    },
+                    Some("stop"),
+                )],
+            ])),
+            requests: Mutex::new(Vec::new()),
+        });
+        let mut runtime = AgentRuntime::new(provider, manifest()).unwrap();
+        runtime.register_tool(EchoTool).unwrap();
+        let order = runtime
+            .run_stream("hello")
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter_map(|event| match event.unwrap() {
+                AgentStreamEvent::ToolCallStarted { call_id, .. } => {
+                    Some(format!("start {call_id}"))
+                }
+                AgentStreamEvent::ToolCallCompleted { execution, .. } => {
+                    Some(format!("end {}", execution.call_id))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            ["start call-1", "end call-1", "start call-2", "end call-2"]
+        );
+    }
+
     #[tokio::test]
     async fn runtime_integrates_semantic_memory_and_clear_operations() {
         let provider = Arc::new(MockProvider {
@@ -1762,6 +2515,158 @@ mod tests {
         assert_eq!(runtime.run("hello").await.unwrap().model_steps, 2);
     }
 
+    #[tokio::test]
+    async fn run_stream_emits_step_events_and_commits_history() {
+        let provider = Arc::new(StreamingProvider {
+            steps: Mutex::new(VecDeque::from([
+                vec![
+                    chunk(
+                        ChatDelta {
+                            reasoning_content: Some("need a tool".into()),
+                            tool_calls: vec![ToolCallDelta {
+                                index: Some(0),
+                                id: Some("call-1".into()),
+                                kind: Some("function".into()),
+                                function: Some(FunctionCallDelta {
+                                    name: Some("echo".into()),
+                                    arguments: Some("{\"value\":".into()),
+                                }),
+                                ..Default::default()
+                            }],
+                            ..Default::default()
+                        },
+                        None,
+                    ),
+                    chunk(
+                        ChatDelta {
+                            tool_calls: vec![ToolCallDelta {
+                                index: Some(0),
+                                function: Some(FunctionCallDelta {
+                                    name: None,
+                                    arguments: Some("\"hello\"}".into()),
+                                }),
+                                ..Default::default()
+                            }],
+                            ..Default::default()
+                        },
+                        Some("tool_calls"),
+                    ),
+                ],
+                vec![
+                    chunk(
+                        ChatDelta {
+                            content: Some(ResponseContent::Text("do".into())),
+                            ..Default::default()
+                        },
+                        None,
+                    ),
+                    ChatCompletionChunk {
+                        usage: Some(Usage {
+                            total_tokens: 7,
+                            ..Default::default()
+                        }),
+                        ..chunk(
+                            ChatDelta {
+                                content: Some(ResponseContent::Text("ne".into())),
+                                ..Default::default()
+                            },
+                            Some("stop"),
+                        )
+                    },
+                ],
+            ])),
+            requests: Mutex::new(Vec::new()),
+        });
+        let mut runtime = AgentRuntime::new(provider.clone(), manifest()).unwrap();
+        runtime.register_tool(EchoTool).unwrap();
+        let events = runtime
+            .run_stream("hello")
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(events[0], AgentStreamEvent::StepStarted { step: 1 });
+        assert_eq!(
+            events[1],
+            AgentStreamEvent::ReasoningDelta {
+                step: 1,
+                delta: "need a tool".into()
+            }
+        );
+        assert!(events.iter().any(|event| matches!(
+            event,
+            AgentStreamEvent::ToolCallStarted { name, arguments, .. }
+                if name == "echo" && arguments == &json!({"value":"hello"})
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            AgentStreamEvent::ToolCallCompleted { step: 1, execution }
+                if execution.output == json!({"echoed":"hello"})
+        )));
+        let deltas = events
+            .iter()
+            .filter_map(|event| match event {
+                AgentStreamEvent::TextDelta { step: 2, delta } => Some(delta.as_str()),
+                _ => None,
+            })
+            .collect::<String>();
+        assert_eq!(deltas, "done");
+        let AgentStreamEvent::Completed(result) = events.last().unwrap() else {
+            panic!("stream did not finish with a completed event");
+        };
+        assert_eq!(result.response.text(), Some("done"));
+        assert_eq!(result.model_steps, 2);
+        assert_eq!(result.response.usage.as_ref().unwrap().total_tokens, 7);
+        assert_eq!(runtime.history().len(), 2);
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests[1].messages.last().unwrap().role, MessageRole::Tool);
+    }
+
+    #[tokio::test]
+    async fn run_stream_reports_validation_and_step_limits() {
+        let provider = Arc::new(StreamingProvider {
+            steps: Mutex::new(VecDeque::new()),
+            requests: Mutex::new(Vec::new()),
+        });
+        let mut runtime = AgentRuntime::new(provider, manifest()).unwrap();
+        let mut stream = runtime.run_stream(" ");
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(SdkError::Validation(_)))
+        ));
+        drop(stream);
+
+        let mut limited = manifest();
+        limited.max_steps = 1;
+        let provider = Arc::new(StreamingProvider {
+            steps: Mutex::new(VecDeque::from([vec![chunk(
+                ChatDelta {
+                    tool_calls: vec![ToolCallDelta {
+                        index: Some(0),
+                        id: Some("call-1".into()),
+                        function: Some(FunctionCallDelta {
+                            name: Some("echo".into()),
+                            arguments: Some("{\"value\":\"x\"}".into()),
+                        }),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                Some("tool_calls"),
+            )]])),
+            requests: Mutex::new(Vec::new()),
+        });
+        let mut runtime = AgentRuntime::new(provider, limited).unwrap();
+        runtime.register_tool(EchoTool).unwrap();
+        let events = runtime.run_stream("hello").collect::<Vec<_>>().await;
+        assert!(matches!(
+            events.last(),
+            Some(Err(SdkError::Agent(AgentError::StepLimit { steps: 1 })))
+        ));
+        assert!(runtime.history().is_empty());
+    }
+
     #[test]
     fn sse_decoder_handles_chunks_done_and_errors() {
         let mut decoder = crate::sse::SseDecoder::<RetrievalAgentEvent>::default();
@@ -190,9 +190,12 @@ impl AuthenticationProvider {
 
     pub(crate) fn header_value(&self) -> Result<HeaderValue> {
         let token = self.token()?;
-        HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| {
+        let mut value = HeaderValue::from_str(&format!("Bearer {token}")).map_err(|_| {
             SdkError::Configuration("authentication value is not a valid header".into())
-        })
+        })?;
+        // Middleware sees the finished headers; keep the key out of their `Debug` output.
+        value.set_sensitive(true);
+        Ok(value)
     }
 
     pub(crate) fn token(&self) -> Result<String> {
@@ -374,6 +377,7 @@ mod tests {
         let provider =
             AuthenticationProvider::zhipu(ZhipuAuthentication::auto("opaque-key")).unwrap();
         assert_eq!(provider.header_value().unwrap(), "Bearer opaque-key");
+        assert!(provider.header_value().unwrap().is_sensitive());
     }
 
     #[test]
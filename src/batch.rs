@@ -0,0 +1,903 @@
+//! Typed batch input files and result-file parsing.
+
+#[cfg(feature = "files")]
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashSet};
+#[cfg(feature = "files")]
+use std::fs;
+#[cfg(feature = "files")]
+use std::io::ErrorKind;
+#[cfg(feature = "files")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "files")]
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "files")]
+use nextjson::Map;
+#[cfg(feature = "files")]
+use nextjson::NsonSerialize as Serialize;
+use nextjson::{NsonDeserialize as Deserialize, Value, json};
+use thiserror::Error;
+#[cfg(feature = "files")]
+use tokio::time::sleep;
+
+use crate::client::{validate_embedding, validate_zhipu_chat};
+use crate::{
+    BatchCreateRequest, BatchError, ChatCompletionRequest, ChatCompletionResponse,
+    EmbeddingRequest, FileUploadRequest, Result, SdkError,
+};
+#[cfg(feature = "files")]
+use crate::{BatchObject, BatchStatus, TimeoutError, ZhipuClient};
+
+pub const BATCH_CHAT_ENDPOINT: &str = "/v4/chat/completions";
+pub const BATCH_EMBEDDING_ENDPOINT: &str = "/v4/embeddings";
+/// Zhipu accepts up to 50,000 requests per input file.
+pub const DEFAULT_BATCH_MAX_REQUESTS: usize = 50_000;
+/// Zhipu accepts input files up to 100 MB.
+pub const DEFAULT_BATCH_MAX_BYTES: usize = 100 * 1024 * 1024;
+/// Metadata keys a [`BatchJob`] adds to each batch it creates, so a resumed job can find
+/// batches whose creation it never recorded.
+#[cfg(feature = "files")]
+const JOB_METADATA_KEY: &str = "rustglm_job";
+#[cfg(feature = "files")]
+const SHARD_METADATA_KEY: &str = "rustglm_shard";
+
+/// One request in a [`BatchInput`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchRequest {
+    Chat(Box<ChatCompletionRequest>),
+    Embedding(Box<EmbeddingRequest>),
+}
+
+impl BatchRequest {
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            Self::Chat(_) => BATCH_CHAT_ENDPOINT,
+            Self::Embedding(_) => BATCH_EMBEDDING_ENDPOINT,
+        }
+    }
+
+    fn body(&self) -> Result<Value> {
+        let body = match self {
+            Self::Chat(request) => {
+                validate_zhipu_chat(request)?;
+                if request.stream {
+                    return Err(SdkError::Validation(
+                        "batch chat requests cannot stream".into(),
+                    ));
+                }
+                nextjson::to_value(request)
+            }
+            Self::Embedding(request) => {
+                validate_embedding(request)?;
+                nextjson::to_value(request)
+            }
+        };
+        body.map_err(|error| SdkError::Validation(error.to_string().into()))
+    }
+}
+
+impl From<ChatCompletionRequest> for BatchRequest {
+    fn from(value: ChatCompletionRequest) -> Self {
+        Self::Chat(Box::new(value))
+    }
+}
+
+impl From<EmbeddingRequest> for BatchRequest {
+    fn from(value: EmbeddingRequest) -> Self {
+        Self::Embedding(Box::new(value))
+    }
+}
+
+/// Builds a JSONL batch input file, one `{custom_id, method, url, body}` line per request.
+///
+/// Every request must target the same endpoint and have a unique `custom_id`. The request and
+/// byte limits are checked as lines are pushed, so an oversized file is never produced.
+#[derive(Debug, Clone)]
+pub struct BatchInput {
+    endpoint: Option<&'static str>,
+    lines: Vec<(String, String)>,
+    ids: HashSet<String>,
+    bytes: usize,
+    max_requests: usize,
+    max_bytes: usize,
+}
+
+impl Default for BatchInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatchInput {
+    pub fn new() -> Self {
+        Self {
+            endpoint: None,
+            lines: Vec::new(),
+            ids: HashSet::new(),
+            bytes: 0,
+            max_requests: DEFAULT_BATCH_MAX_REQUESTS,
+            max_bytes: DEFAULT_BATCH_MAX_BYTES,
+        }
+    }
+
+    pub fn max_requests(mut self, value: usize) -> Self {
+        self.max_requests = value;
+        self
+    }
+
+    pub fn max_bytes(mut self, value: usize) -> Self {
+        self.max_bytes = value;
+        self
+    }
+
+    /// Validates and appends a request. On error the input is left unchanged.
+    pub fn push(
+        &mut self,
+        custom_id: impl Into<String>,
+        request: impl Into<BatchRequest>,
+    ) -> Result<()> {
+        let custom_id = custom_id.into();
+        let request = request.into();
+        check_custom_id(&custom_id, &self.ids)?;
+        check_endpoint(self.endpoint, request.endpoint())?;
+        let line = encode_line(&custom_id, &request)?;
+        self.push_line(custom_id, request.endpoint(), line)
+    }
+
+    /// Appends an already encoded line, enforcing only the request and byte limits.
+    fn push_line(&mut self, custom_id: String, endpoint: &'static str, line: String) -> Result<()> {
+        if self.lines.len() >= self.max_requests {
+            return Err(BatchError::TooManyRequests(self.max_requests).into());
+        }
+        let bytes = self.bytes + line.len() + 1;
+        if bytes > self.max_bytes {
+            return Err(BatchError::InputTooLarge(self.max_bytes).into());
+        }
+        self.endpoint = Some(endpoint);
+        self.bytes = bytes;
+        self.ids.insert(custom_id.clone());
+        self.lines.push((custom_id, line));
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Size of the JSONL file in bytes.
+    pub fn byte_len(&self) -> usize {
+        self.bytes
+    }
+
+    /// The endpoint shared by every request, once one has been pushed.
+    pub fn endpoint(&self) -> Option<&'static str> {
+        self.endpoint
+    }
+
+    pub fn custom_ids(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|(id, _)| id.as_str())
+    }
+
+    pub fn to_jsonl(&self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(self.bytes);
+        for (_, line) in &self.lines {
+            file.extend_from_slice(line.as_bytes());
+            file.push(b'\n');
+        }
+        file
+    }
+
+    /// The upload for `ZhipuClient::upload_file`, with purpose `batch`.
+    pub fn to_upload(&self, file_name: impl Into<String>) -> Result<FileUploadRequest> {
+        if self.is_empty() {
+            return Err(BatchError::EmptyInput.into());
+        }
+        Ok(
+            FileUploadRequest::from_bytes(file_name, self.to_jsonl(), "batch")
+                .mime_type("application/jsonl"),
+        )
+    }
+
+    /// A create request for the uploaded file, using this input's endpoint.
+    pub fn create_request(&self, input_file_id: impl Into<String>) -> Result<BatchCreateRequest> {
+        let endpoint = self.endpoint.ok_or(BatchError::EmptyInput)?;
+        Ok(BatchCreateRequest::new(input_file_id, endpoint))
+    }
+}
+
+fn check_custom_id(custom_id: &str, ids: &HashSet<String>) -> Result<()> {
+    if custom_id.trim().is_empty() {
+        return Err(BatchError::EmptyCustomId.into());
+    }
+    if ids.contains(custom_id) {
+        return Err(BatchError::DuplicateCustomId(custom_id.to_owned()).into());
+    }
+    Ok(())
+}
+
+fn check_endpoint(expected: Option<&'static str>, found: &'static str) -> Result<()> {
+    match expected {
+        Some(expected) if expected != found => {
+            Err(BatchError::MixedEndpoints { expected, found }.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn encode_line(custom_id: &str, request: &BatchRequest) -> Result<String> {
+    let line = json!({
+        "custom_id": custom_id,
+        "method": "POST",
+        "url": request.endpoint(),
+        "body": request.body()?,
+    });
+    nextjson::to_string(&line).map_err(|error| SdkError::Validation(error.to_string().into()))
+}
+
+/// A request that failed inside a batch, as reported by the output or error file.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("batch request failed with status {status_code:?}: {message}")]
+pub struct BatchItemError {
+    pub status_code: Option<u16>,
+    pub code: Option<String>,
+    pub message: String,
+    /// The raw response body, when there was one.
+    pub body: Option<Value>,
+}
+
+/// Typed batch results keyed by `custom_id`, parsed from output and error files.
+///
+/// Each line describes itself, so output and error files can be added in any order; a later line
+/// for the same `custom_id` replaces an earlier one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResults<T = ChatCompletionResponse> {
+    pub items: BTreeMap<String, std::result::Result<T, BatchItemError>>,
+}
+
+impl<T> Default for BatchResults<T> {
+    fn default() -> Self {
+        Self {
+            items: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: for<'de> Deserialize<'de>> BatchResults<T> {
+    /// Parses an output file and, when the batch has one, its error file.
+    pub fn parse(output: &[u8], errors: Option<&[u8]>) -> Result<Self> {
+        let mut results = Self::default();
+        results.add_file(output)?;
+        if let Some(errors) = errors {
+            results.add_file(errors)?;
+        }
+        Ok(results)
+    }
+
+    /// Adds every line of an output or error file.
+    pub fn add_file(&mut self, file: &[u8]) -> Result<()> {
+        for (index, line) in file.split(|byte| *byte == b'\n').enumerate() {
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+            let invalid = |reason: String| BatchError::InvalidResultLine {
+                line: index + 1,
+                reason,
+            };
+            let value: Value =
+                nextjson::from_slice(line).map_err(|error| invalid(error.to_string()))?;
+            let custom_id = value
+                .get("custom_id")
+                .and_then(Value::as_str)
+                .filter(|id| !id.is_empty())
+                .ok_or_else(|| invalid("missing custom_id".into()))?
+                .to_owned();
+            let result = match parse_result(&value) {
+                Ok(body) => {
+                    Ok(nextjson::from_value(body).map_err(|error| invalid(error.to_string()))?)
+                }
+                Err(error) => Err(*error),
+            };
+            self.items.insert(custom_id, result);
+        }
+        Ok(())
+    }
+}
+
+impl<T> BatchResults<T> {
+    pub fn get(&self, custom_id: &str) -> Option<&std::result::Result<T, BatchItemError>> {
+        self.items.get(custom_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.items
+            .iter()
+            .filter_map(|(id, result)| Some((id.as_str(), result.as_ref().ok()?)))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &BatchItemError)> {
+        self.items
+            .iter()
+            .filter_map(|(id, result)| Some((id.as_str(), result.as_ref().err()?)))
+    }
+
+    /// Custom ids from `input` that have no result yet.
+    pub fn missing<'a>(&self, input: &'a BatchInput) -> Vec<&'a str> {
+        input
+            .custom_ids()
+            .filter(|id| !self.items.contains_key(*id))
+            .collect()
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        self.items.extend(other.items);
+    }
+}
+
+/// Splits a result line into the success body or the item error. Both the
+/// `{"response": {"status_code", "body"}}` and the `{"error": {"code", "message"}}` shapes are
+/// understood.
+fn parse_result(line: &Value) -> std::result::Result<Value, Box<BatchItemError>> {
+    let response = line.get("response").filter(|value| !value.is_null());
+    let status_code = response
+        .and_then(|response| response.get("status_code"))
+        .and_then(Value::as_u64)
+        .and_then(|status| u16::try_from(status).ok());
+    let body = response
+        .and_then(|response| response.get("body"))
+        .filter(|value| !value.is_null())
+        .cloned();
+    let error = line
+        .get("error")
+        .filter(|value| !value.is_null())
+        .or_else(|| body.as_ref()?.get("error"));
+    let success = status_code.is_none_or(|status| (200..300).contains(&status));
+    if error.is_none()
+        && success
+        && let Some(body) = body
+    {
+        return Ok(body);
+    }
+    let field = |name: &str| {
+        error?.get(name).map(|value| match value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        })
+    };
+    Err(Box::new(BatchItemError {
+        status_code,
+        code: field("code"),
+        message: field("message").unwrap_or_else(|| match status_code {
+            Some(status) => format!("HTTP {status}"),
+            None => "missing response body".into(),
+        }),
+        body,
+    }))
+}
+
+/// One submitted batch of a [`BatchJob`].
+#[cfg(feature = "files")]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct BatchShard {
+    #[serde(default)]
+    pub custom_ids: Vec<String>,
+    #[serde(default)]
+    pub input_file_id: Option<String>,
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    #[serde(default)]
+    pub status: Option<BatchStatus>,
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+}
+
+#[cfg(feature = "files")]
+impl BatchShard {
+    fn is_terminal(&self) -> bool {
+        self.status.as_ref().is_some_and(BatchStatus::is_terminal)
+    }
+
+    fn update(&mut self, batch: BatchObject) {
+        self.batch_id = Some(batch.id);
+        self.status = batch.status.or(self.status.take());
+        self.output_file_id = batch.output_file_id.or(self.output_file_id.take());
+        self.error_file_id = batch.error_file_id.or(self.error_file_id.take());
+    }
+}
+
+/// The persisted state of a [`BatchJob`]: which files were uploaded, which batches were created,
+/// and their last known status.
+#[cfg(feature = "files")]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct BatchJobManifest {
+    /// Identifies the job in the metadata of the batches it creates.
+    #[serde(default)]
+    pub job_id: String,
+    #[serde(default)]
+    pub endpoint: String,
+    #[serde(default)]
+    pub shards: Vec<BatchShard>,
+}
+
+#[cfg(feature = "files")]
+impl BatchJobManifest {
+    /// Reads a manifest, or returns `None` when the file does not exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(SdkError::io(path.display(), error)),
+        };
+        nextjson::from_slice(&bytes)
+            .map(Some)
+            .map_err(|error| SdkError::Decode {
+                message: error.to_string(),
+                body: String::from_utf8_lossy(&bytes).into_owned(),
+            })
+    }
+
+    /// Writes the manifest through a temporary file, so a crash never leaves it half written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = nextjson::to_vec_pretty(self)
+            .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary, bytes).map_err(|error| SdkError::io(temporary.display(), error))?;
+        fs::rename(&temporary, path).map_err(|error| SdkError::io(path.display(), error))?;
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.shards.iter().all(BatchShard::is_terminal)
+    }
+}
+
+/// Runs any number of requests as batches: shards them to fit the file limits, uploads and
+/// creates each batch, polls until every batch is terminal, and merges the results.
+///
+/// With a [`manifest_path`](Self::manifest_path) the job records its progress after every step.
+/// Running the same job again resumes from the manifest instead of resubmitting; a job with no
+/// requests pushed resumes whatever the manifest describes.
+#[cfg(feature = "files")]
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    shards: Vec<BatchInput>,
+    ids: HashSet<String>,
+    max_requests: usize,
+    max_bytes: usize,
+    poll_interval: Duration,
+    max_poll_interval: Duration,
+    timeout: Option<Duration>,
+    metadata: Option<Value>,
+    manifest_path: Option<PathBuf>,
+}
+
+#[cfg(feature = "files")]
+impl Default for BatchJob {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "files")]
+impl BatchJob {
+    pub fn new() -> Self {
+        Self {
+            shards: Vec::new(),
+            ids: HashSet::new(),
+            max_requests: DEFAULT_BATCH_MAX_REQUESTS,
+            max_bytes: DEFAULT_BATCH_MAX_BYTES,
+            poll_interval: Duration::from_secs(5),
+            max_poll_interval: Duration::from_secs(300),
+            timeout: None,
+            metadata: None,
+            manifest_path: None,
+        }
+    }
+
+    /// Requests per batch; more than this starts a new shard.
+    pub fn max_requests(mut self, value: usize) -> Self {
+        self.max_requests = value;
+        self
+    }
+
+    /// Input file size per batch; more than this starts a new shard.
+    pub fn max_bytes(mut self, value: usize) -> Self {
+        self.max_bytes = value;
+        self
+    }
+
+    /// First delay between polls. It doubles after each round, up to `max_poll_interval`.
+    pub fn poll_interval(mut self, value: Duration) -> Self {
+        self.poll_interval = value;
+        self
+    }
+
+    pub fn max_poll_interval(mut self, value: Duration) -> Self {
+        self.max_poll_interval = value;
+        self
+    }
+
+    /// Gives up polling with `SdkError::Timeout` after this long. The batches keep running and
+    /// the manifest stays resumable.
+    pub fn timeout(mut self, value: Duration) -> Self {
+        self.timeout = Some(value);
+        self
+    }
+
+    /// Metadata attached to every batch the job creates. An object gets the job's
+    /// `rustglm_job` and `rustglm_shard` markers added; other values are sent as they are.
+    pub fn metadata(mut self, value: Value) -> Self {
+        self.metadata = Some(value);
+        self
+    }
+
+    pub fn manifest_path(mut self, value: impl Into<PathBuf>) -> Self {
+        self.manifest_path = Some(value.into());
+        self
+    }
+
+    /// Validates and appends a request, starting a new shard when the current one is full.
+    pub fn push(
+        &mut self,
+        custom_id: impl Into<String>,
+        request: impl Into<BatchRequest>,
+    ) -> Result<()> {
+        let custom_id = custom_id.into();
+        let request = request.into();
+        check_custom_id(&custom_id, &self.ids)?;
+        let endpoint = request.endpoint();
+        check_endpoint(self.shards.first().and_then(BatchInput::endpoint), endpoint)?;
+        let line = encode_line(&custom_id, &request)?;
+        if let Some(shard) = self.shards.last_mut() {
+            match shard.push_line(custom_id.clone(), endpoint, line.clone()) {
+                Ok(()) => {
+                    self.ids.insert(custom_id);
+                    return Ok(());
+                }
+                Err(SdkError::Batch(
+                    BatchError::TooManyRequests(_) | BatchError::InputTooLarge(_),
+                )) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        let mut shard = BatchInput::new()
+            .max_requests(self.max_requests)
+            .max_bytes(self.max_bytes);
+        shard.push_line(custom_id.clone(), endpoint, line)?;
+        self.ids.insert(custom_id);
+        self.shards.push(shard);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn shards(&self) -> &[BatchInput] {
+        &self.shards
+    }
+
+    /// Submits, polls, and collects the results of every shard.
+    ///
+    /// Requests in a batch that ended without a result line, for example because it expired or
+    /// was cancelled, are reported as a [`BatchItemError`] carrying the batch status.
+    pub async fn run<T: for<'de> Deserialize<'de>>(
+        &self,
+        client: &ZhipuClient,
+    ) -> Result<BatchResults<T>> {
+        let mut manifest = self.manifest()?;
+        self.submit(client, &mut manifest).await?;
+        self.wait(client, &mut manifest).await?;
+        let mut results = BatchResults::default();
+        for shard in &manifest.shards {
+            let batch = BatchObject {
+                id: shard.batch_id.clone().unwrap_or_default(),
+                output_file_id: shard.output_file_id.clone(),
+                error_file_id: shard.error_file_id.clone(),
+                ..BatchObject::default()
+            };
+            results.extend(client.batch_results(&batch).await?);
+            let status = shard.status.as_ref().map_or("unknown", BatchStatus::as_str);
+            for custom_id in &shard.custom_ids {
+                if let Entry::Vacant(entry) = results.items.entry(custom_id.clone()) {
+                    entry.insert(Err(BatchItemError {
+                        status_code: None,
+                        code: Some(status.to_owned()),
+                        message: format!("batch {} ended {status} without a result", batch.id),
+                        body: None,
+                    }));
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// The saved manifest when there is one, checked against the pushed requests; otherwise a new
+    /// manifest for them.
+    fn manifest(&self) -> Result<BatchJobManifest> {
+        let saved = match &self.manifest_path {
+            Some(path) => BatchJobManifest::load(path)?,
+            None => None,
+        };
+        if let Some(saved) = saved {
+            let matches = self.shards.is_empty()
+                || (saved.shards.len() == self.shards.len()
+                    && saved.shards.iter().zip(&self.shards).all(|(saved, input)| {
+                        saved
+                            .custom_ids
+                            .iter()
+                            .map(String::as_str)
+                            .eq(input.custom_ids())
+                    }));
+            if !matches {
+                return Err(BatchError::ManifestMismatch.into());
+            }
+            let mut saved = saved;
+            if saved.job_id.is_empty() {
+                saved.job_id = job_id()?;
+            }
+            return Ok(saved);
+        }
+        let endpoint = self
+            .shards
+            .first()
+            .and_then(BatchInput::endpoint)
+            .ok_or(BatchError::EmptyInput)?;
+        Ok(BatchJobManifest {
+            job_id: job_id()?,
+            endpoint: endpoint.to_owned(),
+            shards: self
+                .shards
+                .iter()
+                .map(|input| BatchShard {
+                    custom_ids: input.custom_ids().map(str::to_owned).collect(),
+                    ..BatchShard::default()
+                })
+                .collect(),
+        })
+    }
+
+    fn save(&self, manifest: &BatchJobManifest) -> Result<()> {
+        match &self.manifest_path {
+            Some(path) => manifest.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Uploads and creates each shard's batch. A shard whose input file was uploaded by an
+    /// earlier run may already have a batch the manifest never recorded, so the existing
+    /// batches are searched for it first.
+    async fn submit(&self, client: &ZhipuClient, manifest: &mut BatchJobManifest) -> Result<()> {
+        for index in 0..manifest.shards.len() {
+            if manifest.shards[index].batch_id.is_some() {
+                continue;
+            }
+            let input_file_id = match manifest.shards[index].input_file_id.clone() {
+                Some(id) => {
+                    if let Some(batch) = find_batch(client, &manifest.job_id, index, &id).await? {
+                        manifest.shards[index].update(batch);
+                        self.save(manifest)?;
+                        continue;
+                    }
+                    id
+                }
+                None => {
+                    let input = self.shards.get(index).ok_or(BatchError::ManifestMismatch)?;
+                    let upload = input.to_upload(format!("batch-{index}.jsonl"))?;
+                    let file = client.upload_file(upload).await?;
+                    manifest.shards[index].input_file_id = Some(file.id.clone());
+                    self.save(manifest)?;
+                    file.id
+                }
+            };
+            let mut request = BatchCreateRequest::new(input_file_id, manifest.endpoint.clone());
+            request.metadata = Some(
+                self.metadata
+                    .clone()
+                    .unwrap_or_else(|| Value::Object(Map::new())),
+            );
+            if let Some(Value::Object(object)) = &mut request.metadata {
+                object.insert(JOB_METADATA_KEY.into(), manifest.job_id.clone().into());
+                object.insert(SHARD_METADATA_KEY.into(), index.to_string().into());
+            }
+            let batch = client.create_batch(&request).await?;
+            manifest.shards[index].update(batch);
+            self.save(manifest)?;
+        }
+        Ok(())
+    }
+
+    async fn wait(&self, client: &ZhipuClient, manifest: &mut BatchJobManifest) -> Result<()> {
+        let started = Instant::now();
+        let mut interval = self.poll_interval;
+        loop {
+            let before = manifest.clone();
+            for shard in manifest
+                .shards
+                .iter_mut()
+                .filter(|shard| !shard.is_terminal())
+            {
+                let batch_id = shard.batch_id.clone().unwrap_or_default();
+                shard.update(client.batch(&batch_id).await?);
+            }
+            if *manifest != before {
+                self.save(manifest)?;
+            }
+            if manifest.is_finished() {
+                return Ok(());
+            }
+            if self
+                .timeout
+                .is_some_and(|timeout| started.elapsed() + interval > timeout)
+            {
+                return Err(TimeoutError::Operation {
+                    operation: "batch job",
+                }
+                .into());
+            }
+            sleep(interval).await;
+            interval = interval.saturating_mul(2).min(self.max_poll_interval);
+        }
+    }
+}
+
+/// Pages through the account's batches for one created from `input_file_id` or carrying this
+/// job's marker for `shard`.
+#[cfg(feature = "files")]
+async fn find_batch(
+    client: &ZhipuClient,
+    job_id: &str,
+    shard: usize,
+    input_file_id: &str,
+) -> Result<Option<BatchObject>> {
+    let shard = shard.to_string();
+    let mut after = None;
+    loop {
+        let page = client.batches(Some(100), after.as_deref()).await?;
+        let found = page.data.iter().position(|batch| {
+            let marker = |key: &str| batch.metadata.as_ref()?.get(key)?.as_str();
+            batch.input_file_id.as_deref() == Some(input_file_id)
+                || (marker(JOB_METADATA_KEY) == Some(job_id)
+                    && marker(SHARD_METADATA_KEY) == Some(shard.as_str()))
+        });
+        if let Some(index) = found {
+            return Ok(page.data.into_iter().nth(index));
+        }
+        match page
+            .last_id
+            .or_else(|| page.data.last().map(|batch| batch.id.clone()))
+        {
+            Some(last) if page.has_more => after = Some(last),
+            _ => return Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "files")]
+fn job_id() -> Result<String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| SdkError::Configuration("system clock is before Unix epoch".into()))?
+        .as_nanos();
+    Ok(format!("job-{timestamp:x}-{}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatMessage;
+
+    fn chat(text: &str) -> ChatCompletionRequest {
+        ChatCompletionRequest::new("glm-5").message(ChatMessage::user(text))
+    }
+
+    #[test]
+    fn input_validates_ids_endpoints_and_limits() {
+        let mut input = BatchInput::new().max_requests(2);
+        input.push("a", chat("hello")).unwrap();
+        assert!(matches!(
+            input.push("a", chat("again")),
+            Err(SdkError::Batch(BatchError::DuplicateCustomId(id))) if id == "a"
+        ));
+        assert!(matches!(
+            input.push(" ", chat("blank")),
+            Err(SdkError::Batch(BatchError::EmptyCustomId))
+        ));
+        let embedding = EmbeddingRequest::new("embedding-3", "x".to_owned());
+        assert!(matches!(
+            input.push("e", embedding),
+            Err(SdkError::Batch(BatchError::MixedEndpoints { .. }))
+        ));
+        let mut streaming = chat("stream");
+        streaming.stream = true;
+        assert!(matches!(
+            input.push("s", streaming),
+            Err(SdkError::Validation(_))
+        ));
+        input.push("b", chat("world")).unwrap();
+        assert!(matches!(
+            input.push("c", chat("!")),
+            Err(SdkError::Batch(BatchError::TooManyRequests(2)))
+        ));
+
+        let file = input.to_jsonl();
+        assert_eq!(file.len(), input.byte_len());
+        let lines = file
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| nextjson::from_slice::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["custom_id"].as_str(), Some("b"));
+        assert_eq!(lines[1]["url"].as_str(), Some(BATCH_CHAT_ENDPOINT));
+        assert_eq!(lines[1]["body"]["model"].as_str(), Some("glm-5"));
+        let upload = input.to_upload("input.jsonl").unwrap();
+        assert_eq!(upload.purpose, "batch");
+        assert_eq!(
+            input.create_request("file-1").unwrap().endpoint,
+            BATCH_CHAT_ENDPOINT
+        );
+
+        let mut small = BatchInput::new().max_bytes(64);
+        assert!(matches!(
+            small.push("a", chat("a message that will not fit in sixty-four bytes")),
+            Err(SdkError::Batch(BatchError::InputTooLarge(64)))
+        ));
+        assert!(small.is_empty());
+        assert!(matches!(
+            small.to_upload("empty.jsonl"),
+            Err(SdkError::Batch(BatchError::EmptyInput))
+        ));
+    }
+
+    #[test]
+    fn results_map_output_and_error_files_by_custom_id() {
+        let output = br#"{"id":"batch_1","custom_id":"a","response":{"status_code":200,"body":{"id":"chat-a","model":"glm-5","choices":[{"index":0,"message":{"role":"assistant","content":"hi"},"finish_reason":"stop"}]}}}
+{"custom_id":"b","response":{"status_code":400,"body":{"error":{"code":"1214","message":"bad request"}}}}
+"#;
+        let errors =
+            br#"{"custom_id":"c","response":null,"error":{"code":"timeout","message":"expired"}}"#;
+        let results = BatchResults::<ChatCompletionResponse>::parse(output, Some(errors)).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.get("a").unwrap().as_ref().unwrap().id, "chat-a");
+        let failed = results.failed().collect::<Vec<_>>();
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].1.status_code, Some(400));
+        assert_eq!(failed[0].1.code.as_deref(), Some("1214"));
+        assert_eq!(failed[1].1.message, "expired");
+
+        let mut input = BatchInput::new();
+        for id in ["a", "b", "c", "d"] {
+            input.push(id, chat("x")).unwrap();
+        }
+        assert_eq!(results.missing(&input), ["d"]);
+
+        assert!(matches!(
+            BatchResults::<ChatCompletionResponse>::parse(b"{}\n{\"custom_id\":\"a\"}", None),
+            Err(SdkError::Batch(BatchError::InvalidResultLine {
+                line: 1,
+                ..
+            }))
+        ));
+    }
+}
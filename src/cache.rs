@@ -0,0 +1,474 @@
+//! Opt-in caching of deterministic responses, keyed by a SHA-256 hash of the endpoint path and
+//! the canonical request JSON.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize, Value};
+use sha2::{Digest, Sha256};
+
+use crate::json::canonical;
+use crate::{ChatCompletionRequest, Result, SdkError};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Marks a response served from a [`ResponseCache`] instead of the API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheHit {
+    pub key: String,
+    /// Time since the response was stored.
+    pub age: Duration,
+}
+
+/// A response with its cache status, returned by the `*_with_cache_info` client methods.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cached<R> {
+    pub response: R,
+    /// `Some` when this response came from the cache.
+    pub cache_hit: Option<CacheHit>,
+}
+
+impl<R> Cached<R> {
+    pub fn is_hit(&self) -> bool {
+        self.cache_hit.is_some()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.response
+    }
+}
+
+/// Lookup counts shared by every clone of a [`ResponseCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Requests answered from the cache.
+    pub hits: u64,
+    /// Cacheable requests that went to the API.
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    pub body: Value,
+    pub stored_at: SystemTime,
+    pub expires_at: SystemTime,
+}
+
+impl CachedResponse {
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() >= self.expires_at
+    }
+}
+
+/// Backing storage for a [`ResponseCache`]. Implement it to share a cache through Redis or
+/// similar; the built-in stores are [`MemoryCacheStore`] and [`DiskCacheStore`].
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    /// Returns the entry for `key`, or `None` when it is missing or expired.
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>>;
+    async fn put(&self, key: &str, entry: CachedResponse) -> Result<()>;
+}
+
+/// A cache for `ZhipuClient::chat_completion`, `embedding`, and `rerank`, set with
+/// `ZhipuConfig::response_cache`.
+///
+/// Chat requests are only cached when they are deterministic: `do_sample: false` or
+/// `temperature: 0`, and no hosted tools such as web search. `request_id` is left out of the key.
+/// A failing store is treated as a miss, so the cache never fails a request.
+#[derive(Clone)]
+pub struct ResponseCache {
+    store: Arc<dyn CacheStore>,
+    ttl: Duration,
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(store: impl CacheStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl: DEFAULT_TTL,
+            counters: Arc::default(),
+        }
+    }
+
+    /// An in-process LRU cache holding at most `capacity` responses.
+    pub fn in_memory(capacity: usize) -> Self {
+        Self::new(MemoryCacheStore::new(capacity))
+    }
+
+    /// A cache that keeps one JSON file per response under `directory`.
+    pub fn on_disk(directory: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self::new(DiskCacheStore::open(directory)?))
+    }
+
+    /// How long a stored response stays valid. Defaults to 24 hours.
+    pub fn ttl(mut self, value: Duration) -> Self {
+        self.ttl = value;
+        self
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) async fn get<R: for<'de> Deserialize<'de>>(
+        &self,
+        key: &str,
+    ) -> Option<(R, CacheHit)> {
+        let response = self
+            .store
+            .get(key)
+            .await
+            .ok()
+            .flatten()
+            .filter(|entry| !entry.is_expired())
+            .and_then(|entry| {
+                let hit = CacheHit {
+                    key: key.to_owned(),
+                    age: entry.stored_at.elapsed().unwrap_or_default(),
+                };
+                Some((nextjson::from_value::<R>(entry.body).ok()?, hit))
+            });
+        let counter = match response {
+            Some(_) => &self.counters.hits,
+            None => &self.counters.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        response
+    }
+
+    pub(crate) async fn put<R: Serialize>(&self, key: &str, response: &R) {
+        let Ok(body) = nextjson::to_value(response) else {
+            return;
+        };
+        let stored_at = SystemTime::now();
+        let entry = CachedResponse {
+            body,
+            stored_at,
+            expires_at: stored_at + self.ttl,
+        };
+        let _ = self.store.put(key, entry).await;
+    }
+}
+
+/// Hashes `path` and the canonical JSON of `request`, without its `request_id`.
+pub(crate) fn cache_key<T: Serialize + ?Sized>(path: &str, request: &T) -> Result<String> {
+    let mut value = nextjson::to_value(request)
+        .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+    if let Value::Object(object) = &mut value {
+        object.remove("request_id");
+    }
+    let body = nextjson::to_vec(&canonical(&value))
+        .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+    let mut hasher = Sha256::new();
+    hasher.update(path.trim_matches('/').as_bytes());
+    hasher.update(b"\n");
+    hasher.update(&body);
+    let mut key = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(key, "{byte:02x}");
+    }
+    Ok(key)
+}
+
+/// Whether a chat request always produces the same output, so its response may be cached.
+pub(crate) fn is_deterministic(request: &ChatCompletionRequest) -> bool {
+    let greedy = request.do_sample == Some(false) || request.temperature == Some(0.0);
+    let local_tools = request
+        .tools
+        .iter()
+        .flatten()
+        .all(|tool| tool.kind == "function");
+    greedy && local_tools
+}
+
+/// An in-process store that evicts the least recently used entry once it holds `capacity`.
+pub struct MemoryCacheStore {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<String, (CachedResponse, u64)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, key: &str) -> Option<&CachedResponse> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (entry, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = tick;
+        self.order.insert(tick, key.to_owned());
+        Some(entry)
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+        }
+    }
+}
+
+impl MemoryCacheStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>> {
+        let mut state = self.lock();
+        match state.touch(key).cloned() {
+            Some(entry) if entry.is_expired() => {
+                state.remove(key);
+                Ok(None)
+            }
+            entry => Ok(entry),
+        }
+    }
+
+    async fn put(&self, key: &str, entry: CachedResponse) -> Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut state = self.lock();
+        state.remove(key);
+        while state.entries.len() >= self.capacity {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        state.order.insert(tick, key.to_owned());
+        state.entries.insert(key.to_owned(), (entry, tick));
+        Ok(())
+    }
+}
+
+/// A store that keeps each entry in `<directory>/<key>.json`, so it survives restarts and can be
+/// shared by processes on one machine. Expired files are removed when read. File access runs
+/// on Tokio's blocking pool.
+pub struct DiskCacheStore {
+    directory: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    stored_at_ms: u64,
+    expires_at_ms: u64,
+    body: Value,
+}
+
+impl DiskCacheStore {
+    pub fn open(directory: impl Into<PathBuf>) -> Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).map_err(|error| SdkError::io(directory.display(), error))?;
+        Ok(Self { directory })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty()
+            || !key
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        {
+            return Err(SdkError::Validation(
+                "cache keys must be ASCII letters, digits, or '-'".into(),
+            ));
+        }
+        Ok(self.directory.join(format!("{key}.json")))
+    }
+}
+
+#[async_trait]
+impl CacheStore for DiskCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>> {
+        let path = self.path(key)?;
+        blocking(move || read_entry(&path)).await
+    }
+
+    async fn put(&self, key: &str, entry: CachedResponse) -> Result<()> {
+        let path = self.path(key)?;
+        blocking(move || write_entry(&path, entry)).await
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    operation: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    tokio::task::spawn_blocking(operation)
+        .await
+        .map_err(|error| {
+            SdkError::Configuration(format!("cache file task failed: {error}").into())
+        })?
+}
+
+fn read_entry(path: &Path) -> Result<Option<CachedResponse>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(SdkError::io(path.display(), error)),
+    };
+    let entry: DiskEntry = nextjson::from_slice(&bytes).map_err(|error| SdkError::Decode {
+        message: format!("invalid cache entry {}: {error}", path.display()),
+        body: String::new(),
+    })?;
+    let entry = CachedResponse {
+        body: entry.body,
+        stored_at: UNIX_EPOCH + Duration::from_millis(entry.stored_at_ms),
+        expires_at: UNIX_EPOCH + Duration::from_millis(entry.expires_at_ms),
+    };
+    if entry.is_expired() {
+        let _ = fs::remove_file(path);
+        return Ok(None);
+    }
+    Ok(Some(entry))
+}
+
+fn write_entry(path: &Path, entry: CachedResponse) -> Result<()> {
+    let millis = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    };
+    let bytes = nextjson::to_vec(&DiskEntry {
+        stored_at_ms: millis(entry.stored_at),
+        expires_at_ms: millis(entry.expires_at),
+        body: entry.body,
+    })
+    .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+    let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temporary, bytes).map_err(|error| SdkError::io(temporary.display(), error))?;
+    fs::rename(&temporary, path).map_err(|error| SdkError::io(path.display(), error))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nextjson::json;
+
+    use super::*;
+
+    fn entry(body: Value, ttl: Duration) -> CachedResponse {
+        let stored_at = SystemTime::now();
+        CachedResponse {
+            body,
+            stored_at,
+            expires_at: stored_at + ttl,
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_store_evicts_least_recently_used_and_expired_entries() {
+        let store = MemoryCacheStore::new(2);
+        let hour = Duration::from_secs(3600);
+        store.put("a", entry(json!(1), hour)).await.unwrap();
+        store.put("b", entry(json!(2), hour)).await.unwrap();
+        assert!(store.get("a").await.unwrap().is_some());
+        store.put("c", entry(json!(3), hour)).await.unwrap();
+        assert!(store.get("b").await.unwrap().is_none());
+        assert_eq!(store.get("a").await.unwrap().unwrap().body, json!(1));
+        store
+            .put("c", entry(json!(4), Duration::ZERO))
+            .await
+            .unwrap();
+        assert!(store.get("c").await.unwrap().is_none());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn disk_store_round_trips_and_drops_expired_files() {
+        let temporary = crate::test_support::TempDir::new("cache");
+        let directory = temporary.join("entries");
+        let store = DiskCacheStore::open(&directory).unwrap();
+        store
+            .put("fresh", entry(json!({"ok": true}), Duration::from_secs(60)))
+            .await
+            .unwrap();
+        store
+            .put("stale", entry(json!({}), Duration::ZERO))
+            .await
+            .unwrap();
+        let reopened = DiskCacheStore::open(&directory).unwrap();
+        assert_eq!(
+            reopened.get("fresh").await.unwrap().unwrap().body,
+            json!({"ok": true})
+        );
+        assert!(reopened.get("stale").await.unwrap().is_none());
+        assert!(!directory.join("stale.json").exists());
+        assert!(reopened.get("../escape").await.is_err());
+    }
+
+    #[test]
+    fn keys_ignore_field_order_and_request_id() {
+        let key = cache_key("embeddings", &json!({"model": "m", "input": "x"})).unwrap();
+        assert_eq!(key.len(), 64);
+        assert_eq!(
+            key,
+            cache_key(
+                "/embeddings",
+                &json!({"input": "x", "request_id": "r-1", "model": "m"})
+            )
+            .unwrap()
+        );
+        assert_ne!(
+            key,
+            cache_key("rerank", &json!({"model": "m", "input": "x"})).unwrap()
+        );
+
+        let request = ChatCompletionRequest::new("glm-5");
+        assert!(!is_deterministic(&request));
+        assert!(is_deterministic(&request.clone().temperature(0.0)));
+        let mut search = request.temperature(0.0);
+        search.tools = Some(vec![crate::Tool::configured(
+            "web_search",
+            "web_search",
+            json!({}),
+        )]);
+        assert!(!is_deterministic(&search));
+    }
+}
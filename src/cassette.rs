@@ -0,0 +1,462 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use nextjson::NsonDeserialize as Deserialize;
+use nextjson::NsonSerialize as Serialize;
+use nextjson::Value;
+use reqwest::header::{HeaderName, HeaderValue, SET_COOKIE};
+use reqwest::{Body, Response};
+use tokio::time::sleep_until;
+
+use crate::json::canonical;
+use crate::security::mask_sensitive;
+use crate::{OutgoingRequest, Result, SdkError};
+
+const CASSETTE_VERSION: u32 = 1;
+
+/// Whether a [`Cassette`] captures live traffic or serves it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// Recorded HTTP interactions for tests that cannot reach the API.
+///
+/// In record mode every response is kept once its body has been read to the end, chunk by chunk
+/// with arrival times, so SSE streams keep their shape. The file is written by [`save`] and when
+/// the last clone of the cassette is dropped; call `save` to see write errors. Authorization
+/// headers are never stored, and paths, bodies and header values go through the same secret
+/// filter as `ApiError`. In replay mode nothing reaches the network: each request is answered by
+/// the first unused interaction with the same method, path and normalized JSON body.
+///
+/// [`save`]: Cassette::save
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    realtime: bool,
+    ignored_fields: Vec<String>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    interactions: Vec<Interaction>,
+    used: Vec<bool>,
+    /// The file to write on drop, in record mode.
+    recording: Option<PathBuf>,
+    unsaved: bool,
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        if let Some(path) = self.recording.as_deref().filter(|_| self.unsaved)
+            && let Err(error) = write_cassette(path, &self.interactions)
+        {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(path = %path.display(), %error, "failed to save cassette");
+            #[cfg(not(feature = "tracing"))]
+            let _ = error;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CassetteFile {
+    version: u32,
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default)]
+    headers: Vec<RecordedHeader>,
+    #[serde(default)]
+    chunks: Vec<RecordedChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedHeader {
+    name: String,
+    value: String,
+}
+
+/// One body chunk, `at_ms` after the request was sent. Text is stored as-is, anything else as
+/// base64.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedChunk {
+    at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base64: Option<String>,
+}
+
+impl Cassette {
+    /// Starts an empty cassette at `path`, replacing any existing file.
+    pub fn record(path: impl AsRef<Path>) -> Result<Self> {
+        let state = State {
+            interactions: Vec::new(),
+            used: Vec::new(),
+            recording: Some(path.as_ref().to_owned()),
+            unsaved: false,
+        };
+        let cassette = Self::new(path.as_ref(), CassetteMode::Record, state);
+        cassette.save()?;
+        Ok(cassette)
+    }
+
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file: CassetteFile = nextjson::from_slice(
+            &std::fs::read(path).map_err(|error| SdkError::io(path.display(), error))?,
+        )
+        .map_err(|error| SdkError::Decode {
+            message: format!("cassette {}: {error}", path.display()),
+            body: String::new(),
+        })?;
+        if file.version != CASSETTE_VERSION {
+            return Err(SdkError::Configuration(
+                format!("unsupported cassette version {}", file.version).into(),
+            ));
+        }
+        let state = State {
+            used: vec![false; file.interactions.len()],
+            interactions: file.interactions,
+            recording: None,
+            unsaved: false,
+        };
+        Ok(Self::new(path, CassetteMode::Replay, state))
+    }
+
+    fn new(path: &Path, mode: CassetteMode, state: State) -> Self {
+        Self {
+            path: path.to_owned(),
+            mode,
+            realtime: false,
+            ignored_fields: Vec::new(),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Replays body chunks at their recorded offsets instead of all at once.
+    pub fn realtime(mut self, value: bool) -> Self {
+        self.realtime = value;
+        self
+    }
+
+    /// Leaves a top-level JSON body field out of request matching, such as a generated
+    /// `request_id`.
+    pub fn ignore_body_field(mut self, value: impl Into<String>) -> Self {
+        self.ignored_fields.push(value.into());
+        self
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of interactions recorded or loaded so far.
+    pub fn len(&self) -> usize {
+        self.lock().map_or(0, |state| state.interactions.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the cassette file with every interaction recorded so far. This blocks the calling
+    /// thread.
+    pub fn save(&self) -> Result<()> {
+        let mut state = self.lock()?;
+        write_cassette(&self.path, &state.interactions)?;
+        state.unsaved = false;
+        Ok(())
+    }
+
+    pub(crate) fn replay_response(
+        &self,
+        request: &OutgoingRequest,
+        secrets: &[String],
+    ) -> Result<Response> {
+        let wanted = self.recorded_request(request, secrets);
+        let response = {
+            let mut state = self.lock()?;
+            let State {
+                interactions, used, ..
+            } = &mut *state;
+            let index = interactions
+                .iter()
+                .zip(used.iter())
+                .position(|(interaction, used)| {
+                    !*used && self.matches(&interaction.request, &wanted)
+                })
+                .ok_or_else(|| {
+                    SdkError::Configuration(
+                        format!(
+                            "cassette {} has no unused interaction for {} {}",
+                            self.path.display(),
+                            wanted.method,
+                            wanted.path
+                        )
+                        .into(),
+                    )
+                })?;
+            used[index] = true;
+            interactions[index].response.clone()
+        };
+        let mut builder = http::Response::builder().status(response.status);
+        for header in &response.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(header.name.as_bytes()),
+                HeaderValue::from_str(&header.value),
+            ) {
+                builder = builder.header(name, value);
+            }
+        }
+        let mut chunks = Vec::with_capacity(response.chunks.len());
+        for chunk in &response.chunks {
+            chunks.push((Duration::from_millis(chunk.at_ms), chunk.bytes()?));
+        }
+        let realtime = self.realtime;
+        let started = tokio::time::Instant::now();
+        let body = async_stream::stream! {
+            for (at, bytes) in chunks {
+                if realtime {
+                    sleep_until(started + at).await;
+                }
+                yield Ok::<Bytes, std::io::Error>(bytes);
+            }
+        };
+        let response = builder
+            .body(Body::wrap_stream(body))
+            .map_err(|error| SdkError::Configuration(error.to_string().into()))?;
+        Ok(response.into())
+    }
+
+    /// Passes `response` through, recording its body as the caller reads it.
+    pub(crate) fn record_response(
+        &self,
+        request: &OutgoingRequest,
+        response: Response,
+        started: Instant,
+        secrets: Vec<String>,
+    ) -> Response {
+        let recorded = self.recorded_request(request, &secrets);
+        let status = response.status();
+        let header_map = response.headers().clone();
+        let mut headers = Vec::new();
+        for (name, value) in &header_map {
+            if name == SET_COOKIE {
+                continue;
+            }
+            if let Ok(value) = value.to_str() {
+                headers.push(RecordedHeader {
+                    name: name.as_str().to_owned(),
+                    value: scrub(value, &secrets),
+                });
+            }
+        }
+        let cassette = self.clone();
+        let mut source = response.bytes_stream();
+        let body = async_stream::stream! {
+            let mut chunks = Vec::new();
+            while let Some(item) = source.next().await {
+                match item {
+                    Ok(bytes) => {
+                        chunks.push(RecordedChunk::new(started.elapsed(), &bytes, &secrets));
+                        yield Ok(bytes);
+                    }
+                    Err(error) => {
+                        yield Err(error);
+                        return;
+                    }
+                }
+            }
+            cassette.push(Interaction {
+                request: recorded,
+                response: RecordedResponse {
+                    status: status.as_u16(),
+                    headers,
+                    chunks,
+                },
+            });
+        };
+        let mut response = http::Response::new(Body::wrap_stream(body));
+        *response.status_mut() = status;
+        *response.headers_mut() = header_map;
+        response.into()
+    }
+
+    fn push(&self, interaction: Interaction) {
+        if let Ok(mut state) = self.lock() {
+            state.interactions.push(interaction);
+            state.used.push(false);
+            state.unsaved = true;
+        }
+    }
+
+    fn recorded_request(&self, request: &OutgoingRequest, secrets: &[String]) -> RecordedRequest {
+        let body = request.body.as_ref().map(|body| {
+            let text = scrub(&String::from_utf8_lossy(body), secrets);
+            nextjson::from_str::<Value>(&text).unwrap_or(Value::String(text))
+        });
+        RecordedRequest {
+            method: request.method.as_str().to_owned(),
+            path: scrub(request.path.trim_start_matches('/'), secrets),
+            body,
+        }
+    }
+
+    fn matches(&self, recorded: &RecordedRequest, wanted: &RecordedRequest) -> bool {
+        recorded.method == wanted.method
+            && recorded.path == wanted.path
+            && recorded.body.as_ref().map(|body| self.normalize(body))
+                == wanted.body.as_ref().map(|body| self.normalize(body))
+    }
+
+    fn normalize(&self, body: &Value) -> Value {
+        let mut body = canonical(body);
+        if let Value::Object(object) = &mut body {
+            for field in &self.ignored_fields {
+                object.remove(field);
+            }
+        }
+        body
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, State>> {
+        self.state
+            .lock()
+            .map_err(|_| SdkError::Configuration("cassette lock is poisoned".into()))
+    }
+}
+
+impl RecordedChunk {
+    fn new(at: Duration, bytes: &[u8], secrets: &[String]) -> Self {
+        let at_ms = u64::try_from(at.as_millis()).unwrap_or(u64::MAX);
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Self {
+                at_ms,
+                text: Some(scrub(text, secrets)),
+                base64: None,
+            },
+            Err(_) => Self {
+                at_ms,
+                text: None,
+                base64: Some(STANDARD.encode(bytes)),
+            },
+        }
+    }
+
+    fn bytes(&self) -> Result<Bytes> {
+        match (&self.text, &self.base64) {
+            (Some(text), _) => Ok(Bytes::from(text.clone())),
+            (None, Some(data)) => {
+                STANDARD
+                    .decode(data)
+                    .map(Bytes::from)
+                    .map_err(|error| SdkError::Decode {
+                        message: format!("cassette chunk is not valid base64: {error}"),
+                        body: String::new(),
+                    })
+            }
+            (None, None) => Ok(Bytes::new()),
+        }
+    }
+}
+
+fn write_cassette(path: &Path, interactions: &[Interaction]) -> Result<()> {
+    let file = CassetteFile {
+        version: CASSETTE_VERSION,
+        interactions: interactions.to_vec(),
+    };
+    let bytes = nextjson::to_vec_pretty(&file)
+        .map_err(|error| SdkError::Configuration(error.to_string().into()))?;
+    std::fs::write(path, bytes).map_err(|error| SdkError::io(path.display(), error))
+}
+
+fn scrub(text: &str, secrets: &[String]) -> String {
+    let secrets = secrets.iter().map(String::as_str).collect::<Vec<_>>();
+    mask_sensitive(text, &secrets)
+}
+
+#[cfg(test)]
+mod tests {
+    use nextjson::json;
+    use reqwest::Method;
+    use reqwest::header::HeaderMap;
+
+    use super::*;
+
+    fn request(path: &str, body: Value) -> OutgoingRequest {
+        OutgoingRequest {
+            method: Method::POST,
+            path: path.into(),
+            headers: HeaderMap::new(),
+            body: Some(Bytes::from(nextjson::to_vec(&body).unwrap())),
+        }
+    }
+
+    #[test]
+    fn matches_on_normalized_bodies_and_scrubs_secrets() {
+        let cassette = Cassette::new(
+            Path::new("unused.json"),
+            CassetteMode::Replay,
+            State::default(),
+        )
+        .ignore_body_field("request_id");
+        let secrets = vec!["sk-live".to_owned()];
+        let recorded = cassette.recorded_request(
+            &request(
+                "/chat/completions",
+                json!({"model":"glm-5","request_id":"a","meta":{"y":1,"x":"sk-live"}}),
+            ),
+            &secrets,
+        );
+        assert_eq!(recorded.path, "chat/completions");
+        assert!(!nextjson::to_string(&recorded).unwrap().contains("sk-live"));
+        let reordered = cassette.recorded_request(
+            &request(
+                "chat/completions",
+                json!({"meta":{"x":"sk-live","y":1},"request_id":"b","model":"glm-5"}),
+            ),
+            &secrets,
+        );
+        assert!(cassette.matches(&recorded, &reordered));
+        let different = cassette.recorded_request(
+            &request("chat/completions", json!({"model":"glm-4"})),
+            &secrets,
+        );
+        assert!(!cassette.matches(&recorded, &different));
+
+        let binary = RecordedChunk::new(Duration::from_millis(5), &[0xff, 0x00], &secrets);
+        assert_eq!(binary.base64.as_deref(), Some("/wA="));
+        assert_eq!(binary.bytes().unwrap(), Bytes::from_static(&[0xff, 0x00]));
+    }
+}
@@ -18,6 +18,8 @@ use reqwest::header::{HeaderMap, HeaderValue};
 #[cfg(any(feature = "audio", feature = "files"))]
 use reqwest::multipart::{Form, Part};
 
+#[cfg(all(feature = "batch", feature = "files"))]
+use crate::BatchResults;
 #[cfg(any(feature = "audio", feature = "files"))]
 use crate::Bytes;
 #[cfg(feature = "video")]
@@ -27,10 +29,13 @@ use crate::agent::official_agent_stream;
 #[cfg(feature = "rag")]
 use crate::agent::retrieval_agent_stream;
 use crate::auth::AuthenticationProvider;
+use crate::cache::{Cached, cache_key, is_deterministic};
 use crate::provider::{ChatProvider, ChatStream, ProviderCapabilities};
 #[cfg(feature = "tools")]
 use crate::security::validate_http_url;
-use crate::transport::Transport;
+use crate::transport::{StreamResponse, Transport};
+#[cfg(feature = "tools")]
+use crate::types::{validate_search_count, validate_search_domain, validate_search_recency};
 #[cfg(feature = "agents")]
 use crate::{
     AgentAsyncResultRequest, AgentAsyncResultResponse, AgentConversationRequest,
@@ -38,8 +43,9 @@ use crate::{
 };
 use crate::{
     AsyncTaskResponse, AsyncTaskResult, ChatCompletionChunk, ChatCompletionRequest,
-    ChatCompletionResponse, EmbeddingRequest, EmbeddingResponse, HttpConfig, RerankRequest,
-    RerankResponse, Result, SdkError, TokenizerRequest, TokenizerResponse, ZhipuAuthentication,
+    ChatCompletionResponse, CircuitBreakerConfig, EmbeddingRequest, EmbeddingResponse,
+    FailoverConfig, HttpConfig, RerankRequest, RerankResponse, ResilientChatStream, ResponseCache,
+    Result, SdkError, StreamResumePolicy, TokenizerRequest, TokenizerResponse, ZhipuAuthentication,
 };
 #[cfg(feature = "batch")]
 use crate::{BatchCreateRequest, BatchError, BatchList, BatchObject};
@@ -51,6 +57,11 @@ use crate::{
 use crate::{ChatModel, Ready, TypedChatRequest};
 #[cfg(feature = "files")]
 use crate::{DeleteResponse, FileList, FileObject, FileUploadRequest};
+#[cfg(feature = "files")]
+use crate::{
+    FileParseRequest, FileParseTask, LayoutParsingRequest, LayoutParsingResponse, OcrRequest,
+    OcrResponse, ParsedDocument,
+};
 #[cfg(feature = "audio")]
 use crate::{Glm4VoiceRequest, SpeechRequest, TranscriptionRequest, TranscriptionResponse};
 #[cfg(feature = "images")]
@@ -59,16 +70,29 @@ use crate::{ImageGenerationRequest, ImageGenerationResponse};
 use crate::{ModerationInput, ModerationItem, ModerationRequest, ModerationResponse};
 #[cfg(feature = "rag")]
 use crate::{RetrievalAgentRequest, RetrievalAgentStream};
+#[cfg(feature = "tools")]
+use crate::{WebReaderRequest, WebReaderResponse, WebSearchRequest, WebSearchResponse};
 
 pub const ZHIPU_BASE_URL: &str = "https://open.bigmodel.cn/api/paas/v4";
 pub const ZHIPU_AGENT_BASE_URL: &str = "https://open.bigmodel.cn/api";
 
+const ZHIPU_REQUEST_ID_PATHS: [&str; 6] = [
+    "chat/completions",
+    "embeddings",
+    "images/generations",
+    "videos/generations",
+    "rerank",
+    "tokenizer",
+];
+
 #[derive(Clone)]
 pub struct ZhipuConfig {
     pub authentication: ZhipuAuthentication,
     pub base_url: String,
     pub agent_base_url: String,
+    pub failover: FailoverConfig,
     pub http: HttpConfig,
+    pub response_cache: Option<ResponseCache>,
 }
 
 impl ZhipuConfig {
@@ -77,7 +101,9 @@ impl ZhipuConfig {
             authentication: ZhipuAuthentication::auto(api_key),
             base_url: ZHIPU_BASE_URL.into(),
             agent_base_url: ZHIPU_AGENT_BASE_URL.into(),
+            failover: FailoverConfig::default(),
             http: HttpConfig::default(),
+            response_cache: None,
         }
     }
 
@@ -96,11 +122,58 @@ impl ZhipuConfig {
         self
     }
 
-    pub fn http(mut self, value: HttpConfig) -> Self {
+    /// Adds a mirror of `base_url`, tried in the order added when earlier ones are unhealthy.
+    pub fn fallback_base_url(mut self, value: impl Into<String>) -> Self {
+        self.failover.base_urls.push(value.into());
+        self
+    }
+
+    pub fn circuit_breaker(mut self, value: CircuitBreakerConfig) -> Self {
+        self.failover.circuit_breaker = value;
+        self
+    }
+
+    /// Marks POST paths such as `"embeddings"` as safe to re-send to a fallback base URL.
+    pub fn retry_safe_path(mut self, value: impl Into<String>) -> Self {
+        self.failover.retry_safe_paths.push(value.into());
+        self
+    }
+
+    /// Replaces the HTTP settings. Idempotent endpoints already registered, for example by
+    /// [`ZhipuConfig::idempotent_retries`], are kept unless `value` registers the same prefix.
+    pub fn http(mut self, mut value: HttpConfig) -> Self {
+        for endpoint in std::mem::take(&mut self.http.retry.idempotent_endpoints) {
+            if !value
+                .retry
+                .idempotent_endpoints
+                .iter()
+                .any(|existing| existing.path_prefix == endpoint.path_prefix)
+            {
+                value.retry.idempotent_endpoints.push(endpoint);
+            }
+        }
         self.http = value;
         self
     }
 
+    /// Registers the Zhipu POST endpoints that accept `request_id` as idempotent, keyed by that
+    /// field, plus batch creation keyed by header. File uploads are not covered: multipart
+    /// bodies are sent once and never replayed.
+    pub fn idempotent_retries(mut self) -> Self {
+        let mut retry = self.http.retry;
+        for path in ZHIPU_REQUEST_ID_PATHS {
+            retry = retry.idempotent_endpoint(path, Some("request_id"));
+        }
+        self.http.retry = retry.idempotent_endpoint("batches", None);
+        self
+    }
+
+    /// Serves repeated deterministic chat, embedding, and rerank requests from `value`.
+    pub fn response_cache(mut self, value: ResponseCache) -> Self {
+        self.response_cache = Some(value);
+        self
+    }
+
     pub fn build(self) -> Result<ZhipuClient> {
         ZhipuClient::from_config(self)
     }
@@ -110,6 +183,7 @@ impl ZhipuConfig {
 pub struct ZhipuClient {
     pub(crate) transport: Arc<Transport>,
     pub(crate) agent_transport: Arc<Transport>,
+    pub(crate) cache: Option<ResponseCache>,
 }
 
 impl ZhipuClient {
@@ -119,12 +193,17 @@ impl ZhipuClient {
 
     pub fn from_config(config: ZhipuConfig) -> Result<Self> {
         let authentication = AuthenticationProvider::zhipu(config.authentication)?;
-        let transport =
-            Transport::new(config.base_url, authentication.clone(), config.http.clone())?;
+        let transport = Transport::with_failover(
+            config.base_url,
+            authentication.clone(),
+            config.http.clone(),
+            &config.failover,
+        )?;
         let agent_transport = Transport::new(config.agent_base_url, authentication, config.http)?;
         Ok(Self {
             transport: Arc::new(transport),
             agent_transport: Arc::new(agent_transport),
+            cache: config.response_cache,
         })
     }
 
@@ -140,10 +219,23 @@ impl ZhipuClient {
         &self,
         request: &ChatCompletionRequest,
     ) -> Result<ChatCompletionResponse> {
+        self.chat_completion_with_cache_info(request)
+            .await
+            .map(Cached::into_inner)
+    }
+
+    /// Like [`chat_completion`](Self::chat_completion), also reporting whether the response came
+    /// from the [`ResponseCache`].
+    pub async fn chat_completion_with_cache_info(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<Cached<ChatCompletionResponse>> {
         validate_zhipu_chat(request)?;
         let mut request = request.clone();
         request.stream = false;
-        self.transport.post_json("chat/completions", &request).await
+        let cacheable = is_deterministic(&request);
+        self.cached_post("chat/completions", &request, cacheable)
+            .await
     }
 
     pub async fn chat_completion_stream(
@@ -153,6 +245,16 @@ impl ZhipuClient {
         self.stream_request(request, false).await
     }
 
+    /// Streams a chat completion that resumes from its partial output after a mid-stream
+    /// failure; see [`ResilientChatStream`].
+    pub async fn resilient_chat_completion_stream(
+        &self,
+        request: &ChatCompletionRequest,
+        policy: StreamResumePolicy,
+    ) -> Result<ResilientChatStream> {
+        ResilientChatStream::open(Arc::new(self.clone()), request.clone(), policy).await
+    }
+
     #[cfg(feature = "tools")]
     pub async fn chat_tool_stream(&self, request: &ChatCompletionRequest) -> Result<ToolStream> {
         let stream = self.stream_request(request, true).await?;
@@ -297,15 +399,33 @@ impl ZhipuClient {
     }
 
     pub async fn embedding(&self, request: &EmbeddingRequest) -> Result<EmbeddingResponse> {
-        if request.model.trim().is_empty() {
-            return Err(SdkError::Validation(
-                "embedding model cannot be empty".into(),
-            ));
-        }
-        self.transport.post_json("embeddings", request).await
+        self.embedding_with_cache_info(request)
+            .await
+            .map(Cached::into_inner)
+    }
+
+    /// Like [`embedding`](Self::embedding), also reporting whether the response came from the
+    /// [`ResponseCache`].
+    pub async fn embedding_with_cache_info(
+        &self,
+        request: &EmbeddingRequest,
+    ) -> Result<Cached<EmbeddingResponse>> {
+        validate_embedding(request)?;
+        self.cached_post("embeddings", request, true).await
     }
 
     pub async fn rerank(&self, request: &RerankRequest) -> Result<RerankResponse> {
+        self.rerank_with_cache_info(request)
+            .await
+            .map(Cached::into_inner)
+    }
+
+    /// Like [`rerank`](Self::rerank), also reporting whether the response came from the
+    /// [`ResponseCache`].
+    pub async fn rerank_with_cache_info(
+        &self,
+        request: &RerankRequest,
+    ) -> Result<Cached<RerankResponse>> {
         if request.model.trim().is_empty()
             || request.query.trim().is_empty()
             || request.documents.is_empty()
@@ -314,7 +434,40 @@ impl ZhipuClient {
                 "rerank requires model, query, and at least one document".into(),
             ));
         }
-        self.transport.post_json("rerank", request).await
+        self.cached_post("rerank", request, true).await
+    }
+
+    /// Posts `request`, going through the response cache when one is set and `cacheable` holds.
+    pub(crate) async fn cached_post<T, R>(
+        &self,
+        path: &str,
+        request: &T,
+        cacheable: bool,
+    ) -> Result<Cached<R>>
+    where
+        T: Serialize + ?Sized,
+        R: Serialize + for<'de> Deserialize<'de>,
+    {
+        let Some(cache) = self.cache.as_ref().filter(|_| cacheable) else {
+            let response = self.transport.post_json(path, request).await?;
+            return Ok(Cached {
+                response,
+                cache_hit: None,
+            });
+        };
+        let key = cache_key(path, request)?;
+        if let Some((response, hit)) = cache.get(&key).await {
+            return Ok(Cached {
+                response,
+                cache_hit: Some(hit),
+            });
+        }
+        let response = self.transport.post_json(path, request).await?;
+        cache.put(&key, &response).await;
+        Ok(Cached {
+            response,
+            cache_hit: None,
+        })
     }
 
     pub async fn tokenizer(&self, request: &TokenizerRequest) -> Result<TokenizerResponse> {
@@ -441,16 +594,41 @@ impl ZhipuClient {
         self.transport.post_json("voice/delete", request).await
     }
 
+    /// Untyped form of [`search_web`](Self::search_web); set unmodeled fields through
+    /// `WebSearchRequest::extra` instead.
     #[cfg(feature = "tools")]
+    #[deprecated(note = "use search_web")]
     pub async fn web_search(&self, request: &Value) -> Result<Value> {
         self.transport.post_json("web_search", request).await
     }
 
+    /// Untyped form of [`read_web`](Self::read_web); set unmodeled fields through
+    /// `WebReaderRequest::extra` instead.
     #[cfg(feature = "tools")]
+    #[deprecated(note = "use read_web")]
     pub async fn read_web_page(&self, request: &Value) -> Result<Value> {
         self.transport.post_json("reader", request).await
     }
 
+    #[cfg(feature = "tools")]
+    pub async fn search_web(&self, request: &WebSearchRequest) -> Result<WebSearchResponse> {
+        validate_web_search(request)?;
+        self.transport.post_json("web_search", request).await
+    }
+
+    #[cfg(feature = "tools")]
+    pub async fn read_web(&self, request: &WebReaderRequest) -> Result<WebReaderResponse> {
+        validate_http_url(&request.url, true).map_err(|error| {
+            SdkError::Validation(format!("invalid web reader URL: {error}").into())
+        })?;
+        if request.timeout == Some(0) {
+            return Err(SdkError::Validation(
+                "web reader timeout must be positive".into(),
+            ));
+        }
+        self.transport.post_json("reader", request).await
+    }
+
     #[cfg(feature = "tools")]
     pub async fn moderate(&self, request: &Value) -> Result<Value> {
         self.transport.post_json("moderations", request).await
@@ -470,6 +648,17 @@ impl ZhipuClient {
         self.transport.post_json("layout_parsing", request).await
     }
 
+    /// Typed [`ZhipuClient::parse_layout`]; see [`LayoutParsingResponse::to_layout`].
+    #[cfg(feature = "files")]
+    pub async fn analyze_layout(
+        &self,
+        request: &LayoutParsingRequest,
+    ) -> Result<LayoutParsingResponse> {
+        require_id(&request.model, "layout parsing model")?;
+        require_id(&request.file_url, "layout parsing file url")?;
+        self.transport.post_json("layout_parsing", request).await
+    }
+
     #[cfg(feature = "files")]
     pub async fn upload_file(&self, request: FileUploadRequest) -> Result<FileObject> {
         if request.file.is_empty()
@@ -550,11 +739,45 @@ impl ZhipuClient {
         self.transport.post_json("files/parser/sync", request).await
     }
 
+    /// Starts a parser task that [`ZhipuClient::wait_for`] can poll for its text result.
+    #[cfg(feature = "files")]
+    pub async fn start_file_parse(&self, request: &FileParseRequest) -> Result<FileParseTask> {
+        require_id(&request.file_url, "parser file url")?;
+        let value: Value = self
+            .transport
+            .post_json("files/parser/create", request)
+            .await?;
+        let task_id = value
+            .get("task_id")
+            .and_then(Value::as_str)
+            .filter(|task_id| !task_id.is_empty())
+            .ok_or_else(|| SdkError::Decode {
+                message: "file parser response has no task_id".into(),
+                body: value.to_string(),
+            })?;
+        Ok(FileParseTask::new(task_id, "text"))
+    }
+
+    /// Typed [`ZhipuClient::parse_file_sync`]; see [`ParsedDocument::to_layout`].
+    #[cfg(feature = "files")]
+    pub async fn parse_file(&self, request: &FileParseRequest) -> Result<ParsedDocument> {
+        require_id(&request.file_url, "parser file url")?;
+        self.transport.post_json("files/parser/sync", request).await
+    }
+
     #[cfg(feature = "files")]
     pub async fn ocr(&self, request: &Value) -> Result<Value> {
         self.transport.post_json("files/ocr", request).await
     }
 
+    /// Typed [`ZhipuClient::ocr`]; see [`OcrResponse::to_layout`].
+    #[cfg(feature = "files")]
+    pub async fn recognize_text(&self, request: &OcrRequest) -> Result<OcrResponse> {
+        require_id(&request.file_url, "ocr file url")?;
+        require_id(&request.tool_type, "ocr tool type")?;
+        self.transport.post_json("files/ocr", request).await
+    }
+
     #[cfg(feature = "batch")]
     pub async fn create_batch(&self, request: &BatchCreateRequest) -> Result<BatchObject> {
         if request.input_file_id.trim().is_empty() || request.endpoint.trim().is_empty() {
@@ -602,6 +825,23 @@ impl ZhipuClient {
             .await
     }
 
+    /// Downloads a finished batch's output and error files and parses them by `custom_id`.
+    #[cfg(all(feature = "batch", feature = "files"))]
+    pub async fn batch_results<T: for<'de> Deserialize<'de>>(
+        &self,
+        batch: &BatchObject,
+    ) -> Result<BatchResults<T>> {
+        let mut results = BatchResults::default();
+        for file_id in [&batch.output_file_id, &batch.error_file_id]
+            .into_iter()
+            .flatten()
+            .filter(|id| !id.is_empty())
+        {
+            results.add_file(&self.file_content(file_id).await?)?;
+        }
+        Ok(results)
+    }
+
     #[cfg(feature = "agents")]
     pub async fn assistant(&self, request: &Value) -> Result<Value> {
         self.transport.post_json("assistant", request).await
@@ -719,6 +959,14 @@ impl OpenAiCompatibleClient {
         Ok(sse_stream(response))
     }
 
+    pub async fn resilient_chat_completion_stream(
+        &self,
+        request: &ChatCompletionRequest,
+        policy: StreamResumePolicy,
+    ) -> Result<ResilientChatStream> {
+        ResilientChatStream::open(Arc::new(self.clone()), request.clone(), policy).await
+    }
+
     pub async fn request_json<T, R>(
         &self,
         method: Method,
@@ -771,23 +1019,38 @@ impl ChatProvider for OpenAiCompatibleClient {
     }
 }
 
-fn sse_stream(response: reqwest::Response) -> ChatStream {
+fn sse_stream(response: StreamResponse) -> ChatStream {
     let stream = try_stream! {
         let mut response = response;
         let mut decoder = crate::sse::SseDecoder::<ChatCompletionChunk>::default();
         while let Some(bytes) = response.chunk().await? {
             for chunk in decoder.push(&bytes)? {
+                if let Some(usage) = &chunk.usage {
+                    response.record_usage(usage);
+                }
                 yield chunk;
             }
         }
         for chunk in decoder.finish()? {
+            if let Some(usage) = &chunk.usage {
+                response.record_usage(usage);
+            }
             yield chunk;
         }
     };
     Box::pin(stream)
 }
 
-fn validate_zhipu_chat(request: &ChatCompletionRequest) -> Result<()> {
+pub(crate) fn validate_embedding(request: &EmbeddingRequest) -> Result<()> {
+    if request.model.trim().is_empty() {
+        return Err(SdkError::Validation(
+            "embedding model cannot be empty".into(),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_zhipu_chat(request: &ChatCompletionRequest) -> Result<()> {
     validate_chat(request, 1.0, 0.01)?;
     if request
         .max_tokens
@@ -894,6 +1157,27 @@ fn validate_image(request: &ImageGenerationRequest) -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "tools")]
+fn validate_web_search(request: &WebSearchRequest) -> Result<()> {
+    if request.search_query.trim().is_empty() {
+        return Err(SdkError::Validation(
+            "web search query cannot be empty".into(),
+        ));
+    }
+    if let Some(count) = request.count {
+        validate_search_count(count)?;
+    }
+    if let Some(domain) = &request.search_domain_filter {
+        validate_search_domain(domain)?;
+    }
+    if let Some(recency) = &request.search_recency_filter {
+        validate_search_recency(recency)?;
+    }
+    validate_optional_id(request.request_id.as_deref(), "request_id", 6, 64)?;
+    validate_optional_id(request.user_id.as_deref(), "user_id", 6, 128)?;
+    Ok(())
+}
+
 #[cfg(feature = "tools")]
 fn validate_moderation(request: &ModerationRequest) -> Result<()> {
     if request.model.trim().is_empty() {
@@ -1013,8 +1297,8 @@ mod tests {
 
     use super::*;
     use crate::{
-        ContentPart, FunctionDefinition, Glm52, Glm53, MessageRole, ReasoningEffort, Thinking,
-        Tool, ToolStreamEvent, TypedChatRequest,
+        ContentPart, EmbeddingProvider, FunctionDefinition, Glm52, Glm53, MessageRole,
+        ReasoningEffort, Thinking, Tool, ToolStreamEvent, TypedChatRequest,
     };
 
     struct MockResponse {
@@ -1574,8 +1858,11 @@ mod tests {
         client.clone_voice(&json!({"voice":"x"})).await.unwrap();
         client.voices().await.unwrap();
         client.delete_voice(&json!({"voice":"x"})).await.unwrap();
-        client.web_search(&json!({"query":"x"})).await.unwrap();
-        client.read_web_page(&json!({"url":"x"})).await.unwrap();
+        #[allow(deprecated)]
+        {
+            client.web_search(&json!({"query":"x"})).await.unwrap();
+            client.read_web_page(&json!({"url":"x"})).await.unwrap();
+        }
         client.moderate(&json!({"input":"x"})).await.unwrap();
         client.parse_layout(&json!({"file":"x"})).await.unwrap();
         client
@@ -1868,6 +2155,28 @@ mod tests {
         assert!(!matches!(valid_items, SdkError::Validation(_)));
     }
 
+    #[test]
+    fn idempotent_retries_survive_a_later_http_config() {
+        let mut http = HttpConfig::default();
+        http.retry = http.retry.idempotent_endpoint("batches", Some("custom"));
+        let config = ZhipuConfig::new("key").idempotent_retries().http(http);
+        let endpoints = &config.http.retry.idempotent_endpoints;
+        assert_eq!(endpoints.len(), ZHIPU_REQUEST_ID_PATHS.len() + 1);
+        assert!(
+            endpoints
+                .iter()
+                .any(|endpoint| endpoint.path_prefix == "batches"
+                    && endpoint.key_field.as_deref() == Some("custom"))
+        );
+        let config = ZhipuConfig::new("key")
+            .http(HttpConfig::default())
+            .idempotent_retries();
+        assert_eq!(
+            config.http.retry.idempotent_endpoints.len(),
+            ZHIPU_REQUEST_ID_PATHS.len() + 1
+        );
+    }
+
     #[test]
     fn client_configuration_rejects_invalid_values() {
         assert!(ZhipuClient::new("").is_err());
@@ -1909,4 +2218,390 @@ mod tests {
                 .is_ok()
         );
     }
+
+    #[tokio::test]
+    async fn batch_results_download_output_and_error_files() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::binary(
+                r#"{"custom_id":"a","response":{"status_code":200,"body":{"id":"chat-a","choices":[]}}}"#,
+            ),
+            MockResponse::binary(
+                r#"{"custom_id":"b","response":{"status_code":429,"body":{"error":{"code":"1302","message":"busy"}}}}"#,
+            ),
+        ])
+        .await;
+        let client = ZhipuConfig::new("key").base_url(&base_url).build().unwrap();
+        let batch = BatchObject {
+            output_file_id: Some("out".into()),
+            error_file_id: Some("err".into()),
+            ..BatchObject::default()
+        };
+        let results = client
+            .batch_results::<ChatCompletionResponse>(&batch)
+            .await
+            .unwrap();
+        assert_eq!(results.succeeded().next().unwrap().1.id, "chat-a");
+        assert_eq!(results.failed().next().unwrap().1.status_code, Some(429));
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /files/out/content "));
+        assert!(requests[1].starts_with("GET /files/err/content "));
+    }
+
+    #[tokio::test]
+    async fn batch_job_shards_polls_and_resumes_from_its_manifest() {
+        const OUTPUT: &str = r#"{"custom_id":"a","response":{"status_code":200,"body":{"id":"chat-a","choices":[]}}}"#;
+        let (base_url, server) = mock_server(vec![
+            MockResponse::json(r#"{"id":"file-1"}"#),
+            MockResponse::json(r#"{"id":"batch-1","status":"validating"}"#),
+            MockResponse::json(r#"{"id":"file-2"}"#),
+            MockResponse::json(r#"{"id":"batch-2","status":"validating"}"#),
+            MockResponse::json(r#"{"id":"batch-1","status":"completed","output_file_id":"out-1"}"#),
+            MockResponse::json(r#"{"id":"batch-2","status":"in_progress"}"#),
+            MockResponse::json(r#"{"id":"batch-2","status":"expired"}"#),
+            MockResponse::binary(OUTPUT),
+            MockResponse::binary(OUTPUT),
+        ])
+        .await;
+        let client = ZhipuConfig::new("key").base_url(&base_url).build().unwrap();
+        let directory = crate::test_support::TempDir::new("batch-job");
+        let path = directory.join("manifest.json");
+        let mut job = crate::BatchJob::new()
+            .max_requests(1)
+            .poll_interval(Duration::from_millis(1))
+            .manifest_path(&path);
+        job.push("a", valid_chat()).unwrap();
+        job.push("b", valid_chat()).unwrap();
+        assert!(job.push("a", valid_chat()).is_err());
+        assert_eq!(job.shards().len(), 2);
+
+        let results = job.run::<ChatCompletionResponse>(&client).await.unwrap();
+        assert_eq!(results.get("a").unwrap().as_ref().unwrap().id, "chat-a");
+        let expired = results.get("b").unwrap().as_ref().unwrap_err();
+        assert_eq!(expired.code.as_deref(), Some("expired"));
+
+        let manifest = crate::BatchJobManifest::load(&path).unwrap().unwrap();
+        assert!(manifest.is_finished());
+        assert_eq!(manifest.shards[1].batch_id.as_deref(), Some("batch-2"));
+        let resumed = crate::BatchJob::new().manifest_path(&path);
+        let again = resumed
+            .run::<ChatCompletionResponse>(&client)
+            .await
+            .unwrap();
+        assert_eq!(again, results);
+
+        let requests = server.await.unwrap();
+        let lines = requests
+            .iter()
+            .map(|request| request.lines().next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines[0], "POST /files HTTP/1.1");
+        assert_eq!(lines[3], "POST /batches HTTP/1.1");
+        assert_eq!(lines[6], "GET /batches/batch-2 HTTP/1.1");
+        assert_eq!(lines[8], "GET /files/out-1/content HTTP/1.1");
+        assert!(requests[3].contains(&format!(r#""rustglm_job":"{}""#, manifest.job_id)));
+        assert!(requests[3].contains(r#""rustglm_shard":"1""#));
+    }
+
+    #[tokio::test]
+    async fn batch_job_resume_finds_a_batch_its_manifest_missed() {
+        const OUTPUT: &str = r#"{"custom_id":"a","response":{"status_code":200,"body":{"id":"chat-a","choices":[]}}}"#;
+        let (base_url, server) = mock_server(vec![
+            MockResponse::json(
+                r#"{"data":[{"id":"other","input_file_id":"file-9"}],"last_id":"other","has_more":true}"#,
+            ),
+            MockResponse::json(
+                r#"{"data":[{"id":"batch-1","input_file_id":"file-1","status":"completed","output_file_id":"out-1"}],"has_more":false}"#,
+            ),
+            MockResponse::binary(OUTPUT),
+        ])
+        .await;
+        let client = ZhipuConfig::new("key").base_url(&base_url).build().unwrap();
+        let directory = crate::test_support::TempDir::new("batch-resume");
+        let path = directory.join("manifest.json");
+        crate::BatchJobManifest {
+            job_id: "job-1".into(),
+            endpoint: crate::BATCH_CHAT_ENDPOINT.into(),
+            shards: vec![crate::BatchShard {
+                custom_ids: vec!["a".into()],
+                input_file_id: Some("file-1".into()),
+                ..crate::BatchShard::default()
+            }],
+        }
+        .save(&path)
+        .unwrap();
+        let results = crate::BatchJob::new()
+            .manifest_path(&path)
+            .run::<ChatCompletionResponse>(&client)
+            .await
+            .unwrap();
+        assert_eq!(results.get("a").unwrap().as_ref().unwrap().id, "chat-a");
+        let manifest = crate::BatchJobManifest::load(&path).unwrap().unwrap();
+        assert_eq!(manifest.shards[0].batch_id.as_deref(), Some("batch-1"));
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /batches?limit=100 "));
+        assert!(requests[1].starts_with("GET /batches?limit=100&after=other "));
+        assert!(requests[2].starts_with("GET /files/out-1/content "));
+    }
+
+    #[tokio::test]
+    async fn wait_for_polls_async_results_and_parser_tasks() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::json(r#"{"id":"task-1","task_status":"PROCESSING"}"#),
+            MockResponse::json(
+                r#"{"id":"task-1","task_status":"SUCCESS","model":"glm-5","choices":[{"index":0,"message":{"role":"assistant","content":"done"},"finish_reason":"stop"}]}"#,
+            ),
+            MockResponse::json(r#"{"id":"task-2","task_status":"FAIL"}"#),
+            MockResponse::json(r#"{"task_id":"parse-1","status":"processing"}"#),
+            MockResponse::json(
+                r#"{"task_id":"parse-1","status":"succeeded","content":"text","message":"ok"}"#,
+            ),
+        ])
+        .await;
+        let client = ZhipuConfig::new("key").base_url(&base_url).build().unwrap();
+        let policy = crate::TaskPollPolicy::default().interval(Duration::from_millis(1));
+
+        let chat = client
+            .wait_for(crate::ChatTask("task-1".into()), &policy)
+            .await
+            .unwrap();
+        assert_eq!(chat.text(), Some("done"));
+        assert!(matches!(
+            client.wait_for(crate::ImageTask("task-2".into()), &policy).await,
+            Err(SdkError::Task(crate::TaskError::Failed { task_id, .. })) if task_id == "task-2"
+        ));
+        let document = client
+            .wait_for(crate::FileParseTask::new("parse-1", "text"), &policy)
+            .await
+            .unwrap();
+        assert_eq!(document.content.as_deref(), Some("text"));
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /async-result/task-1 "));
+        assert!(requests[4].starts_with("GET /files/parser/result/parse-1/text "));
+    }
+
+    #[tokio::test]
+    async fn typed_web_search_and_reader_validate_and_decode() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::json(
+                r#"{"id":"s-1","search_intent":[{"query":"rust","intent":"SEARCH_ALL","keywords":"rust"}],"search_result":[{"title":"Rust","link":"https://www.rust-lang.org","content":"A language","media":"rust-lang"}]}"#,
+            ),
+            MockResponse::json(
+                r#"{"id":"r-1","reader_result":{"title":"Rust","url":"https://www.rust-lang.org","content":"A language","metadata":{"keywords":"rust"}}}"#,
+            ),
+        ])
+        .await;
+        let client = ZhipuConfig::new("key").base_url(&base_url).build().unwrap();
+
+        assert!(
+            WebSearchRequest::new("rust", crate::WebSearchEngine::SearchStd)
+                .count(0)
+                .is_err()
+        );
+        for domain in [
+            "https://example.com",
+            "example",
+            "a b.com",
+            "example.com/path",
+        ] {
+            assert!(
+                WebSearchRequest::new("rust", crate::WebSearchEngine::SearchStd)
+                    .domain_filter(domain)
+                    .is_err(),
+                "{domain}"
+            );
+        }
+        assert!(
+            WebSearchRequest::new("rust", crate::WebSearchEngine::SearchStd)
+                .recency(crate::WebSearchRecency::Other("fortnight".into()))
+                .is_err()
+        );
+        assert_validation(
+            client
+                .search_web(&WebSearchRequest::new(
+                    " ",
+                    crate::WebSearchEngine::SearchStd,
+                ))
+                .await,
+            "query",
+        );
+        assert_validation(
+            client
+                .read_web(&WebReaderRequest::new("file:///etc/passwd"))
+                .await,
+            "reader URL",
+        );
+
+        let mut request = WebSearchRequest::new("rust", crate::WebSearchEngine::SearchPro)
+            .count(5)
+            .unwrap()
+            .domain_filter("www.rust-lang.org")
+            .unwrap()
+            .recency(crate::WebSearchRecency::OneWeek)
+            .unwrap();
+        request
+            .extra
+            .insert("location".into(), Value::String("cn".into()));
+        let search = client.search_web(&request).await.unwrap();
+        assert_eq!(search.search_intent[0].intent, "SEARCH_ALL");
+        assert_eq!(
+            search.to_context(),
+            "[1] Rust (https://www.rust-lang.org)\nA language"
+        );
+        let document = search.search_result[0].to_memory_document();
+        assert_eq!(document.id, "https://www.rust-lang.org");
+        assert_eq!(document.metadata["media"], "rust-lang");
+
+        let page = client
+            .read_web(
+                &WebReaderRequest::new("https://www.rust-lang.org")
+                    .return_format(crate::WebReaderFormat::Markdown),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.reader_result.content, "A language");
+        assert_eq!(
+            page.reader_result
+                .metadata
+                .get("keywords")
+                .and_then(Value::as_str),
+            Some("rust")
+        );
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /web_search "));
+        assert!(requests[0].contains(r#""search_domain_filter":"www.rust-lang.org""#));
+        assert!(requests[0].contains(r#""search_recency_filter":"oneWeek""#));
+        assert!(requests[0].contains(r#""location":"cn""#));
+        assert!(requests[1].contains(r#""return_format":"markdown""#));
+    }
+
+    #[tokio::test]
+    async fn typed_document_endpoints_decode_into_layouts() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse::json(
+                r#"{"status":"succeeded","words_result_num":1,"words_result":[{"words":"hello","location":{"left":1,"top":2,"width":30,"height":8},"probability":{"average":0.9,"variance":0.01,"min":0.8}}]}"#,
+            ),
+            MockResponse::json(
+                r##"{"id":"l-1","model":"glm-ocr","md_results":"# Title","layout_details":[[{"index":0,"label":"title","bbox_2d":[0,0,100,20],"content":"Title"}]],"usage":{"prompt_tokens":1,"completion_tokens":2,"total_tokens":3}}"##,
+            ),
+            MockResponse::json(
+                r##"{"task_id":"parse-1","status":"succeeded","content":"# Title\n\nBody"}"##,
+            ),
+            MockResponse::json(r#"{"task_id":"parse-2","message":"ok","success":true}"#),
+        ])
+        .await;
+        let client = ZhipuConfig::new("key").base_url(&base_url).build().unwrap();
+
+        assert_validation(
+            client.recognize_text(&OcrRequest::new(" ")).await,
+            "file url",
+        );
+        assert!(
+            LayoutParsingRequest::new("https://example.com/a.pdf")
+                .pages(3, 2)
+                .is_err()
+        );
+
+        let ocr = client
+            .recognize_text(
+                &OcrRequest::new("https://example.com/note.png")
+                    .language_type("CHN_ENG")
+                    .probability(true),
+            )
+            .await
+            .unwrap();
+        let line = &ocr.to_layout().pages[0].blocks[0].lines[0];
+        assert_eq!(line.text, "hello");
+        assert_eq!(line.bbox.unwrap().right(), 31.0);
+
+        let layout = client
+            .analyze_layout(
+                &LayoutParsingRequest::new("https://example.com/a.pdf")
+                    .pages(1, 2)
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(layout.usage.as_ref().unwrap().total_tokens, 3);
+        assert_eq!(layout.to_layout().to_markdown(), "# Title");
+
+        let request =
+            FileParseRequest::new("https://example.com/a.pdf", crate::FileParserTool::Expert)
+                .file_type("PDF");
+        let parsed = client.parse_file(&request).await.unwrap();
+        assert_eq!(parsed.to_layout().to_text(), "Title\n\nBody");
+        let task = client.start_file_parse(&request).await.unwrap();
+        assert_eq!(
+            (task.task_id.as_str(), task.format_type.as_str()),
+            ("parse-2", "text")
+        );
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /files/ocr "));
+        assert!(requests[0].contains(r#""tool_type":"hand_write""#));
+        assert!(requests[1].contains(r#""model":"glm-ocr""#));
+        assert!(requests[1].contains(r#""end_page_id":2"#));
+        assert!(requests[2].starts_with("POST /files/parser/sync "));
+        assert!(requests[2].contains(r#""tool_type":"expert""#));
+        assert!(requests[3].starts_with("POST /files/parser/create "));
+    }
+
+    #[tokio::test]
+    async fn response_cache_serves_deterministic_repeats_without_the_network() {
+        const CHAT: &str = r#"{"id":"chat-1","model":"glm-test","choices":[{"index":0,"message":{"role":"assistant","content":"cached"},"finish_reason":"stop"}]}"#;
+        let (base_url, server) = mock_server(vec![
+            MockResponse::json(CHAT),
+            MockResponse::json(CHAT),
+            MockResponse::json(CHAT),
+            MockResponse::json(
+                r#"{"model":"embedding-3","data":[{"index":1,"embedding":[0.2]},{"index":0,"embedding":[0.1]}]}"#,
+            ),
+            MockResponse::json(
+                r#"{"model":"embedding-3","data":[{"index":0,"embedding":[0.3]}]}"#,
+            ),
+        ])
+        .await;
+        let client = ZhipuConfig::new("key")
+            .base_url(&base_url)
+            .response_cache(ResponseCache::in_memory(16))
+            .build()
+            .unwrap();
+
+        let greedy = valid_chat().temperature(0.0);
+        let first = client
+            .chat_completion_with_cache_info(&greedy)
+            .await
+            .unwrap();
+        assert!(!first.is_hit());
+        let mut keyed = greedy.clone();
+        keyed.request_id = Some("fresh-id".into());
+        let second = client
+            .chat_completion_with_cache_info(&keyed)
+            .await
+            .unwrap();
+        assert!(second.is_hit());
+        assert_eq!(second.response.text(), Some("cached"));
+        let cache = client.cache.clone().unwrap();
+        assert_eq!(cache.stats(), crate::CacheStats { hits: 1, misses: 1 });
+        client.chat_completion(&valid_chat()).await.unwrap();
+        client.chat_completion(&valid_chat()).await.unwrap();
+
+        let embeddings = crate::ZhipuEmbeddingProvider::new(client, "embedding-3");
+        let texts = ["a".to_owned(), "b".to_owned()];
+        assert_eq!(
+            embeddings.embed(&texts).await.unwrap(),
+            vec![vec![0.1], vec![0.2]]
+        );
+        let mixed = ["b".to_owned(), "c".to_owned(), "a".to_owned()];
+        assert_eq!(
+            embeddings.embed(&mixed).await.unwrap(),
+            vec![vec![0.2], vec![0.3], vec![0.1]]
+        );
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 5);
+        assert!(requests[4].ends_with(r#""input":["c"],"encoding_format":"float"}"#));
+    }
 }
@@ -0,0 +1,1085 @@
+//! Typed OCR, layout parsing, and file parsing, with one document model for all three.
+//!
+//! Each endpoint's response converts into a [`DocumentLayout`]: pages of blocks in reading order,
+//! with bounding boxes, confidence, lines, tables as cells, and image links. The layout renders to
+//! Markdown or plain text and splits into chunks for semantic memory or knowledge bases.
+
+use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize};
+
+#[cfg(feature = "rag")]
+use crate::RagDocumentUpload;
+use crate::{
+    DocumentChunker, ExtraFields, MemoryDocument, ParsedDocument, Result, SdkError, Usage,
+    wire_enum,
+};
+
+pub const GLM_OCR_MODEL: &str = "glm-ocr";
+
+/// An axis-aligned box in page pixels, measured from the top-left corner.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub struct BoundingBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl BoundingBox {
+    pub fn from_corners(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self {
+            x: left.min(right),
+            y: top.min(bottom),
+            width: (right - left).abs(),
+            height: (bottom - top).abs(),
+        }
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    /// The smallest box containing both.
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_corners(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.right().max(other.right()),
+            self.bottom().max(other.bottom()),
+        )
+    }
+}
+
+wire_enum! {
+    /// The kind of a layout block, as labelled by layout parsing.
+    pub enum BlockKind {
+        Text => "text",
+        Title => "title",
+        DocumentTitle => "doc_title",
+        ParagraphTitle => "paragraph_title",
+        Table => "table",
+        Image => "image",
+        Formula => "formula",
+        Header => "header",
+        Footer => "footer",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Word {
+    pub text: String,
+    pub bbox: Option<BoundingBox>,
+    pub confidence: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextLine {
+    pub text: String,
+    pub bbox: Option<BoundingBox>,
+    pub confidence: Option<f32>,
+    pub words: Vec<Word>,
+}
+
+/// A table cell. `row` and `column` are zero-based and point at the cell's top-left slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableCell {
+    pub row: usize,
+    pub column: usize,
+    pub row_span: usize,
+    pub column_span: usize,
+    pub text: String,
+    pub header: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Table {
+    pub rows: usize,
+    pub columns: usize,
+    pub cells: Vec<TableCell>,
+}
+
+impl Table {
+    /// Parses an HTML `<table>` (with `rowspan` and `colspan`) or a Markdown pipe table. Spans
+    /// are clamped to the HTML limits, and an HTML table whose grid would exceed one million
+    /// slots is not parsed.
+    pub fn parse(content: &str) -> Option<Self> {
+        if content.to_ascii_lowercase().contains("<table") {
+            parse_html_table(content)
+        } else {
+            parse_pipe_table(content)
+        }
+    }
+
+    /// Cell text by row and column. A spanned cell's text sits in its top-left slot; the slots
+    /// it covers are empty. Cells outside `rows` and `columns` are skipped.
+    pub fn grid(&self) -> Vec<Vec<String>> {
+        let mut grid = vec![vec![String::new(); self.columns]; self.rows];
+        for cell in &self.cells {
+            if let Some(slot) = grid
+                .get_mut(cell.row)
+                .and_then(|row| row.get_mut(cell.column))
+            {
+                *slot = cell.text.clone();
+            }
+        }
+        grid
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let grid = self.grid();
+        let mut markdown = String::new();
+        for (index, row) in grid.iter().enumerate() {
+            let cells = row
+                .iter()
+                .map(|cell| cell.replace('|', "\\|").replace('\n', " "))
+                .collect::<Vec<_>>();
+            markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+            if index == 0 {
+                markdown.push_str(&format!("|{}\n", " --- |".repeat(self.columns)));
+            }
+        }
+        markdown.truncate(markdown.trim_end().len());
+        markdown
+    }
+
+    fn to_text(&self) -> String {
+        self.grid()
+            .iter()
+            .map(|row| row.join("\t"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// A region of a page. `text` is the block's content as returned, which is Markdown or HTML for
+/// tables and formulas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutBlock {
+    pub kind: BlockKind,
+    /// Position in reading order across the page.
+    pub order: usize,
+    pub bbox: Option<BoundingBox>,
+    pub confidence: Option<f32>,
+    pub text: String,
+    pub lines: Vec<TextLine>,
+    pub table: Option<Table>,
+    pub image_url: Option<String>,
+}
+
+impl LayoutBlock {
+    pub fn new(kind: BlockKind, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let table = if kind == BlockKind::Table {
+            Table::parse(&text)
+        } else {
+            None
+        };
+        let image_url = if kind == BlockKind::Image {
+            image_link(&text)
+        } else {
+            None
+        };
+        Self {
+            kind,
+            order: 0,
+            bbox: None,
+            confidence: None,
+            text,
+            lines: Vec::new(),
+            table,
+            image_url,
+        }
+    }
+
+    /// Page headers and footers, which the renderers leave out.
+    pub fn is_page_furniture(&self) -> bool {
+        matches!(self.kind, BlockKind::Header | BlockKind::Footer)
+    }
+
+    fn to_markdown(&self) -> Option<String> {
+        let text = self.text.trim();
+        let markdown = match &self.kind {
+            BlockKind::Header | BlockKind::Footer => return None,
+            BlockKind::Title | BlockKind::DocumentTitle if !text.starts_with('#') => {
+                format!("# {text}")
+            }
+            BlockKind::ParagraphTitle if !text.starts_with('#') => format!("## {text}"),
+            BlockKind::Table => match &self.table {
+                Some(table) => table.to_markdown(),
+                None => text.to_owned(),
+            },
+            BlockKind::Image => format!("![]({})", self.image_url.as_deref()?),
+            BlockKind::Formula if !text.starts_with('$') => format!("$$\n{text}\n$$"),
+            _ => text.to_owned(),
+        };
+        (!markdown.is_empty()).then_some(markdown)
+    }
+
+    fn to_text(&self) -> Option<String> {
+        let text = match &self.kind {
+            BlockKind::Header | BlockKind::Footer | BlockKind::Image => return None,
+            BlockKind::Table => match &self.table {
+                Some(table) => table.to_text(),
+                None => self.text.trim().to_owned(),
+            },
+            BlockKind::Title | BlockKind::DocumentTitle | BlockKind::ParagraphTitle => self
+                .text
+                .trim()
+                .trim_start_matches('#')
+                .trim_start()
+                .to_owned(),
+            _ => self.text.trim().to_owned(),
+        };
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LayoutPage {
+    /// One-based page number.
+    pub number: usize,
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+    /// Blocks in reading order.
+    pub blocks: Vec<LayoutBlock>,
+}
+
+/// A parsed document: pages of blocks in reading order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DocumentLayout {
+    pub pages: Vec<LayoutPage>,
+}
+
+impl DocumentLayout {
+    /// Splits Markdown (as returned by file parsing) into blocks: headings, pipe or HTML tables,
+    /// `$$` formulas, images, and paragraphs.
+    pub fn from_markdown(markdown: &str) -> Self {
+        let mut blocks = Vec::new();
+        for paragraph in split_paragraphs(markdown) {
+            let kind = if paragraph.starts_with('#') {
+                BlockKind::Title
+            } else if paragraph.starts_with('|')
+                || paragraph.to_ascii_lowercase().starts_with("<table")
+            {
+                BlockKind::Table
+            } else if paragraph.starts_with("$$") {
+                BlockKind::Formula
+            } else if paragraph.starts_with("![") {
+                BlockKind::Image
+            } else {
+                BlockKind::Text
+            };
+            let mut block = LayoutBlock::new(kind, paragraph);
+            block.order = blocks.len();
+            blocks.push(block);
+        }
+        Self {
+            pages: vec![LayoutPage {
+                number: 1,
+                blocks,
+                ..LayoutPage::default()
+            }],
+        }
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &LayoutBlock> {
+        self.pages.iter().flat_map(|page| &page.blocks)
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &Table> {
+        self.blocks().filter_map(|block| block.table.as_ref())
+    }
+
+    /// Markdown in reading order, without page headers and footers.
+    pub fn to_markdown(&self) -> String {
+        self.render(LayoutBlock::to_markdown).0
+    }
+
+    /// Plain text in reading order: heading markers dropped, tables as tab-separated rows, and no
+    /// images, headers, or footers.
+    pub fn to_text(&self) -> String {
+        self.render(LayoutBlock::to_text).0
+    }
+
+    /// The Markdown rendering as one memory document, ready for `SemanticMemory::ingest`.
+    pub fn to_memory_document(&self, id: impl Into<String>) -> MemoryDocument {
+        MemoryDocument::new(id, self.to_markdown()).metadata("pages", self.pages.len().to_string())
+    }
+
+    /// Chunks the Markdown rendering with `chunker`. Chunk ids and metadata match
+    /// `SemanticMemory::ingest` (`{id}#{n}`, `parent_id`, `chunk_index`, `chunk_start`,
+    /// `chunk_end`, `heading`), plus the `page` each chunk starts on.
+    pub fn to_memory_chunks(
+        &self,
+        id: &str,
+        chunker: &DocumentChunker,
+    ) -> Result<Vec<MemoryDocument>> {
+        let (markdown, page_starts) = self.render(LayoutBlock::to_markdown);
+        let chunks = chunker.chunk(&markdown)?;
+        Ok(chunks
+            .into_iter()
+            .map(|chunk| {
+                let page = page_starts
+                    .iter()
+                    .rev()
+                    .find(|(start, _)| *start <= chunk.start)
+                    .map_or(1, |(_, number)| *number);
+                let mut document = MemoryDocument::new(format!("{id}#{}", chunk.index), chunk.text)
+                    .metadata("parent_id", id)
+                    .metadata("chunk_index", chunk.index.to_string())
+                    .metadata("chunk_start", chunk.start.to_string())
+                    .metadata("chunk_end", chunk.end.to_string())
+                    .metadata("page", page.to_string());
+                if let Some(heading) = chunk.heading {
+                    document = document.metadata("heading", heading);
+                }
+                document
+            })
+            .collect())
+    }
+
+    /// The Markdown rendering as a knowledge-base upload named `file_name`.
+    #[cfg(feature = "rag")]
+    pub fn to_rag_upload(&self, file_name: impl Into<String>) -> RagDocumentUpload {
+        let mut upload = RagDocumentUpload::from_bytes(file_name, self.to_markdown());
+        upload.mime_type = Some("text/markdown".into());
+        upload
+    }
+
+    /// Renders every block and records the byte offset at which each page starts.
+    fn render(
+        &self,
+        block: impl Fn(&LayoutBlock) -> Option<String>,
+    ) -> (String, Vec<(usize, usize)>) {
+        let mut output = String::new();
+        let mut page_starts = Vec::new();
+        for page in &self.pages {
+            let mut started = false;
+            for rendered in page.blocks.iter().filter_map(&block) {
+                if !output.is_empty() {
+                    output.push_str("\n\n");
+                }
+                if !started {
+                    page_starts.push((output.len(), page.number));
+                    started = true;
+                }
+                output.push_str(&rendered);
+            }
+        }
+        (output, page_starts)
+    }
+}
+
+/// A handwriting or printed-text OCR request for `files/ocr`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OcrRequest {
+    pub file_url: String,
+    pub tool_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Such as `CHN_ENG`, `ENG`, or `JAP`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_type: Option<String>,
+    /// Ask for per-line confidence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probability: Option<bool>,
+}
+
+impl OcrRequest {
+    /// Handwriting recognition (`tool_type: hand_write`) of the file at `file_url`.
+    pub fn new(file_url: impl Into<String>) -> Self {
+        Self {
+            file_url: file_url.into(),
+            tool_type: "hand_write".into(),
+            model: None,
+            language_type: None,
+            probability: None,
+        }
+    }
+
+    pub fn tool_type(mut self, value: impl Into<String>) -> Self {
+        self.tool_type = value.into();
+        self
+    }
+
+    pub fn model(mut self, value: impl Into<String>) -> Self {
+        self.model = Some(value.into());
+        self
+    }
+
+    pub fn language_type(mut self, value: impl Into<String>) -> Self {
+        self.language_type = Some(value.into());
+        self
+    }
+
+    pub fn probability(mut self, enabled: bool) -> Self {
+        self.probability = Some(enabled);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub struct OcrLocation {
+    #[serde(default)]
+    pub left: f32,
+    #[serde(default)]
+    pub top: f32,
+    #[serde(default)]
+    pub width: f32,
+    #[serde(default)]
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub struct OcrProbability {
+    #[serde(default)]
+    pub average: f32,
+    #[serde(default)]
+    pub variance: f32,
+    #[serde(default)]
+    pub min: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct OcrWordsResult {
+    #[serde(default)]
+    pub words: String,
+    #[serde(default)]
+    pub location: Option<OcrLocation>,
+    #[serde(default)]
+    pub probability: Option<OcrProbability>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct OcrResponse {
+    #[serde(default)]
+    pub task_id: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub words_result_num: Option<u32>,
+    #[serde(default)]
+    pub words_result: Vec<OcrWordsResult>,
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
+impl OcrResponse {
+    /// One page holding one text block, with a line per recognized line.
+    pub fn to_layout(&self) -> DocumentLayout {
+        let lines = self
+            .words_result
+            .iter()
+            .map(|line| TextLine {
+                text: line.words.clone(),
+                bbox: line.location.map(|location| BoundingBox {
+                    x: location.left,
+                    y: location.top,
+                    width: location.width,
+                    height: location.height,
+                }),
+                confidence: line.probability.map(|probability| probability.average),
+                words: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut block = LayoutBlock::new(
+            BlockKind::Text,
+            lines
+                .iter()
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        block.bbox = lines
+            .iter()
+            .filter_map(|line| line.bbox)
+            .reduce(|left, right| left.union(&right));
+        let confidences = lines
+            .iter()
+            .filter_map(|line| line.confidence)
+            .collect::<Vec<_>>();
+        block.confidence = (!confidences.is_empty())
+            .then(|| confidences.iter().sum::<f32>() / confidences.len() as f32);
+        block.lines = lines;
+        DocumentLayout {
+            pages: vec![LayoutPage {
+                number: 1,
+                blocks: vec![block],
+                ..LayoutPage::default()
+            }],
+        }
+    }
+}
+
+/// A GLM-OCR layout parsing request for `layout_parsing`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LayoutParsingRequest {
+    pub model: String,
+    pub file_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_page_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_page_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub return_crop_images: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub need_layout_visualization: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+}
+
+impl LayoutParsingRequest {
+    pub fn new(file_url: impl Into<String>) -> Self {
+        Self {
+            model: GLM_OCR_MODEL.into(),
+            file_url: file_url.into(),
+            start_page_id: None,
+            end_page_id: None,
+            return_crop_images: None,
+            need_layout_visualization: None,
+            request_id: None,
+            user_id: None,
+        }
+    }
+
+    /// Parses pages `start..=end`, counted from 1.
+    pub fn pages(mut self, start: u32, end: u32) -> Result<Self> {
+        if start == 0 || end < start {
+            return Err(SdkError::Validation(
+                "layout parsing pages must satisfy 1 <= start <= end".into(),
+            ));
+        }
+        self.start_page_id = Some(start);
+        self.end_page_id = Some(end);
+        Ok(self)
+    }
+
+    /// Return links to cropped images of image blocks.
+    pub fn return_crop_images(mut self, enabled: bool) -> Self {
+        self.return_crop_images = Some(enabled);
+        self
+    }
+
+    pub fn need_layout_visualization(mut self, enabled: bool) -> Self {
+        self.need_layout_visualization = Some(enabled);
+        self
+    }
+
+    pub fn request_id(mut self, value: impl Into<String>) -> Self {
+        self.request_id = Some(value.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct LayoutDetail {
+    /// Reading order within the page.
+    #[serde(default)]
+    pub index: Option<usize>,
+    #[serde(default)]
+    pub label: Option<BlockKind>,
+    /// `[left, top, right, bottom]`.
+    #[serde(default)]
+    pub bbox_2d: Option<Vec<f32>>,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub score: Option<f32>,
+    #[serde(default)]
+    pub width: Option<f32>,
+    #[serde(default)]
+    pub height: Option<f32>,
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub struct LayoutPageSize {
+    #[serde(default)]
+    pub width: f32,
+    #[serde(default)]
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct LayoutDataInfo {
+    #[serde(default)]
+    pub num_pages: Option<u32>,
+    #[serde(default)]
+    pub pages: Vec<LayoutPageSize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct LayoutParsingResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub created: Option<i64>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// The whole document as Markdown.
+    #[serde(default)]
+    pub md_results: Option<String>,
+    /// Blocks per page.
+    #[serde(default)]
+    pub layout_details: Vec<Vec<LayoutDetail>>,
+    #[serde(default)]
+    pub layout_visualization: Vec<String>,
+    #[serde(default)]
+    pub data_info: Option<LayoutDataInfo>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
+impl LayoutParsingResponse {
+    /// The pages and blocks from `layout_details`, sorted into reading order. Falls back to
+    /// splitting `md_results` when there are no details.
+    pub fn to_layout(&self) -> DocumentLayout {
+        if self.layout_details.is_empty() {
+            return DocumentLayout::from_markdown(self.md_results.as_deref().unwrap_or_default());
+        }
+        let sizes = self
+            .data_info
+            .as_ref()
+            .map(|info| info.pages.as_slice())
+            .unwrap_or_default();
+        let pages = self
+            .layout_details
+            .iter()
+            .enumerate()
+            .map(|(index, details)| {
+                let mut details = details.iter().enumerate().collect::<Vec<_>>();
+                details.sort_by_key(|(position, detail)| detail.index.unwrap_or(*position));
+                let blocks = details
+                    .into_iter()
+                    .enumerate()
+                    .map(|(order, (_, detail))| {
+                        let kind = detail.label.clone().unwrap_or(BlockKind::Text);
+                        let mut block =
+                            LayoutBlock::new(kind, detail.content.clone().unwrap_or_default());
+                        block.order = order;
+                        block.confidence = detail.score;
+                        block.bbox = match detail.bbox_2d.as_deref() {
+                            Some(&[left, top, right, bottom]) => {
+                                Some(BoundingBox::from_corners(left, top, right, bottom))
+                            }
+                            _ => None,
+                        };
+                        block
+                    })
+                    .collect();
+                let size = sizes.get(index);
+                let first = self.layout_details[index].first();
+                LayoutPage {
+                    number: index + 1,
+                    width: size
+                        .map(|size| size.width)
+                        .or_else(|| first.and_then(|detail| detail.width)),
+                    height: size
+                        .map(|size| size.height)
+                        .or_else(|| first.and_then(|detail| detail.height)),
+                    blocks,
+                }
+            })
+            .collect();
+        DocumentLayout { pages }
+    }
+}
+
+wire_enum! {
+    /// File parser service tier.
+    pub enum FileParserTool {
+        Lite => "lite",
+        Expert => "expert",
+        Prime => "prime",
+    }
+}
+
+/// A request for `files/parser/create` or `files/parser/sync`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileParseRequest {
+    pub file_url: String,
+    pub tool_type: FileParserTool,
+    /// Such as `PDF`, `DOCX`, or `PNG`; detected from the file when omitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_type: Option<String>,
+}
+
+impl FileParseRequest {
+    pub fn new(file_url: impl Into<String>, tool_type: FileParserTool) -> Self {
+        Self {
+            file_url: file_url.into(),
+            tool_type,
+            file_type: None,
+        }
+    }
+
+    pub fn file_type(mut self, value: impl Into<String>) -> Self {
+        self.file_type = Some(value.into());
+        self
+    }
+}
+
+impl ParsedDocument {
+    /// The parsed `content`, split into blocks with [`DocumentLayout::from_markdown`].
+    pub fn to_layout(&self) -> DocumentLayout {
+        DocumentLayout::from_markdown(self.content.as_deref().unwrap_or_default())
+    }
+}
+
+/// Paragraphs separated by blank lines, keeping fenced code and `$$` formulas whole.
+fn split_paragraphs(markdown: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current = Vec::new();
+    let mut fence: Option<&str> = None;
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if let Some(marker) = fence {
+            current.push(line);
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(current.join("\n").trim().to_owned());
+                current.clear();
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            fence = Some("```");
+        } else if trimmed == "$$" {
+            fence = Some("$$");
+        }
+        current.push(line);
+    }
+    if !current.is_empty() {
+        paragraphs.push(current.join("\n").trim().to_owned());
+    }
+    paragraphs
+}
+
+/// The first link in `![alt](url)` form, or the text itself when it is a bare URL.
+fn image_link(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.starts_with("http://") || text.starts_with("https://") {
+        return Some(text.to_owned());
+    }
+    let start = text.find("](")? + 2;
+    let end = start + text[start..].find(')')?;
+    let link = text[start..end].trim();
+    (!link.is_empty()).then(|| link.to_owned())
+}
+
+const MAX_ROW_SPAN: usize = 65_534;
+const MAX_COLUMN_SPAN: usize = 1_000;
+const MAX_TABLE_SLOTS: usize = 1_000_000;
+
+fn parse_html_table(content: &str) -> Option<Table> {
+    let lower = content.to_ascii_lowercase();
+    let mut occupied: Vec<Vec<bool>> = Vec::new();
+    let mut columns = 0;
+    let mut cells = Vec::new();
+    let mut row = 0;
+    let mut cursor = 0;
+    while let Some(found) = lower[cursor..].find("<tr") {
+        let row_start = cursor + found + 3;
+        let row_end = lower[row_start..]
+            .find("</tr")
+            .map_or(lower.len(), |end| row_start + end);
+        let mut column = 0;
+        let mut position = row_start;
+        while let Some((tag_start, header)) = next_cell(&lower, position, row_end) {
+            let tag_end = tag_start + lower[tag_start..row_end].find('>')?;
+            let attributes = &lower[tag_start + 3..tag_end];
+            let close = if header { "</th" } else { "</td" };
+            let content_end = lower[tag_end..row_end]
+                .find(close)
+                .map_or(row_end, |end| tag_end + end);
+            let row_span = span(attributes, "rowspan", MAX_ROW_SPAN);
+            let column_span = span(attributes, "colspan", MAX_COLUMN_SPAN);
+            while occupied
+                .get(row)
+                .is_some_and(|slots| slots.get(column) == Some(&true))
+            {
+                column += 1;
+            }
+            let rows = occupied.len().max(row.checked_add(row_span)?);
+            columns = columns.max(column.checked_add(column_span)?);
+            if rows.saturating_mul(columns) > MAX_TABLE_SLOTS {
+                return None;
+            }
+            for slot_row in row..row + row_span {
+                if occupied.len() <= slot_row {
+                    occupied.resize(slot_row + 1, Vec::new());
+                }
+                let slots = &mut occupied[slot_row];
+                if slots.len() < column + column_span {
+                    slots.resize(column + column_span, false);
+                }
+                for slot in &mut slots[column..column + column_span] {
+                    *slot = true;
+                }
+            }
+            cells.push(TableCell {
+                row,
+                column,
+                row_span,
+                column_span,
+                text: strip_tags(&content[tag_end + 1..content_end]),
+                header,
+            });
+            column += column_span;
+            position = content_end;
+        }
+        row += 1;
+        cursor = row_end;
+    }
+    (!cells.is_empty()).then_some(Table {
+        rows: occupied.len(),
+        columns,
+        cells,
+    })
+}
+
+fn next_cell(lower: &str, from: usize, to: usize) -> Option<(usize, bool)> {
+    let slice = &lower[from..to];
+    let data = slice.find("<td");
+    let header = slice.find("<th");
+    match (data, header) {
+        (Some(data), Some(header)) if header < data => Some((from + header, true)),
+        (Some(data), _) => Some((from + data, false)),
+        (None, Some(header)) => Some((from + header, true)),
+        (None, None) => None,
+    }
+}
+
+fn span(attributes: &str, name: &str, max: usize) -> usize {
+    attributes
+        .find(name)
+        .and_then(|start| {
+            let value = attributes[start + name.len()..].trim_start_matches(|character: char| {
+                character == '=' || character == '"' || character == '\'' || character == ' '
+            });
+            let digits = value
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>();
+            (!digits.is_empty()).then(|| digits.parse::<usize>().unwrap_or(usize::MAX))
+        })
+        .map_or(1, |value| value.clamp(1, max))
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for character in html.chars() {
+        match character {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(character),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_pipe_table(content: &str) -> Option<Table> {
+    let rows = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('|'))
+        .filter(|line| {
+            !line
+                .chars()
+                .all(|character| matches!(character, '|' | '-' | ':' | ' '))
+        })
+        .map(|line| {
+            line.trim_matches('|')
+                .split('|')
+                .map(|cell| cell.trim().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    if rows.is_empty() {
+        return None;
+    }
+    let cells = rows
+        .iter()
+        .enumerate()
+        .flat_map(|(row, values)| {
+            values
+                .iter()
+                .enumerate()
+                .map(move |(column, text)| TableCell {
+                    row,
+                    column,
+                    row_span: 1,
+                    column_span: 1,
+                    text: text.clone(),
+                    header: row == 0,
+                })
+        })
+        .collect();
+    Some(Table {
+        rows: rows.len(),
+        columns: rows.iter().map(Vec::len).max().unwrap_or_default(),
+        cells,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChunkStrategy;
+
+    #[test]
+    fn html_tables_keep_spans_and_render_as_markdown() {
+        let table = Table::parse(
+            "<table><tr><th>Name</th><th colspan=\"2\">Score</th></tr>\
+             <tr><td rowspan=2>Ann</td><td>1</td><td>2 &amp; 3</td></tr>\
+             <tr><td>4</td><td>5</td></tr></table>",
+        )
+        .unwrap();
+        assert_eq!((table.rows, table.columns), (3, 3));
+        assert_eq!(table.cells[1].column_span, 2);
+        let last = table.cells.last().unwrap();
+        assert_eq!((last.row, last.column, last.text.as_str()), (2, 2, "5"));
+        assert_eq!(
+            table.to_markdown(),
+            "| Name | Score |  |\n| --- | --- | --- |\n| Ann | 1 | 2 & 3 |\n|  | 4 | 5 |"
+        );
+        let pipe = Table::parse("| a | b |\n|---|---|\n| 1 | 2 |").unwrap();
+        assert_eq!(pipe.grid(), [["a", "b"], ["1", "2"]]);
+    }
+
+    #[test]
+    fn html_table_spans_are_clamped_and_oversized_grids_rejected() {
+        let table =
+            Table::parse("<table><tr><td colspan=99999999999999999999999>a</td></tr></table>")
+                .unwrap();
+        assert_eq!((table.rows, table.columns), (1, 1_000));
+        assert_eq!(table.cells[0].column_span, 1_000);
+        assert!(
+            Table::parse("<table><tr><td rowspan=65534 colspan=1000>a</td></tr></table>").is_none()
+        );
+
+        let hand_built = Table {
+            rows: 1,
+            columns: 1,
+            cells: vec![TableCell {
+                row: 3,
+                column: 0,
+                row_span: 1,
+                column_span: 1,
+                text: "lost".into(),
+                header: false,
+            }],
+        };
+        assert_eq!(hand_built.grid(), [[""]]);
+    }
+
+    #[test]
+    fn layout_response_renders_in_reading_order_and_chunks_by_page() {
+        let response: LayoutParsingResponse = nextjson::from_str(
+            r#"{"id":"l-1","layout_details":[
+                [{"index":1,"label":"text","bbox_2d":[10,40,200,80],"content":"Body text.","score":0.9},
+                 {"index":0,"label":"doc_title","bbox_2d":[10,10,200,30],"content":"Report"},
+                 {"index":2,"label":"footer","content":"page 1"}],
+                [{"index":0,"label":"table","content":"<table><tr><td>a</td><td>b</td></tr></table>"},
+                 {"index":1,"label":"image","content":"![](https://example.com/figure.png)"}]
+            ],"data_info":{"num_pages":2,"pages":[{"width":595,"height":842},{"width":595,"height":842}]}}"#,
+        )
+        .unwrap();
+        let layout = response.to_layout();
+        let first = &layout.pages[0];
+        assert_eq!(first.width, Some(595.0));
+        assert_eq!(first.blocks[0].kind, BlockKind::DocumentTitle);
+        assert_eq!(
+            first.blocks[1].bbox,
+            Some(BoundingBox {
+                x: 10.0,
+                y: 40.0,
+                width: 190.0,
+                height: 40.0
+            })
+        );
+        assert_eq!(
+            layout.pages[1].blocks[1].image_url.as_deref(),
+            Some("https://example.com/figure.png")
+        );
+        assert_eq!(
+            layout.to_markdown(),
+            "# Report\n\nBody text.\n\n| a | b |\n| --- | --- |\n\n![](https://example.com/figure.png)"
+        );
+        assert_eq!(layout.to_text(), "Report\n\nBody text.\n\na\tb");
+
+        let chunker = DocumentChunker::new(ChunkStrategy::Fixed {
+            max_bytes: 24,
+            overlap_bytes: 0,
+        });
+        let chunks = layout.to_memory_chunks("report", &chunker).unwrap();
+        assert_eq!(chunks[0].id, "report#0");
+        assert_eq!(chunks[0].metadata["page"], "1");
+        assert_eq!(chunks.last().unwrap().metadata["page"], "2");
+    }
+
+    #[test]
+    fn ocr_lines_and_parsed_markdown_become_layouts() {
+        let response: OcrResponse = nextjson::from_str(
+            r#"{"status":"succeeded","words_result_num":2,"words_result":[
+                {"words":"hello","location":{"left":0,"top":0,"width":50,"height":10},"probability":{"average":0.8,"variance":0,"min":0.7}},
+                {"words":"world","location":{"left":0,"top":12,"width":60,"height":10},"probability":{"average":0.6,"variance":0,"min":0.5}}]}"#,
+        )
+        .unwrap();
+        let layout = response.to_layout();
+        let block = &layout.pages[0].blocks[0];
+        assert_eq!(block.text, "hello\nworld");
+        assert_eq!(block.bbox.unwrap().bottom(), 22.0);
+        assert!((block.confidence.unwrap() - 0.7).abs() < 1e-6);
+
+        let parsed = ParsedDocument {
+            content: Some(
+                "# Title\n\nText\n\n$$\nx = 1\n\ny = 2\n$$\n\n| k | v |\n|---|---|\n| a | 1 |"
+                    .into(),
+            ),
+            ..ParsedDocument::default()
+        };
+        let kinds = parsed
+            .to_layout()
+            .blocks()
+            .map(|block| block.kind.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                BlockKind::Title,
+                BlockKind::Text,
+                BlockKind::Formula,
+                BlockKind::Table
+            ]
+        );
+        assert_eq!(parsed.to_layout().tables().next().unwrap().rows, 2);
+    }
+}
@@ -1,6 +1,8 @@
 use reqwest::StatusCode;
 use thiserror::Error;
 
+use crate::TaskStatus;
+
 pub type Result<T> = std::result::Result<T, SdkError>;
 
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
@@ -184,6 +186,40 @@ pub enum BatchError {
     MissingCreateFields,
     #[error("invalid batch list limit {0}; expected 1..=100")]
     InvalidLimit(u32),
+    #[error("batch custom_id cannot be empty")]
+    EmptyCustomId,
+    #[error("duplicate batch custom_id {0:?}")]
+    DuplicateCustomId(String),
+    #[error("batch input mixes endpoints {expected} and {found}")]
+    MixedEndpoints {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("batch input exceeds the {0} request limit")]
+    TooManyRequests(usize),
+    #[error("batch input exceeds the {0} byte limit")]
+    InputTooLarge(usize),
+    #[error("batch input is empty")]
+    EmptyInput,
+    #[error("invalid batch result on line {line}: {reason}")]
+    InvalidResultLine { line: usize, reason: String },
+    #[error("batch job manifest does not match the pushed requests")]
+    ManifestMismatch,
+}
+
+/// An asynchronous task that did not produce a result, with the task's last known status.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TaskError {
+    #[error("async task {task_id} ended with status {status}")]
+    Failed {
+        task_id: String,
+        status: TaskStatus,
+        message: Option<String>,
+    },
+    #[error("async task {task_id} was still {status} at the deadline")]
+    DeadlineExceeded { task_id: String, status: TaskStatus },
+    #[error("waiting for async task {task_id} was cancelled while it was {status}")]
+    Cancelled { task_id: String, status: TaskStatus },
 }
 
 #[cfg(feature = "rag")]
@@ -208,6 +244,10 @@ pub enum McpClientError {
     InvalidHeader(String),
     #[error("failed to build MCP HTTP client: {0}")]
     ClientBuild(String),
+    #[error("invalid MCP server command: {0}")]
+    InvalidCommand(String),
+    #[error("failed to spawn MCP server process: {0}")]
+    Spawn(String),
     #[error("MCP initialization failed: {0}")]
     Initialize(String),
     #[error("MCP request failed: {0}")]
@@ -216,6 +256,23 @@ pub enum McpClientError {
     Shutdown(String),
 }
 
+/// A [`UsageLedger`](crate::UsageLedger) budget that was used up before the request was sent.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum BudgetError {
+    #[error("{scope} token budget exhausted: {spent} of {limit} tokens used")]
+    Tokens {
+        scope: String,
+        limit: u64,
+        spent: u64,
+    },
+    #[error("{scope} cost budget exhausted: {spent} of {limit} spent")]
+    Cost {
+        scope: String,
+        limit: f64,
+        spent: f64,
+    },
+}
+
 #[derive(Debug, Error)]
 pub enum SdkError {
     #[error("invalid configuration: {0}")]
@@ -243,6 +300,10 @@ pub enum SdkError {
     Agent(#[from] AgentError),
     #[error("tool execution error: {0}")]
     Tool(#[from] ToolError),
+    #[error("usage budget exceeded: {0}")]
+    Budget(#[from] BudgetError),
+    #[error(transparent)]
+    Task(#[from] TaskError),
     #[cfg(feature = "batch")]
     #[error(transparent)]
     Batch(#[from] BatchError),
@@ -253,3 +314,51 @@ pub enum SdkError {
     #[error(transparent)]
     Mcp(#[from] McpClientError),
 }
+
+impl SdkError {
+    /// Reports a failed local file or socket operation. The SDK has no I/O variant, so these
+    /// surface as configuration errors naming what was being accessed.
+    pub(crate) fn io(target: impl std::fmt::Display, error: std::io::Error) -> Self {
+        Self::Configuration(ConfigurationError::Message(format!(
+            "I/O error on {target}: {error}"
+        )))
+    }
+
+    /// The idempotency key a failed POST under
+    /// [`RetryPolicy::idempotent_endpoints`](crate::RetryPolicy::idempotent_endpoints) was sent
+    /// with. Resending with the same key is safe. API errors carry it at the end of their
+    /// message, transport errors in the fragment of their URL.
+    pub fn idempotency_key(&self) -> Option<&str> {
+        match self {
+            Self::Api(error) => error
+                .message
+                .rsplit_once(IDEMPOTENCY_KEY_NOTE)
+                .and_then(|(_, key)| key.strip_suffix(']')),
+            Self::Transport(error) => error
+                .url()?
+                .fragment()?
+                .strip_prefix(IDEMPOTENCY_KEY_FRAGMENT),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn with_idempotency_key(self, key: &str) -> Self {
+        match self {
+            Self::Api(mut error) => {
+                error.message = format!("{}{IDEMPOTENCY_KEY_NOTE}{key}]", error.message);
+                Self::Api(error)
+            }
+            Self::Transport(error) => match error.url().cloned() {
+                Some(mut url) => {
+                    url.set_fragment(Some(&format!("{IDEMPOTENCY_KEY_FRAGMENT}{key}")));
+                    Self::Transport(error.with_url(url))
+                }
+                None => Self::Transport(error),
+            },
+            error => error,
+        }
+    }
+}
+
+const IDEMPOTENCY_KEY_NOTE: &str = " [idempotency key ";
+const IDEMPOTENCY_KEY_FRAGMENT: &str = "idempotency-key=";
@@ -0,0 +1,381 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::transport::path_has_prefix;
+use crate::{Result, SdkError};
+
+/// When a base URL is taken out of rotation and how it is probed back in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitBreakerConfig {
+    /// Opens the circuit after this many failures in a row.
+    pub consecutive_failures: u32,
+    /// Opens the circuit when the failure share of the last `window` requests reaches this.
+    pub error_rate: f64,
+    pub window: usize,
+    /// How long an open circuit rejects traffic before half-open probing starts.
+    pub cooldown: Duration,
+    /// Successful probes needed to close a half-open circuit.
+    pub half_open_probes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: 5,
+            error_rate: 0.5,
+            window: 20,
+            cooldown: Duration::from_secs(30),
+            half_open_probes: 1,
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    pub fn consecutive_failures(mut self, value: u32) -> Self {
+        self.consecutive_failures = value;
+        self
+    }
+
+    pub fn error_rate(mut self, value: f64, window: usize) -> Self {
+        self.error_rate = value;
+        self.window = window;
+        self
+    }
+
+    pub fn cooldown(mut self, value: Duration) -> Self {
+        self.cooldown = value;
+        self
+    }
+
+    pub fn half_open_probes(mut self, value: u32) -> Self {
+        self.half_open_probes = value;
+        self
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.consecutive_failures == 0 || self.half_open_probes == 0 || self.window == 0 {
+            return Err(SdkError::Configuration(
+                "circuit breaker thresholds, window, and probes must be greater than zero".into(),
+            ));
+        }
+        if !(self.error_rate > 0.0 && self.error_rate <= 1.0) {
+            return Err(SdkError::Configuration(
+                "circuit breaker error_rate must be in (0, 1]".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Fallback base URLs tried in priority order after the primary one.
+///
+/// Requests are sent to the first base URL whose circuit is closed. A request that fails there
+/// with a connection error or 5xx is only re-sent to the next base URL when it is idempotent or
+/// its path is listed in `retry_safe_paths`, since the failed server may already have acted on it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FailoverConfig {
+    pub base_urls: Vec<String>,
+    pub circuit_breaker: CircuitBreakerConfig,
+    pub retry_safe_paths: Vec<String>,
+}
+
+impl FailoverConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.base_urls.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Endpoints {
+    urls: Vec<String>,
+    breakers: Vec<Mutex<Breaker>>,
+    config: CircuitBreakerConfig,
+    retry_safe_paths: Vec<String>,
+}
+
+impl Endpoints {
+    pub(crate) fn single(url: String) -> Arc<Self> {
+        Arc::new(Self {
+            urls: vec![url],
+            breakers: Vec::new(),
+            config: CircuitBreakerConfig::default(),
+            retry_safe_paths: Vec::new(),
+        })
+    }
+
+    pub(crate) fn new(urls: Vec<String>, config: &FailoverConfig) -> Result<Arc<Self>> {
+        config.circuit_breaker.validate()?;
+        Ok(Arc::new(Self {
+            breakers: urls
+                .iter()
+                .map(|_| Mutex::new(Breaker::default()))
+                .collect(),
+            urls,
+            config: config.circuit_breaker.clone(),
+            retry_safe_paths: config
+                .retry_safe_paths
+                .iter()
+                .map(|path| path.trim_matches('/').to_owned())
+                .collect(),
+        }))
+    }
+
+    pub(crate) fn primary(&self) -> &str {
+        &self.urls[0]
+    }
+
+    pub(crate) fn url(&self, index: usize) -> &str {
+        &self.urls[index]
+    }
+
+    pub(crate) fn retry_safe(&self, path: &str) -> bool {
+        self.retry_safe_paths
+            .iter()
+            .any(|prefix| path_has_prefix(path, prefix))
+    }
+
+    /// Picks the highest-priority endpoint not in `tried` whose circuit admits a request. When
+    /// every circuit is open, the untried endpoint that reopens soonest is used rather than
+    /// failing without a request.
+    pub(crate) fn select(&self, tried: &[usize]) -> Option<usize> {
+        if self.breakers.is_empty() {
+            return (!tried.contains(&0)).then_some(0);
+        }
+        let now = Instant::now();
+        let untried = (0..self.urls.len()).filter(|index| !tried.contains(index));
+        let mut soonest: Option<(usize, Instant)> = None;
+        for index in untried {
+            let Ok(mut breaker) = self.breakers[index].lock() else {
+                continue;
+            };
+            match breaker.admit(now, &self.config) {
+                Ok(()) => return Some(index),
+                Err(reopens) => {
+                    if soonest.is_none_or(|(_, current)| reopens < current) {
+                        soonest = Some((index, reopens));
+                    }
+                }
+            }
+        }
+        soonest.map(|(index, _)| index)
+    }
+
+    pub(crate) fn record(&self, index: usize, success: bool) {
+        if let Some(breaker) = self.breakers.get(index)
+            && let Ok(mut breaker) = breaker.lock()
+        {
+            breaker.record(Instant::now(), success, &self.config);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed,
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        probes: u32,
+        successes: u32,
+        since: Instant,
+    },
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: State,
+    consecutive_failures: u32,
+    outcomes: VecDeque<bool>,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self {
+            state: State::Closed,
+            consecutive_failures: 0,
+            outcomes: VecDeque::new(),
+        }
+    }
+}
+
+impl Breaker {
+    /// Admits a request or returns when the circuit may admit one again.
+    fn admit(
+        &mut self,
+        now: Instant,
+        config: &CircuitBreakerConfig,
+    ) -> std::result::Result<(), Instant> {
+        if let State::Open { until } = self.state {
+            if now < until {
+                return Err(until);
+            }
+            self.state = State::HalfOpen {
+                probes: 0,
+                successes: 0,
+                since: now,
+            };
+        }
+        if let State::HalfOpen {
+            probes,
+            successes,
+            since,
+        } = self.state
+        {
+            // Probes abandoned by dropped requests never report back; let new ones through
+            // after another cooldown.
+            let stale = now.saturating_duration_since(since) >= config.cooldown;
+            if probes >= config.half_open_probes && !stale {
+                return Err(since + config.cooldown);
+            }
+            self.state = State::HalfOpen {
+                probes: if stale { 1 } else { probes + 1 },
+                successes,
+                since: if stale { now } else { since },
+            };
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, now: Instant, success: bool, config: &CircuitBreakerConfig) {
+        match self.state {
+            State::HalfOpen { successes, .. } if success => {
+                if successes + 1 >= config.half_open_probes {
+                    *self = Self::default();
+                } else if let State::HalfOpen { successes, .. } = &mut self.state {
+                    *successes += 1;
+                }
+            }
+            State::HalfOpen { .. } => self.open(now, config),
+            // A late result from a request admitted before the circuit opened.
+            State::Open { .. } => {}
+            State::Closed => {
+                self.consecutive_failures = if success {
+                    0
+                } else {
+                    self.consecutive_failures + 1
+                };
+                self.outcomes.push_back(success);
+                if self.outcomes.len() > config.window {
+                    self.outcomes.pop_front();
+                }
+                let failures = self.outcomes.iter().filter(|success| !**success).count();
+                let rate_tripped = self.outcomes.len() == config.window
+                    && failures as f64 >= config.error_rate * config.window as f64;
+                if self.consecutive_failures >= config.consecutive_failures || rate_tripped {
+                    self.open(now, config);
+                }
+            }
+        }
+    }
+
+    fn open(&mut self, now: Instant, config: &CircuitBreakerConfig) {
+        self.state = State::Open {
+            until: now + config.cooldown,
+        };
+        self.consecutive_failures = 0;
+        self.outcomes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig::default()
+            .consecutive_failures(3)
+            .error_rate(0.5, 4)
+            .cooldown(Duration::from_secs(10))
+            .half_open_probes(2)
+    }
+
+    #[test]
+    fn opens_on_consecutive_failures_or_error_rate_and_probes_back() {
+        let config = config();
+        let start = Instant::now();
+        let mut breaker = Breaker::default();
+        for _ in 0..2 {
+            breaker.record(start, false, &config);
+        }
+        assert_eq!(breaker.state, State::Closed);
+        breaker.record(start, false, &config);
+        assert_eq!(
+            breaker.admit(start, &config),
+            Err(start + Duration::from_secs(10))
+        );
+
+        let later = start + Duration::from_secs(10);
+        assert!(breaker.admit(later, &config).is_ok());
+        assert!(breaker.admit(later, &config).is_ok());
+        assert!(breaker.admit(later, &config).is_err());
+        breaker.record(later, true, &config);
+        assert!(matches!(breaker.state, State::HalfOpen { .. }));
+        breaker.record(later, true, &config);
+        assert_eq!(breaker.state, State::Closed);
+
+        for success in [false, true, false, true] {
+            breaker.record(later, success, &config);
+        }
+        assert!(matches!(breaker.state, State::Open { .. }));
+        let reopened = later + Duration::from_secs(10);
+        assert!(breaker.admit(reopened, &config).is_ok());
+        breaker.record(reopened, false, &config);
+        assert!(breaker.admit(reopened, &config).is_err());
+    }
+
+    #[test]
+    fn abandoned_half_open_probes_expire() {
+        let config = config();
+        let start = Instant::now();
+        let mut breaker = Breaker {
+            state: State::Open { until: start },
+            ..Breaker::default()
+        };
+        assert!(breaker.admit(start, &config).is_ok());
+        assert!(breaker.admit(start, &config).is_ok());
+        assert!(breaker.admit(start, &config).is_err());
+        assert!(
+            breaker
+                .admit(start + Duration::from_secs(10), &config)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn selects_by_priority_and_falls_back_to_the_soonest_reopening() {
+        let failover = FailoverConfig {
+            base_urls: vec!["b".into(), "c".into()],
+            circuit_breaker: config().consecutive_failures(1),
+            retry_safe_paths: vec!["/embeddings/".into()],
+        };
+        let endpoints =
+            Endpoints::new(vec!["a".into(), "b".into(), "c".into()], &failover).unwrap();
+        assert_eq!(endpoints.select(&[]), Some(0));
+        endpoints.record(0, false);
+        assert_eq!(endpoints.select(&[]), Some(1));
+        assert_eq!(endpoints.select(&[1]), Some(2));
+        endpoints.record(2, false);
+        endpoints.record(1, false);
+        assert_eq!(endpoints.select(&[]), Some(0));
+        assert_eq!(endpoints.select(&[0, 1, 2]), None);
+        assert!(endpoints.retry_safe("embeddings"));
+        assert!(endpoints.retry_safe("/embeddings?x=1"));
+        assert!(!endpoints.retry_safe("embeddings-v2"));
+
+        let single = Endpoints::single("a".into());
+        assert_eq!(single.select(&[]), Some(0));
+        assert_eq!(single.select(&[0]), None);
+        assert!(
+            Endpoints::new(
+                vec!["a".into()],
+                &FailoverConfig {
+                    circuit_breaker: config().error_rate(1.5, 4),
+                    ..FailoverConfig::default()
+                }
+            )
+            .is_err()
+        );
+    }
+}
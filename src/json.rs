@@ -0,0 +1,18 @@
+use nextjson::{Map, Value};
+
+/// Sorts object keys recursively so bodies match regardless of field order.
+pub(crate) fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries = object.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(key, _)| *key);
+            let mut sorted = Map::with_capacity(entries.len());
+            for (key, value) in entries {
+                sorted.insert(key.to_owned(), canonical(value));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonical).collect()),
+        value => value.clone(),
+    }
+}
@@ -1,47 +1,95 @@
 #[cfg(any(feature = "agents", feature = "rag"))]
 mod agent;
 mod auth;
+#[cfg(feature = "batch")]
+mod batch;
+mod cache;
+mod cassette;
+#[cfg(feature = "files")]
+mod document;
 mod error;
+mod failover;
+mod json;
 #[cfg(feature = "mcp")]
 mod mcp;
 mod memory;
+mod middleware;
 mod model;
 mod provider;
 #[cfg(feature = "rag")]
 mod rag;
+mod rate_limit;
 #[cfg(feature = "realtime")]
 mod realtime;
 mod security;
 mod sse;
-#[cfg(feature = "tools")]
+mod stream_resume;
+mod task;
+mod telemetry;
+#[cfg(test)]
+mod test_support;
+#[cfg(any(feature = "agents", feature = "rag", feature = "tools"))]
 mod tool_stream;
 mod transport;
 mod types;
+mod usage;
 #[cfg(feature = "audio")]
 mod voice;
 mod wire_enum;
 
 pub mod client;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 #[cfg(any(feature = "agents", feature = "rag"))]
 pub use agent::*;
 pub use auth::{JwtAuthentication, ZhipuAuthentication};
+#[cfg(feature = "batch")]
+pub use batch::{
+    BATCH_CHAT_ENDPOINT, BATCH_EMBEDDING_ENDPOINT, BatchInput, BatchItemError, BatchRequest,
+    BatchResults, DEFAULT_BATCH_MAX_BYTES, DEFAULT_BATCH_MAX_REQUESTS,
+};
+#[cfg(all(feature = "batch", feature = "files"))]
+pub use batch::{BatchJob, BatchJobManifest, BatchShard};
 pub use bytes::Bytes;
+pub use cache::{
+    CacheHit, CacheStats, CacheStore, Cached, CachedResponse, DiskCacheStore, MemoryCacheStore,
+    ResponseCache,
+};
+pub use cassette::{Cassette, CassetteMode};
 pub use client::{OpenAiCompatibleClient, OpenAiCompatibleConfig, ZhipuClient, ZhipuConfig};
+#[cfg(feature = "files")]
+pub use document::*;
 pub use error::*;
+pub use failover::{CircuitBreakerConfig, FailoverConfig};
 #[cfg(feature = "mcp")]
 pub use mcp::*;
 pub use memory::*;
+pub use middleware::*;
 pub use model::*;
 pub use nextjson::{NsonDeserialize, NsonSerialize};
 pub use provider::{ChatProvider, ChatStream, ProviderCapabilities};
 #[cfg(feature = "rag")]
 pub use rag::*;
+pub use rate_limit::{RateLimiter, RateLimits};
 #[cfg(feature = "realtime")]
 pub use realtime::*;
-#[cfg(feature = "tools")]
+pub use stream_resume::*;
+#[cfg(feature = "agents")]
+pub use task::AgentTask;
+#[cfg(feature = "images")]
+pub use task::ImageTask;
+#[cfg(feature = "video")]
+pub use task::VideoTask;
+pub use task::{AsyncTask, ChatTask, TaskCancellation, TaskPoll, TaskPollPolicy, TaskStatus};
+#[cfg(feature = "files")]
+pub use task::{FileParseTask, ParsedDocument};
+#[cfg(any(feature = "agents", feature = "rag", feature = "tools"))]
 pub use tool_stream::*;
-pub use transport::{HttpConfig, RetryPolicy};
+pub use transport::{HttpConfig, IdempotentEndpoint, RetryPolicy};
 pub use types::*;
+pub use usage::{
+    ModelPrice, PriceTable, UsageBudget, UsageLedger, UsageRecord, UsageReport, UsageTotals,
+};
 #[cfg(feature = "audio")]
 pub use voice::*;
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -6,12 +6,26 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use async_trait::async_trait;
 use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize};
 
+use crate::cache::cache_key;
+use crate::client::validate_embedding;
 use crate::security::{DEFAULT_MAX_MEMORY_TEXT_BYTES, DEFAULT_VECTOR_STORE_CAPACITY, truncate};
 use crate::{
-    ChatCompletionRequest, ChatCompletionResponse, ChatMessage, ChatProvider, EmbeddingInput,
-    EmbeddingRequest, Result, SdkError, ZhipuClient,
+    ChatCompletionRequest, ChatCompletionResponse, ChatMessage, ChatProvider, EmbeddingData,
+    EmbeddingInput, EmbeddingRequest, EmbeddingResponse, Result, SdkError, UnsupportedError,
+    ZhipuClient,
 };
 
+mod chunking;
+mod compaction;
+mod file_store;
+mod hnsw;
+mod hybrid;
+pub use chunking::*;
+pub use compaction::*;
+pub use file_store::*;
+pub use hnsw::*;
+pub use hybrid::*;
+
 static MEMORY_SEQUENCE: AtomicU64 = AtomicU64::new(1);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -110,6 +124,91 @@ pub struct MemoryMatch {
     pub score: f32,
 }
 
+/// Conditions on [`MemoryDocument::metadata`]. A document matches when every condition holds;
+/// an empty filter matches everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataFilter {
+    conditions: Vec<MetadataCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataCondition {
+    Equals {
+        key: String,
+        value: String,
+    },
+    OneOf {
+        key: String,
+        values: BTreeSet<String>,
+    },
+    Prefix {
+        key: String,
+        prefix: String,
+    },
+}
+
+impl MetadataCondition {
+    pub fn matches(&self, metadata: &BTreeMap<String, String>) -> bool {
+        match self {
+            Self::Equals { key, value } => metadata.get(key) == Some(value),
+            Self::OneOf { key, values } => metadata
+                .get(key)
+                .is_some_and(|value| values.contains(value)),
+            Self::Prefix { key, prefix } => metadata
+                .get(key)
+                .is_some_and(|value| value.starts_with(prefix.as_str())),
+        }
+    }
+}
+
+impl MetadataFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn equals(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.conditions.push(MetadataCondition::Equals {
+            key: key.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn one_of(
+        mut self,
+        key: impl Into<String>,
+        values: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.conditions.push(MetadataCondition::OneOf {
+            key: key.into(),
+            values: values.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+
+    pub fn prefix(mut self, key: impl Into<String>, prefix: impl Into<String>) -> Self {
+        self.conditions.push(MetadataCondition::Prefix {
+            key: key.into(),
+            prefix: prefix.into(),
+        });
+        self
+    }
+
+    pub fn conditions(&self) -> &[MetadataCondition] {
+        &self.conditions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    pub fn matches(&self, document: &MemoryDocument) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(&document.metadata))
+    }
+}
+
 #[async_trait]
 pub trait EmbeddingProvider: Send + Sync {
     async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>>;
@@ -124,6 +223,49 @@ pub trait VectorStore: Send + Sync {
     async fn is_empty(&self) -> Result<bool> {
         Ok(self.len().await? == 0)
     }
+
+    /// Searches only records whose metadata matches `filter`. Stores that predate filtering
+    /// support an empty filter and report anything else as unsupported.
+    async fn search_filtered(
+        &self,
+        query: &[f32],
+        limit: usize,
+        filter: &MetadataFilter,
+    ) -> Result<Vec<MemoryMatch>> {
+        if filter.is_empty() {
+            return self.search(query, limit).await;
+        }
+        Err(unsupported("metadata-filtered vector search"))
+    }
+
+    /// Removes one record by document id, returning whether it existed.
+    async fn delete(&self, _id: &str) -> Result<bool> {
+        Err(unsupported("vector store deletes"))
+    }
+
+    /// Removes every record matching a non-empty `filter` and returns how many were removed.
+    async fn delete_where(&self, _filter: &MetadataFilter) -> Result<usize> {
+        Err(unsupported("vector store deletes"))
+    }
+
+    /// Whether a record with this document id is stored. Hybrid recall uses it to drop
+    /// lexical hits for records the store has already evicted.
+    async fn contains(&self, _id: &str) -> Result<bool> {
+        Err(unsupported("vector store lookups"))
+    }
+}
+
+fn unsupported(capability: &str) -> SdkError {
+    SdkError::Unsupported(UnsupportedError::Capability(capability.to_owned()))
+}
+
+pub(crate) fn validate_delete_filter(filter: &MetadataFilter) -> Result<()> {
+    if filter.is_empty() {
+        return Err(SdkError::Validation(
+            "delete filter cannot be empty; use clear to remove every memory".into(),
+        ));
+    }
+    Ok(())
 }
 
 pub struct InMemoryVectorStore {
@@ -180,11 +322,8 @@ impl InMemoryVectorStore {
             .map_err(|error| SdkError::Configuration(error.to_string().into()))?;
         self.restore(records)
     }
-}
 
-#[async_trait]
-impl VectorStore for InMemoryVectorStore {
-    async fn upsert(&self, memory: StoredMemory) -> Result<()> {
+    pub(crate) fn insert(&self, memory: StoredMemory) -> Result<()> {
         let mut records = self
             .records
             .write()
@@ -203,7 +342,90 @@ impl VectorStore for InMemoryVectorStore {
         Ok(())
     }
 
+    pub(crate) fn record_count(&self) -> Result<usize> {
+        self.records
+            .read()
+            .map(|records| records.len())
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))
+    }
+
+    pub(crate) fn contains_record(&self, id: &str) -> Result<bool> {
+        self.records
+            .read()
+            .map(|records| records.iter().any(|memory| memory.document.id == id))
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))
+    }
+
+    pub(crate) fn matching_ids(&self, filter: &MetadataFilter) -> Result<Vec<String>> {
+        self.records
+            .read()
+            .map(|records| {
+                records
+                    .iter()
+                    .filter(|memory| filter.matches(&memory.document))
+                    .map(|memory| memory.document.id.clone())
+                    .collect()
+            })
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))
+    }
+
+    pub(crate) fn remove(&self, id: &str) -> Result<bool> {
+        let mut records = self
+            .records
+            .write()
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))?;
+        let before = records.len();
+        records.retain(|memory| memory.document.id != id);
+        Ok(records.len() < before)
+    }
+
+    pub(crate) fn remove_matching(&self, filter: &MetadataFilter) -> Result<Vec<String>> {
+        validate_delete_filter(filter)?;
+        let mut records = self
+            .records
+            .write()
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))?;
+        let mut removed = Vec::new();
+        records.retain(|memory| {
+            let matches = filter.matches(&memory.document);
+            if matches {
+                removed.push(memory.document.id.clone());
+            }
+            !matches
+        });
+        Ok(removed)
+    }
+
+    pub(crate) fn remove_all(&self) -> Result<()> {
+        self.records
+            .write()
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))?
+            .clear();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VectorStore for InMemoryVectorStore {
+    async fn upsert(&self, memory: StoredMemory) -> Result<()> {
+        self.insert(memory)
+    }
+
+    async fn contains(&self, id: &str) -> Result<bool> {
+        self.contains_record(id)
+    }
+
     async fn search(&self, query: &[f32], limit: usize) -> Result<Vec<MemoryMatch>> {
+        self.search_filtered(query, limit, &MetadataFilter::default())
+            .await
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &[f32],
+        limit: usize,
+        filter: &MetadataFilter,
+    ) -> Result<Vec<MemoryMatch>> {
         if limit == 0 {
             return Ok(Vec::new());
         }
@@ -213,6 +435,7 @@ impl VectorStore for InMemoryVectorStore {
             .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))?;
         let mut matches = records
             .iter()
+            .filter(|memory| filter.matches(&memory.document))
             .map(|memory| {
                 memory
                     .embedding
@@ -232,18 +455,19 @@ impl VectorStore for InMemoryVectorStore {
     }
 
     async fn clear(&self) -> Result<()> {
-        self.records
-            .write()
-            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))?
-            .clear();
-        Ok(())
+        self.remove_all()
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        self.remove(id)
+    }
+
+    async fn delete_where(&self, filter: &MetadataFilter) -> Result<usize> {
+        Ok(self.remove_matching(filter)?.len())
     }
 
     async fn len(&self) -> Result<usize> {
-        self.records
-            .read()
-            .map(|records| records.len())
-            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))
+        self.record_count()
     }
 }
 
@@ -252,16 +476,53 @@ pub trait ConversationMemory: Send + Sync {
     async fn remember(&self, document: MemoryDocument) -> Result<()>;
     async fn recall(&self, query: &str, limit: usize) -> Result<Vec<MemoryMatch>>;
     async fn clear(&self) -> Result<()>;
+
+    /// Recalls only memories whose metadata matches `filter`, e.g. one tenant or session.
+    async fn recall_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        filter: &MetadataFilter,
+    ) -> Result<Vec<MemoryMatch>> {
+        if filter.is_empty() {
+            return self.recall(query, limit).await;
+        }
+        Err(unsupported("metadata-filtered recall"))
+    }
+
+    async fn forget(&self, _id: &str) -> Result<bool> {
+        Err(unsupported("forgetting individual memories"))
+    }
+
+    async fn forget_where(&self, _filter: &MetadataFilter) -> Result<usize> {
+        Err(unsupported("forgetting individual memories"))
+    }
 }
 
 pub struct SemanticMemory {
     embeddings: Arc<dyn EmbeddingProvider>,
     store: Arc<dyn VectorStore>,
+    hybrid: Option<hybrid::HybridRecall>,
+    reranker: Option<Arc<dyn Reranker>>,
 }
 
 impl SemanticMemory {
     pub fn new(embeddings: Arc<dyn EmbeddingProvider>, store: Arc<dyn VectorStore>) -> Self {
-        Self { embeddings, store }
+        Self {
+            embeddings,
+            store,
+            hybrid: None,
+            reranker: None,
+        }
+    }
+
+    async fn store_memory(&self, memory: StoredMemory) -> Result<()> {
+        let indexed = self.hybrid.as_ref().map(|_| memory.document.clone());
+        self.store.upsert(memory).await?;
+        if let (Some(hybrid), Some(document)) = (&self.hybrid, indexed) {
+            hybrid.index_mut()?.upsert(document);
+        }
+        Ok(())
     }
 }
 
@@ -279,15 +540,32 @@ impl ConversationMemory for SemanticMemory {
             message: "embedding response did not contain a vector".into(),
             body: String::new(),
         })?;
-        self.store
-            .upsert(StoredMemory {
-                document,
-                embedding: QuantizedVector::compress(&vector)?,
-            })
-            .await
+        self.store_memory(StoredMemory {
+            document,
+            embedding: QuantizedVector::compress(&vector)?,
+        })
+        .await
     }
 
     async fn recall(&self, query: &str, limit: usize) -> Result<Vec<MemoryMatch>> {
+        self.recall_filtered(query, limit, &MetadataFilter::default())
+            .await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.store.clear().await?;
+        if let Some(hybrid) = &self.hybrid {
+            hybrid.index_mut()?.clear();
+        }
+        Ok(())
+    }
+
+    async fn recall_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        filter: &MetadataFilter,
+    ) -> Result<Vec<MemoryMatch>> {
         if query.trim().is_empty() || limit == 0 {
             return Ok(Vec::new());
         }
@@ -296,11 +574,23 @@ impl ConversationMemory for SemanticMemory {
             message: "embedding response did not contain a vector".into(),
             body: String::new(),
         })?;
-        self.store.search(vector, limit).await
+        self.rank(query, limit, filter, vector).await
     }
 
-    async fn clear(&self) -> Result<()> {
-        self.store.clear().await
+    async fn forget(&self, id: &str) -> Result<bool> {
+        let removed = self.store.delete(id).await?;
+        if let Some(hybrid) = &self.hybrid {
+            hybrid.index_mut()?.remove(id);
+        }
+        Ok(removed)
+    }
+
+    async fn forget_where(&self, filter: &MetadataFilter) -> Result<usize> {
+        let removed = self.store.delete_where(filter).await?;
+        if let Some(hybrid) = &self.hybrid {
+            hybrid.index_mut()?.remove_where(filter);
+        }
+        Ok(removed)
     }
 }
 
@@ -324,26 +614,27 @@ impl ZhipuEmbeddingProvider {
         self.dimensions = Some(value);
         self
     }
-}
 
-#[async_trait]
-impl EmbeddingProvider for ZhipuEmbeddingProvider {
-    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
-        if inputs.is_empty() {
-            return Ok(Vec::new());
+    fn request(&self, inputs: Vec<String>) -> EmbeddingRequest {
+        EmbeddingRequest {
+            model: self.model.clone(),
+            input: EmbeddingInput::Texts(inputs),
+            dimensions: self.dimensions,
+            encoding_format: Some("float".into()),
+            user_id: None,
+            request_id: None,
+            extra: Default::default(),
         }
-        let response = self
+    }
+
+    async fn fetch(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = self.request(inputs.to_vec());
+        validate_embedding(&request)?;
+        let response: EmbeddingResponse = self
             .client
-            .embedding(&EmbeddingRequest {
-                model: self.model.clone(),
-                input: EmbeddingInput::Texts(inputs.to_vec()),
-                dimensions: self.dimensions,
-                encoding_format: Some("float".into()),
-                user_id: None,
-                request_id: None,
-                extra: Default::default(),
-            })
-            .await?;
+            .cached_post("embeddings", &request, false)
+            .await?
+            .response;
         let mut data = response.data;
         data.sort_by_key(|item| item.index);
         let vectors = data
@@ -360,14 +651,66 @@ impl EmbeddingProvider for ZhipuEmbeddingProvider {
     }
 }
 
+#[async_trait]
+impl EmbeddingProvider for ZhipuEmbeddingProvider {
+    /// With a response cache on the client, each input is cached on its own, so a document
+    /// embedded once is not sent again whatever batch it later arrives in.
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Some(cache) = &self.client.cache else {
+            return self.fetch(inputs).await;
+        };
+        let mut vectors = Vec::with_capacity(inputs.len());
+        let mut misses = Vec::new();
+        for (index, input) in inputs.iter().enumerate() {
+            let key = cache_key("embeddings", &self.request(vec![input.clone()]))?;
+            let cached = cache
+                .get::<EmbeddingResponse>(&key)
+                .await
+                .and_then(|(response, _)| response.data.into_iter().next())
+                .map(|data| data.embedding)
+                .filter(|vector| !vector.is_empty());
+            if cached.is_none() {
+                misses.push((index, key));
+            }
+            vectors.push(cached);
+        }
+        if !misses.is_empty() {
+            let texts = misses
+                .iter()
+                .map(|(index, _)| inputs[*index].clone())
+                .collect::<Vec<_>>();
+            for ((index, key), embedding) in misses.into_iter().zip(self.fetch(&texts).await?) {
+                let response = EmbeddingResponse {
+                    model: self.model.clone(),
+                    object: "list".into(),
+                    data: vec![EmbeddingData {
+                        object: Some("embedding".into()),
+                        index: 0,
+                        embedding: embedding.clone(),
+                    }],
+                    ..Default::default()
+                };
+                cache.put(&key, &response).await;
+                vectors[index] = Some(embedding);
+            }
+        }
+        Ok(vectors.into_iter().flatten().collect())
+    }
+}
+
 #[derive(Clone)]
 pub struct ConversationConfig {
     pub model: String,
     pub system_prompt: Option<String>,
     pub retain_history: bool,
     pub max_history_messages: usize,
+    pub history_budget: Option<HistoryBudget>,
     pub memory: Option<Arc<dyn ConversationMemory>>,
     pub recall_limit: usize,
+    pub memory_scope: BTreeMap<String, String>,
 }
 
 impl ConversationConfig {
@@ -377,8 +720,10 @@ impl ConversationConfig {
             system_prompt: None,
             retain_history: false,
             max_history_messages: 20,
+            history_budget: None,
             memory: None,
             recall_limit: 4,
+            memory_scope: BTreeMap::new(),
         }
     }
 
@@ -397,6 +742,12 @@ impl ConversationConfig {
         self
     }
 
+    /// Also keeps retained history under a token budget, on top of the message-count limit.
+    pub fn history_budget(mut self, value: HistoryBudget) -> Self {
+        self.history_budget = Some(value);
+        self
+    }
+
     pub fn semantic_memory(
         mut self,
         memory: Arc<dyn ConversationMemory>,
@@ -406,6 +757,20 @@ impl ConversationConfig {
         self.recall_limit = recall_limit;
         self
     }
+
+    /// Tags remembered turns with `key = value` and recalls only memories in the same scope.
+    pub fn memory_scope(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.memory_scope.insert(key.into(), value.into());
+        self
+    }
+
+    pub(crate) fn memory_filter(&self) -> MetadataFilter {
+        self.memory_scope
+            .iter()
+            .fold(MetadataFilter::new(), |filter, (key, value)| {
+                filter.equals(key, value)
+            })
+    }
 }
 
 pub(crate) fn memory_context_message(
@@ -444,6 +809,9 @@ impl Conversation {
                 "history limit must be greater than zero".into(),
             ));
         }
+        if let Some(budget) = &config.history_budget {
+            budget.validate()?;
+        }
         if config.memory.is_some() && config.recall_limit == 0 {
             return Err(SdkError::Configuration(
                 "semantic recall limit must be greater than zero".into(),
@@ -466,7 +834,11 @@ impl Conversation {
 
     pub async fn clear_memory(&self) -> Result<()> {
         if let Some(memory) = &self.config.memory {
-            memory.clear().await?;
+            if self.config.memory_scope.is_empty() {
+                memory.clear().await?;
+            } else {
+                memory.forget_where(&self.config.memory_filter()).await?;
+            }
         }
         Ok(())
     }
@@ -483,7 +855,13 @@ impl Conversation {
             messages.push(ChatMessage::system(system_prompt));
         }
         if let Some(memory) = &self.config.memory {
-            let recalled = memory.recall(&input, self.config.recall_limit).await?;
+            let recalled = memory
+                .recall_filtered(
+                    &input,
+                    self.config.recall_limit,
+                    &self.config.memory_filter(),
+                )
+                .await?;
             if let Some(message) = memory_context_message(recalled, DEFAULT_MAX_MEMORY_TEXT_BYTES)?
             {
                 messages.push(message);
@@ -512,17 +890,24 @@ impl Conversation {
                 if overflow > 0 {
                     self.history.drain(..overflow);
                 }
+                if let Some(budget) = &self.config.history_budget {
+                    // The turn already succeeded; if the budget cannot be measured, the message
+                    // limit above still bounds the history.
+                    let _ = budget
+                        .compact(
+                            &mut self.history,
+                            self.provider.as_ref(),
+                            &self.config.model,
+                        )
+                        .await;
+                }
             }
             if let Some(memory) = &self.config.memory {
-                memory
-                    .remember(
-                        MemoryDocument::new(
-                            memory_id()?,
-                            format!("User: {input}\nAssistant: {text}"),
-                        )
-                        .metadata("source", "conversation"),
-                    )
-                    .await?;
+                let mut document =
+                    MemoryDocument::new(memory_id()?, format!("User: {input}\nAssistant: {text}"))
+                        .metadata("source", "conversation");
+                document.metadata.extend(self.config.memory_scope.clone());
+                memory.remember(document).await?;
             }
         }
         Ok(response)
@@ -685,6 +1070,94 @@ mod tests {
         assert!(store.is_empty().await.unwrap());
     }
 
+    #[tokio::test]
+    async fn filters_and_deletes_memories_by_metadata() {
+        let filter = MetadataFilter::new()
+            .equals("tenant", "acme")
+            .one_of("kind", ["note", "ticket"])
+            .prefix("session", "2026-");
+        let document = MemoryDocument::new("id", "text")
+            .metadata("tenant", "acme")
+            .metadata("kind", "ticket")
+            .metadata("session", "2026-10-01");
+        assert!(filter.matches(&document));
+        assert!(!filter.matches(&document.clone().metadata("kind", "chat")));
+        assert!(!filter.matches(&MemoryDocument::new("bare", "text")));
+        assert!(MetadataFilter::new().matches(&MemoryDocument::new("bare", "text")));
+        assert_eq!(filter.conditions().len(), 3);
+
+        let store = Arc::new(InMemoryVectorStore::new());
+        let memory = SemanticMemory::new(Arc::new(FakeEmbeddings), store.clone());
+        for (id, tenant) in [("a1", "acme"), ("a2", "acme"), ("b1", "beta")] {
+            memory
+                .remember(MemoryDocument::new(id, format!("Rust {id}")).metadata("tenant", tenant))
+                .await
+                .unwrap();
+        }
+        let beta = MetadataFilter::new().equals("tenant", "beta");
+        let recalled = memory.recall_filtered("Rust", 5, &beta).await.unwrap();
+        assert_eq!(recalled.len(), 1);
+        assert_eq!(recalled[0].document.id, "b1");
+        assert_eq!(memory.recall("Rust", 5).await.unwrap().len(), 3);
+
+        assert!(memory.forget("a1").await.unwrap());
+        assert!(!memory.forget("a1").await.unwrap());
+        assert!(matches!(
+            memory.forget_where(&MetadataFilter::new()).await,
+            Err(SdkError::Validation(_))
+        ));
+        assert_eq!(memory.forget_where(&beta).await.unwrap(), 1);
+        let ids = store
+            .snapshot()
+            .unwrap()
+            .into_iter()
+            .map(|memory| memory.document.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a2"]);
+    }
+
+    #[tokio::test]
+    async fn stores_without_filter_support_report_unsupported() {
+        struct LegacyStore;
+
+        #[async_trait]
+        impl VectorStore for LegacyStore {
+            async fn upsert(&self, _: StoredMemory) -> Result<()> {
+                Ok(())
+            }
+
+            async fn search(&self, _: &[f32], _: usize) -> Result<Vec<MemoryMatch>> {
+                Ok(Vec::new())
+            }
+
+            async fn clear(&self) -> Result<()> {
+                Ok(())
+            }
+
+            async fn len(&self) -> Result<usize> {
+                Ok(0)
+            }
+        }
+
+        let store = LegacyStore;
+        let filter = MetadataFilter::new().equals("tenant", "acme");
+        assert!(
+            store
+                .search_filtered(&[1.0], 1, &MetadataFilter::new())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            store.search_filtered(&[1.0], 1, &filter).await,
+            Err(SdkError::Unsupported(_))
+        ));
+        assert!(matches!(
+            store.delete("id").await,
+            Err(SdkError::Unsupported(_))
+        ));
+    }
+
     #[tokio::test]
     async fn bounded_store_evicts_oldest_records_when_full() {
         let store = InMemoryVectorStore::with_capacity(2);
@@ -786,6 +1259,59 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn conversation_memory_scope_separates_tenants() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let provider = Arc::new(FakeProvider {
+            requests: requests.clone(),
+        });
+        let store = Arc::new(InMemoryVectorStore::new());
+        let memory = Arc::new(SemanticMemory::new(Arc::new(FakeEmbeddings), store.clone()));
+        let mut alice = Conversation::new(
+            provider.clone(),
+            ConversationConfig::new("model")
+                .semantic_memory(memory.clone(), 2)
+                .memory_scope("user", "alice"),
+        )
+        .unwrap();
+        let mut bob = Conversation::new(
+            provider,
+            ConversationConfig::new("model")
+                .semantic_memory(memory, 2)
+                .memory_scope("user", "bob"),
+        )
+        .unwrap();
+        alice.send("Rust secret").await.unwrap();
+        bob.send("Rust question").await.unwrap();
+        assert_eq!(requests.lock().unwrap()[1].messages.len(), 1);
+        assert_eq!(
+            store.snapshot().unwrap()[0].document.metadata["user"],
+            "alice"
+        );
+        bob.clear_memory().await.unwrap();
+        assert_eq!(store.len().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn conversation_history_budget_drops_oldest_turns() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let provider = Arc::new(FakeProvider {
+            requests: requests.clone(),
+        });
+        let mut conversation = Conversation::new(
+            provider,
+            ConversationConfig::new("model")
+                .retain_history(true)
+                .history_budget(HistoryBudget::new(30)),
+        )
+        .unwrap();
+        conversation.send("first").await.unwrap();
+        conversation.send("second").await.unwrap();
+        assert_eq!(conversation.history().len(), 2);
+        assert_eq!(conversation.history()[0], ChatMessage::user("second"));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
     #[test]
     fn conversation_rejects_invalid_configuration() {
         let provider = Arc::new(FakeProvider {
@@ -807,11 +1333,18 @@ mod tests {
         ));
         assert!(
             Conversation::new(
-                provider,
+                provider.clone(),
                 ConversationConfig::new("model").semantic_memory(memory, 0)
             )
             .is_err()
         );
+        assert!(
+            Conversation::new(
+                provider,
+                ConversationConfig::new("model").history_budget(HistoryBudget::new(0))
+            )
+            .is_err()
+        );
     }
 
     #[tokio::test]
@@ -0,0 +1,550 @@
+use super::{ConversationMemory, MemoryDocument, QuantizedVector, SemanticMemory};
+use crate::security::DEFAULT_MAX_MEMORY_TEXT_BYTES;
+use crate::{Result, SdkError, StoredMemory};
+
+const DEFAULT_CHUNK_BYTES: usize = 2 * 1024;
+const DEFAULT_EMBEDDING_BATCH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    /// Windows of at most `max_bytes`, each starting `overlap_bytes` before the previous end.
+    /// Breaks prefer line boundaries, which keeps code readable.
+    Fixed {
+        max_bytes: usize,
+        overlap_bytes: usize,
+    },
+    /// One chunk per Markdown section. Headings inside fenced code blocks are ignored, and
+    /// oversized sections fall back to fixed windows.
+    Headings { max_bytes: usize },
+    /// Packs whole sentences up to `max_bytes`, repeating the last `overlap_sentences` sentences
+    /// at the start of the next chunk.
+    Sentences {
+        max_bytes: usize,
+        overlap_sentences: usize,
+    },
+}
+
+impl ChunkStrategy {
+    fn max_bytes(&self) -> usize {
+        match self {
+            Self::Fixed { max_bytes, .. }
+            | Self::Headings { max_bytes }
+            | Self::Sentences { max_bytes, .. } => *max_bytes,
+        }
+    }
+}
+
+/// A slice of a source document. `start` and `end` are byte offsets into the source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentChunk {
+    pub index: usize,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    /// Enclosing Markdown headings joined with ` > `, for [`ChunkStrategy::Headings`].
+    pub heading: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentChunker {
+    pub strategy: ChunkStrategy,
+    pub embedding_batch_size: usize,
+}
+
+impl Default for DocumentChunker {
+    fn default() -> Self {
+        Self::new(ChunkStrategy::Sentences {
+            max_bytes: DEFAULT_CHUNK_BYTES,
+            overlap_sentences: 1,
+        })
+    }
+}
+
+impl DocumentChunker {
+    pub fn new(strategy: ChunkStrategy) -> Self {
+        Self {
+            strategy,
+            embedding_batch_size: DEFAULT_EMBEDDING_BATCH,
+        }
+    }
+
+    /// Chunks sent per [`EmbeddingProvider::embed`](super::EmbeddingProvider::embed) call
+    /// during ingestion.
+    pub fn embedding_batch_size(mut self, value: usize) -> Self {
+        self.embedding_batch_size = value;
+        self
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let max_bytes = self.strategy.max_bytes();
+        if max_bytes == 0 || max_bytes > DEFAULT_MAX_MEMORY_TEXT_BYTES {
+            return Err(SdkError::Configuration(
+                format!("chunk size must be between 1 and {DEFAULT_MAX_MEMORY_TEXT_BYTES} bytes")
+                    .into(),
+            ));
+        }
+        if let ChunkStrategy::Fixed { overlap_bytes, .. } = self.strategy
+            && overlap_bytes >= max_bytes
+        {
+            return Err(SdkError::Configuration(
+                "chunk overlap must be smaller than the chunk size".into(),
+            ));
+        }
+        if self.embedding_batch_size == 0 {
+            return Err(SdkError::Configuration(
+                "embedding batch size must be greater than zero".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn chunk(&self, text: &str) -> Result<Vec<DocumentChunk>> {
+        self.validate()?;
+        let ranges = match self.strategy {
+            ChunkStrategy::Fixed {
+                max_bytes,
+                overlap_bytes,
+            } => fixed_ranges(text, 0, text.len(), max_bytes, overlap_bytes)
+                .into_iter()
+                .map(|(start, end)| (start, end, None))
+                .collect(),
+            ChunkStrategy::Headings { max_bytes } => heading_ranges(text, max_bytes),
+            ChunkStrategy::Sentences {
+                max_bytes,
+                overlap_sentences,
+            } => sentence_ranges(text, max_bytes, overlap_sentences)
+                .into_iter()
+                .map(|(start, end)| (start, end, None))
+                .collect(),
+        };
+        let mut chunks = Vec::new();
+        for (start, end, heading) in ranges {
+            let slice = &text[start..end];
+            let trimmed_start = start + (slice.len() - slice.trim_start().len());
+            let trimmed_end = end - (slice.len() - slice.trim_end().len());
+            if trimmed_start >= trimmed_end {
+                continue;
+            }
+            chunks.push(DocumentChunk {
+                index: chunks.len(),
+                text: text[trimmed_start..trimmed_end].to_owned(),
+                start: trimmed_start,
+                end: trimmed_end,
+                heading,
+            });
+        }
+        Ok(chunks)
+    }
+}
+
+impl SemanticMemory {
+    /// Splits `document` into chunks and stores each one as its own memory, embedding them in
+    /// batches. Chunk ids are `{document.id}#{index}`; every chunk keeps the parent metadata
+    /// plus `parent_id`, `chunk_index`, `chunk_start`, `chunk_end` and, when known, `heading`.
+    /// New chunks are stored first; chunks left over from an earlier, longer version of the
+    /// document are then forgotten when the store supports deletes.
+    pub async fn ingest(
+        &self,
+        document: MemoryDocument,
+        chunker: &DocumentChunker,
+    ) -> Result<Vec<String>> {
+        if document.id.trim().is_empty() {
+            return Err(SdkError::Validation("memory id cannot be empty".into()));
+        }
+        let chunks = chunker.chunk(&document.text)?;
+        let documents = chunks
+            .into_iter()
+            .map(|chunk| {
+                let mut chunk_document =
+                    MemoryDocument::new(format!("{}#{}", document.id, chunk.index), chunk.text);
+                chunk_document.metadata = document.metadata.clone();
+                chunk_document = chunk_document
+                    .metadata("parent_id", &document.id)
+                    .metadata("chunk_index", chunk.index.to_string())
+                    .metadata("chunk_start", chunk.start.to_string())
+                    .metadata("chunk_end", chunk.end.to_string());
+                if let Some(heading) = chunk.heading {
+                    chunk_document = chunk_document.metadata("heading", heading);
+                }
+                chunk_document
+            })
+            .collect::<Vec<_>>();
+        let mut ids = Vec::with_capacity(documents.len());
+        for batch in documents.chunks(chunker.embedding_batch_size) {
+            let texts = batch
+                .iter()
+                .map(|document| document.text.clone())
+                .collect::<Vec<_>>();
+            let vectors = self.embeddings.embed(&texts).await?;
+            if vectors.len() != batch.len() {
+                return Err(SdkError::Decode {
+                    message: "embedding response count does not match chunk count".into(),
+                    body: String::new(),
+                });
+            }
+            for (document, vector) in batch.iter().zip(vectors) {
+                ids.push(document.id.clone());
+                self.store_memory(StoredMemory {
+                    document: document.clone(),
+                    embedding: QuantizedVector::compress(&vector)?,
+                })
+                .await?;
+            }
+        }
+        for index in ids.len().. {
+            match self.forget(&format!("{}#{index}", document.id)).await {
+                Ok(true) => {}
+                Ok(false) | Err(SdkError::Unsupported(_)) => break,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(ids)
+    }
+}
+
+fn floor_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+fn fixed_ranges(
+    text: &str,
+    start: usize,
+    end: usize,
+    max_bytes: usize,
+    overlap_bytes: usize,
+) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut cursor = start;
+    while cursor < end {
+        let mut stop = floor_boundary(text, (cursor + max_bytes).min(end));
+        if stop < end {
+            let window = &text[cursor..stop];
+            let half = window.len() / 2;
+            if let Some(line) = window.rfind('\n').filter(|line| *line >= half) {
+                stop = cursor + line + 1;
+            } else if let Some(space) = window
+                .rfind(char::is_whitespace)
+                .filter(|space| *space >= half)
+            {
+                stop = cursor + space + 1;
+            }
+        }
+        if stop <= cursor {
+            stop = ceil_boundary(text, cursor + 1);
+        }
+        ranges.push((cursor, stop));
+        if stop >= end {
+            break;
+        }
+        let next = ceil_boundary(text, stop.saturating_sub(overlap_bytes));
+        cursor = if next > cursor { next } else { stop };
+    }
+    ranges
+}
+
+fn sentence_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut characters = text.char_indices().peekable();
+    while let Some((index, character)) = characters.next() {
+        let next = characters.peek().map(|(_, next)| *next);
+        let boundary = match character {
+            '。' | '！' | '？' | '；' => true,
+            '.' | '!' | '?' => next.is_none_or(char::is_whitespace),
+            '\n' => next == Some('\n'),
+            _ => false,
+        };
+        if boundary {
+            let end = index + character.len_utf8();
+            spans.push((start, end));
+            start = end;
+        }
+    }
+    if start < text.len() {
+        spans.push((start, text.len()));
+    }
+    spans
+}
+
+fn sentence_ranges(text: &str, max_bytes: usize, overlap_sentences: usize) -> Vec<(usize, usize)> {
+    let spans = sentence_spans(text);
+    let mut ranges = Vec::new();
+    let mut first = 0;
+    while first < spans.len() {
+        let start = spans[first].0;
+        if spans[first].1 - start > max_bytes {
+            ranges.extend(fixed_ranges(text, start, spans[first].1, max_bytes, 0));
+            first += 1;
+            continue;
+        }
+        let mut last = first;
+        while last + 1 < spans.len() && spans[last + 1].1 - start <= max_bytes {
+            last += 1;
+        }
+        ranges.push((start, spans[last].1));
+        if last + 1 >= spans.len() {
+            break;
+        }
+        let overlap_start = (last + 1).saturating_sub(overlap_sentences);
+        first = if overlap_start > first {
+            overlap_start
+        } else {
+            last + 1
+        };
+    }
+    ranges
+}
+
+fn heading_ranges(text: &str, max_bytes: usize) -> Vec<(usize, usize, Option<String>)> {
+    let mut sections = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut section_start = 0;
+    let mut section_heading = None;
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        } else if let Some((level, title)) = atx_heading(line) {
+            if offset > section_start {
+                sections.push((section_start, offset, section_heading.clone()));
+            }
+            headings.retain(|(existing, _)| *existing < level);
+            headings.push((level, title));
+            section_start = offset;
+            section_heading = Some(
+                headings
+                    .iter()
+                    .map(|(_, title)| title.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" > "),
+            );
+        }
+        offset += line.len();
+    }
+    if text.len() > section_start {
+        sections.push((section_start, text.len(), section_heading));
+    }
+    let mut ranges = Vec::new();
+    for (start, end, heading) in sections {
+        if end - start <= max_bytes {
+            ranges.push((start, end, heading));
+        } else {
+            ranges.extend(
+                fixed_ranges(text, start, end, max_bytes, 0)
+                    .into_iter()
+                    .map(|(start, end)| (start, end, heading.clone())),
+            );
+        }
+    }
+    ranges
+}
+
+fn atx_heading(line: &str) -> Option<(usize, String)> {
+    let line = line.trim_end();
+    let hashes = line.bytes().take_while(|byte| *byte == b'#').count();
+    if !(1..=6).contains(&hashes) {
+        return None;
+    }
+    let rest = &line[hashes..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches('#').trim_end();
+    (!title.is_empty()).then(|| (hashes, title.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{EmbeddingProvider, InMemoryVectorStore, VectorStore};
+
+    #[derive(Default)]
+    struct CountingEmbeddings {
+        calls: AtomicUsize,
+        largest_batch: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for CountingEmbeddings {
+        async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut largest = self.largest_batch.lock().unwrap();
+            *largest = (*largest).max(inputs.len());
+            Ok(inputs
+                .iter()
+                .map(|input| vec![input.len() as f32, 1.0])
+                .collect())
+        }
+    }
+
+    fn assert_offsets(text: &str, chunks: &[DocumentChunk]) {
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.index, index);
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+        }
+    }
+
+    #[test]
+    fn fixed_windows_overlap_and_respect_utf8_boundaries() {
+        let text = "fn main() {\n    println!(\"你好\");\n}\n".repeat(6);
+        let chunker = DocumentChunker::new(ChunkStrategy::Fixed {
+            max_bytes: 64,
+            overlap_bytes: 16,
+        });
+        let chunks = chunker.chunk(&text).unwrap();
+        assert!(chunks.len() > 3);
+        assert_offsets(&text, &chunks);
+        assert!(chunks.iter().all(|chunk| chunk.text.len() <= 64));
+        assert!(chunks.windows(2).all(|pair| pair[1].start < pair[0].end));
+        assert_eq!(chunks.last().unwrap().end, text.trim_end().len());
+
+        let tiny = DocumentChunker::new(ChunkStrategy::Fixed {
+            max_bytes: 1,
+            overlap_bytes: 0,
+        });
+        assert_eq!(tiny.chunk("界a").unwrap().len(), 2);
+        assert!(
+            DocumentChunker::new(ChunkStrategy::Fixed {
+                max_bytes: 8,
+                overlap_bytes: 8,
+            })
+            .chunk("text")
+            .is_err()
+        );
+        assert!(
+            DocumentChunker::new(ChunkStrategy::Headings {
+                max_bytes: DEFAULT_MAX_MEMORY_TEXT_BYTES + 1,
+            })
+            .chunk("text")
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn heading_chunks_track_section_paths_outside_code_fences() {
+        let text = "Intro line.\n# Guide\nStart here.\n## Install\n```sh\n# not a heading\ncargo add rustglm\n```\n## Usage\nCall it.\n# FAQ #\nAsk.\n";
+        let chunks = DocumentChunker::new(ChunkStrategy::Headings { max_bytes: 512 })
+            .chunk(text)
+            .unwrap();
+        assert_offsets(text, &chunks);
+        let headings = chunks
+            .iter()
+            .map(|chunk| chunk.heading.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headings,
+            [
+                None,
+                Some("Guide"),
+                Some("Guide > Install"),
+                Some("Guide > Usage"),
+                Some("FAQ"),
+            ]
+        );
+        assert!(chunks[2].text.contains("# not a heading"));
+
+        let long = format!("# Big\n{}", "word ".repeat(100));
+        let split = DocumentChunker::new(ChunkStrategy::Headings { max_bytes: 128 })
+            .chunk(&long)
+            .unwrap();
+        assert!(split.len() > 1);
+        assert!(
+            split
+                .iter()
+                .all(|chunk| chunk.heading.as_deref() == Some("Big"))
+        );
+    }
+
+    #[test]
+    fn sentence_chunks_pack_whole_sentences_with_overlap() {
+        let text = "First point. Second point! Third point? 第四句。第五句。Version 1.2 ships.";
+        let chunks = DocumentChunker::new(ChunkStrategy::Sentences {
+            max_bytes: 30,
+            overlap_sentences: 1,
+        })
+        .chunk(text)
+        .unwrap();
+        assert_offsets(text, &chunks);
+        assert_eq!(chunks[0].text, "First point. Second point!");
+        assert!(chunks[1].text.starts_with("Second point!"));
+        assert!(
+            chunks
+                .iter()
+                .any(|chunk| chunk.text.ends_with("Version 1.2 ships."))
+        );
+        let oversized = DocumentChunker::new(ChunkStrategy::Sentences {
+            max_bytes: 8,
+            overlap_sentences: 0,
+        })
+        .chunk("A single sentence that is long.")
+        .unwrap();
+        assert!(oversized.iter().all(|chunk| chunk.text.len() <= 8));
+    }
+
+    #[tokio::test]
+    async fn ingest_batches_embeddings_and_replaces_stale_chunks() {
+        let embeddings = Arc::new(CountingEmbeddings::default());
+        let store = Arc::new(InMemoryVectorStore::new());
+        let memory = SemanticMemory::new(embeddings.clone(), store.clone());
+        let chunker = DocumentChunker::new(ChunkStrategy::Sentences {
+            max_bytes: 16,
+            overlap_sentences: 0,
+        })
+        .embedding_batch_size(2);
+        let text = "One fish. Two fish. Red fish. Blue fish. Old fish.";
+        let ids = memory
+            .ingest(
+                MemoryDocument::new("doc", text).metadata("tenant", "acme"),
+                &chunker,
+            )
+            .await
+            .unwrap();
+        assert_eq!(ids, ["doc#0", "doc#1", "doc#2", "doc#3", "doc#4"]);
+        assert_eq!(embeddings.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(*embeddings.largest_batch.lock().unwrap(), 2);
+        let stored = store.snapshot().unwrap();
+        let second = &stored[1].document;
+        assert_eq!(second.text, "Two fish.");
+        assert_eq!(second.metadata["tenant"], "acme");
+        assert_eq!(second.metadata["parent_id"], "doc");
+        assert_eq!(second.metadata["chunk_index"], "1");
+        let start = second.metadata["chunk_start"].parse::<usize>().unwrap();
+        let end = second.metadata["chunk_end"].parse::<usize>().unwrap();
+        assert_eq!(&text[start..end], "Two fish.");
+
+        memory
+            .ingest(MemoryDocument::new("doc", "Short now."), &chunker)
+            .await
+            .unwrap();
+        assert_eq!(store.len().await.unwrap(), 1);
+        assert!(
+            memory
+                .ingest(MemoryDocument::new(" ", "text"), &chunker)
+                .await
+                .is_err()
+        );
+    }
+}
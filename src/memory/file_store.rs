@@ -0,0 +1,596 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize};
+
+use super::{
+    InMemoryVectorStore, MemoryMatch, MetadataFilter, StoredMemory, VectorStore,
+    validate_delete_filter,
+};
+use crate::security::DEFAULT_VECTOR_STORE_CAPACITY;
+use crate::{Result, SdkError};
+
+const DEFAULT_COMPACTION_INTERVAL: usize = 1_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum LogRecord {
+    Upsert { memory: StoredMemory },
+    Delete { ids: Vec<String> },
+    Clear,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileVectorStoreConfig {
+    pub path: PathBuf,
+    pub max_records: usize,
+    pub compaction_interval: usize,
+    pub sync_writes: bool,
+}
+
+impl FileVectorStoreConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_records: DEFAULT_VECTOR_STORE_CAPACITY,
+            compaction_interval: DEFAULT_COMPACTION_INTERVAL,
+            sync_writes: false,
+        }
+    }
+
+    pub fn max_records(mut self, value: usize) -> Self {
+        self.max_records = value;
+        self
+    }
+
+    /// Rewrites the log from the live records once it holds this many records that no longer
+    /// describe a live memory (overwrites, deletes, evictions). Zero disables automatic
+    /// compaction; [`FileVectorStore::compact`] still works.
+    pub fn compaction_interval(mut self, value: usize) -> Self {
+        self.compaction_interval = value;
+        self
+    }
+
+    /// Calls `fsync` after every append. Without it a process crash loses nothing, but a power
+    /// failure can drop the most recent writes.
+    pub fn sync_writes(mut self, value: bool) -> Self {
+        self.sync_writes = value;
+        self
+    }
+
+    pub fn open(self) -> Result<FileVectorStore> {
+        FileVectorStore::open_with(self)
+    }
+}
+
+/// A durable [`VectorStore`] backed by an append-only JSON-lines log.
+///
+/// Every write is appended before it becomes visible to searches. Opening the store replays the
+/// log into an in-memory index and truncates a torn final record left behind by a crash; damage
+/// anywhere else is reported instead of silently dropping data. Appends, `fsync`, and compaction
+/// run on tokio's blocking pool, so writes never stall an async worker.
+pub struct FileVectorStore {
+    inner: Arc<FileLog>,
+}
+
+struct FileLog {
+    index: InMemoryVectorStore,
+    log: Mutex<LogWriter>,
+    path: PathBuf,
+    compaction_interval: usize,
+    sync_writes: bool,
+}
+
+struct LogWriter {
+    file: File,
+    /// Records in the log file, live or not.
+    records: usize,
+}
+
+impl FileVectorStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        FileVectorStoreConfig::new(path).open()
+    }
+
+    /// Replays the log synchronously; call it from `spawn_blocking` when opening a large store
+    /// inside a running runtime.
+    pub fn open_with(config: FileVectorStoreConfig) -> Result<Self> {
+        let index = InMemoryVectorStore::with_capacity(config.max_records);
+        let records = recover_log(&config.path)?;
+        let replayed = records.len();
+        for record in records {
+            match record {
+                LogRecord::Upsert { memory } => index.insert(memory)?,
+                LogRecord::Delete { ids } => {
+                    for id in ids {
+                        index.remove(&id)?;
+                    }
+                }
+                LogRecord::Clear => index.remove_all()?,
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)
+            .map_err(|error| SdkError::io(config.path.display(), error))?;
+        Ok(Self {
+            inner: Arc::new(FileLog {
+                index,
+                log: Mutex::new(LogWriter {
+                    file,
+                    records: replayed,
+                }),
+                path: config.path,
+                compaction_interval: config.compaction_interval,
+                sync_writes: config.sync_writes,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    pub fn snapshot(&self) -> Result<Vec<StoredMemory>> {
+        self.inner.index.snapshot()
+    }
+
+    /// Rewrites the log so it holds exactly one record per live memory. This blocks the calling
+    /// thread; automatic compaction already runs on the blocking pool.
+    pub fn compact(&self) -> Result<()> {
+        let mut log = self.inner.lock_log()?;
+        self.inner.compact_locked(&mut log)
+    }
+
+    /// Runs a log write on the blocking pool while holding the log lock.
+    async fn write<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&FileLog, &mut LogWriter) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut log = inner.lock_log()?;
+            operation(&inner, &mut log)
+        })
+        .await
+        .map_err(|error| {
+            SdkError::Configuration(format!("vector store write task failed: {error}").into())
+        })?
+    }
+}
+
+impl FileLog {
+    fn compact_locked(&self, log: &mut LogWriter) -> Result<()> {
+        let io = |path: &Path| {
+            let path = path.display().to_string();
+            move |error| SdkError::io(path, error)
+        };
+        let records = self.index.snapshot()?;
+        let temporary = self.path.with_extension("compacting");
+        {
+            let mut file = File::create(&temporary).map_err(io(&temporary))?;
+            let mut buffer = Vec::new();
+            for memory in &records {
+                encode_record(
+                    &mut buffer,
+                    &LogRecord::Upsert {
+                        memory: memory.clone(),
+                    },
+                )?;
+            }
+            file.write_all(&buffer).map_err(io(&temporary))?;
+            file.sync_all().map_err(io(&temporary))?;
+        }
+        fs::rename(&temporary, &self.path).map_err(io(&self.path))?;
+        sync_parent(&self.path).map_err(io(&self.path))?;
+        log.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(io(&self.path))?;
+        log.records = records.len();
+        Ok(())
+    }
+
+    fn append(&self, log: &mut LogWriter, record: &LogRecord) -> Result<()> {
+        let mut buffer = Vec::new();
+        encode_record(&mut buffer, record)?;
+        log.file
+            .write_all(&buffer)
+            .and_then(|()| {
+                if self.sync_writes {
+                    log.file.sync_data()
+                } else {
+                    Ok(())
+                }
+            })
+            .map_err(|error| SdkError::io(self.path.display(), error))?;
+        log.records += 1;
+        Ok(())
+    }
+
+    fn compact_if_due(&self, log: &mut LogWriter) -> Result<()> {
+        if self.compaction_interval > 0 {
+            let garbage = log.records.saturating_sub(self.index.record_count()?);
+            if garbage >= self.compaction_interval {
+                self.compact_locked(log)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lock_log(&self) -> Result<std::sync::MutexGuard<'_, LogWriter>> {
+        self.log
+            .lock()
+            .map_err(|_| SdkError::Configuration("vector store log lock is poisoned".into()))
+    }
+}
+
+#[async_trait]
+impl VectorStore for FileVectorStore {
+    async fn upsert(&self, memory: StoredMemory) -> Result<()> {
+        self.write(|store, log| {
+            let record = LogRecord::Upsert { memory };
+            store.append(log, &record)?;
+            let LogRecord::Upsert { memory } = record else {
+                unreachable!("record was constructed as an upsert");
+            };
+            store.index.insert(memory)?;
+            store.compact_if_due(log)
+        })
+        .await
+    }
+
+    async fn search(&self, query: &[f32], limit: usize) -> Result<Vec<MemoryMatch>> {
+        self.inner.index.search(query, limit).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.write(|store, log| {
+            store.append(log, &LogRecord::Clear)?;
+            store.index.remove_all()?;
+            store.compact_if_due(log)
+        })
+        .await
+    }
+
+    async fn len(&self) -> Result<usize> {
+        self.inner.index.len().await
+    }
+
+    async fn contains(&self, id: &str) -> Result<bool> {
+        self.inner.index.contains_record(id)
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &[f32],
+        limit: usize,
+        filter: &MetadataFilter,
+    ) -> Result<Vec<MemoryMatch>> {
+        self.inner.index.search_filtered(query, limit, filter).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        let id = id.to_owned();
+        self.write(move |store, log| {
+            if !store.index.contains_record(&id)? {
+                return Ok(false);
+            }
+            store.append(
+                log,
+                &LogRecord::Delete {
+                    ids: vec![id.clone()],
+                },
+            )?;
+            store.index.remove(&id)?;
+            store.compact_if_due(log)?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn delete_where(&self, filter: &MetadataFilter) -> Result<usize> {
+        validate_delete_filter(filter)?;
+        let filter = filter.clone();
+        self.write(move |store, log| {
+            let ids = store.index.matching_ids(&filter)?;
+            if ids.is_empty() {
+                return Ok(0);
+            }
+            let removed = ids.len();
+            store.append(log, &LogRecord::Delete { ids })?;
+            store.index.remove_matching(&filter)?;
+            store.compact_if_due(log)?;
+            Ok(removed)
+        })
+        .await
+    }
+}
+
+fn encode_record(buffer: &mut Vec<u8>, record: &LogRecord) -> Result<()> {
+    let line = nextjson::to_string(record)
+        .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+    buffer.extend_from_slice(line.as_bytes());
+    buffer.push(b'\n');
+    Ok(())
+}
+
+/// Flushes the directory entry after a rename, which POSIX needs for the rename to survive a
+/// crash. Other platforms cannot open directories this way and make renames durable themselves.
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Reads every intact record and truncates the file after the last one when the final record
+/// was only partially written.
+fn recover_log(path: &Path) -> Result<Vec<LogRecord>> {
+    let io = |error| SdkError::io(path.display(), error);
+    let mut bytes = Vec::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.read_to_end(&mut bytes).map_err(io)?;
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(io(error)),
+    }
+    let mut records = Vec::new();
+    let mut valid_len = 0;
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let Some(end) = bytes[offset..].iter().position(|byte| *byte == b'\n') else {
+            break;
+        };
+        let line = &bytes[offset..offset + end];
+        let next = offset + end + 1;
+        if !line.iter().all(u8::is_ascii_whitespace) {
+            match nextjson::from_slice::<LogRecord>(line) {
+                Ok(record) => records.push(record),
+                Err(_) if next == bytes.len() => break,
+                Err(error) => {
+                    return Err(SdkError::Decode {
+                        message: format!(
+                            "vector store log {} is corrupt at byte {offset}: {error}",
+                            path.display()
+                        ),
+                        body: String::new(),
+                    });
+                }
+            }
+        }
+        valid_len = next;
+        offset = next;
+    }
+    if valid_len < bytes.len() {
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(valid_len as u64))
+            .map_err(io)?;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use crate::{MemoryDocument, QuantizedVector, SemanticMemory};
+
+    fn memory(id: &str, vector: &[f32]) -> StoredMemory {
+        StoredMemory {
+            document: MemoryDocument::new(id, format!("text {id}")),
+            embedding: QuantizedVector::compress(vector).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn persists_replays_and_compacts_records() {
+        let directory = TempDir::new("vector-log");
+        let path = directory.join("log.jsonl");
+        {
+            let store = FileVectorStoreConfig::new(&path)
+                .compaction_interval(0)
+                .sync_writes(true)
+                .open()
+                .unwrap();
+            store.upsert(memory("rust", &[1.0, 0.0])).await.unwrap();
+            store.upsert(memory("weather", &[0.0, 1.0])).await.unwrap();
+            store.upsert(memory("rust", &[0.9, 0.1])).await.unwrap();
+            assert_eq!(store.len().await.unwrap(), 2);
+            assert_eq!(store.path(), path.as_path());
+        }
+        let store = FileVectorStore::open(&path).unwrap();
+        assert_eq!(store.len().await.unwrap(), 2);
+        assert_eq!(
+            store.search(&[1.0, 0.0], 1).await.unwrap()[0].document.id,
+            "rust"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+        store.compact().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        store.clear().await.unwrap();
+        store.upsert(memory("after", &[1.0, 1.0])).await.unwrap();
+        drop(store);
+        let store = FileVectorStore::open(&path).unwrap();
+        let ids = store
+            .snapshot()
+            .unwrap()
+            .into_iter()
+            .map(|memory| memory.document.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["after"]);
+    }
+
+    #[tokio::test]
+    async fn persists_deletes_across_reopen() {
+        let directory = TempDir::new("vector-delete");
+        let path = directory.join("log.jsonl");
+        {
+            let store = FileVectorStore::open(&path).unwrap();
+            for (id, tenant) in [("a", "acme"), ("b", "acme"), ("c", "beta")] {
+                let mut stored = memory(id, &[1.0, 0.0]);
+                stored.document = stored.document.metadata("tenant", tenant);
+                store.upsert(stored).await.unwrap();
+            }
+            assert!(store.delete("c").await.unwrap());
+            assert!(!store.delete("c").await.unwrap());
+            let acme = MetadataFilter::new().equals("tenant", "acme");
+            assert_eq!(
+                store
+                    .search_filtered(&[1.0, 0.0], 5, &acme)
+                    .await
+                    .unwrap()
+                    .len(),
+                2
+            );
+            assert_eq!(
+                store
+                    .delete_where(&MetadataFilter::new().prefix("tenant", "ac"))
+                    .await
+                    .unwrap(),
+                2
+            );
+            store.upsert(memory("d", &[0.0, 1.0])).await.unwrap();
+        }
+        let store = FileVectorStore::open(&path).unwrap();
+        let ids = store
+            .snapshot()
+            .unwrap()
+            .into_iter()
+            .map(|memory| memory.document.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["d"]);
+    }
+
+    #[tokio::test]
+    async fn compacts_automatically_after_the_configured_interval() {
+        let directory = TempDir::new("vector-compaction");
+        let path = directory.join("log.jsonl");
+        let store = FileVectorStoreConfig::new(&path)
+            .compaction_interval(3)
+            .open()
+            .unwrap();
+        for _ in 0..3 {
+            store.upsert(memory("same", &[1.0, 0.0])).await.unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+        store.upsert(memory("same", &[1.0, 0.0])).await.unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        drop(store);
+        let bounded = FileVectorStoreConfig::new(&path)
+            .max_records(1)
+            .open()
+            .unwrap();
+        bounded.upsert(memory("other", &[0.0, 1.0])).await.unwrap();
+        assert_eq!(bounded.len().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn evicting_at_capacity_compacts_only_after_the_interval() {
+        let directory = TempDir::new("vector-capacity");
+        let path = directory.join("log.jsonl");
+        let store = FileVectorStoreConfig::new(&path)
+            .max_records(4)
+            .compaction_interval(3)
+            .open()
+            .unwrap();
+        for id in ["a", "b", "c", "d"] {
+            store.upsert(memory(id, &[1.0, 0.0])).await.unwrap();
+        }
+        let mut lines = Vec::new();
+        for id in ["e", "f", "g", "h", "i", "j"] {
+            store.upsert(memory(id, &[1.0, 0.0])).await.unwrap();
+            lines.push(fs::read_to_string(&path).unwrap().lines().count());
+        }
+        assert_eq!(lines, [5, 6, 4, 5, 6, 4]);
+        assert_eq!(store.len().await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn truncates_a_torn_tail_but_rejects_mid_log_corruption() {
+        let directory = TempDir::new("vector-torn");
+        let path = directory.join("log.jsonl");
+        {
+            let store = FileVectorStore::open(&path).unwrap();
+            store.upsert(memory("kept", &[1.0, 0.0])).await.unwrap();
+        }
+        let intact = fs::read(&path).unwrap();
+        let mut torn = intact.clone();
+        torn.extend_from_slice(b"{\"op\":\"upsert\",\"memory\":{\"document\"");
+        fs::write(&path, &torn).unwrap();
+        let store = FileVectorStore::open(&path).unwrap();
+        assert_eq!(store.len().await.unwrap(), 1);
+        assert_eq!(fs::read(&path).unwrap(), intact);
+        store.upsert(memory("next", &[0.0, 1.0])).await.unwrap();
+        drop(store);
+        assert_eq!(
+            FileVectorStore::open(&path).unwrap().len().await.unwrap(),
+            2
+        );
+
+        let mut corrupt = b"not json\n".to_vec();
+        corrupt.extend_from_slice(&intact);
+        fs::write(&path, corrupt).unwrap();
+        assert!(matches!(
+            FileVectorStore::open(&path),
+            Err(SdkError::Decode { .. })
+        ));
+
+        let missing = directory.join("missing").join("nested.jsonl");
+        assert!(matches!(
+            FileVectorStore::open(&missing),
+            Err(SdkError::Configuration(error)) if error.to_string().contains("nested.jsonl")
+        ));
+    }
+
+    #[tokio::test]
+    async fn semantic_memory_accepts_the_file_store() {
+        struct AxisEmbeddings;
+
+        #[async_trait]
+        impl crate::EmbeddingProvider for AxisEmbeddings {
+            async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+                Ok(inputs
+                    .iter()
+                    .map(|input| {
+                        if input.contains("Rust") {
+                            vec![1.0, 0.0]
+                        } else {
+                            vec![0.0, 1.0]
+                        }
+                    })
+                    .collect())
+            }
+        }
+
+        let directory = TempDir::new("vector-semantic");
+        let path = directory.join("log.jsonl");
+        let store = std::sync::Arc::new(FileVectorStore::open(&path).unwrap());
+        let memory = SemanticMemory::new(std::sync::Arc::new(AxisEmbeddings), store);
+        crate::ConversationMemory::remember(&memory, MemoryDocument::new("one", "Rust traits"))
+            .await
+            .unwrap();
+        drop(memory);
+        let store = std::sync::Arc::new(FileVectorStore::open(&path).unwrap());
+        let memory = SemanticMemory::new(std::sync::Arc::new(AxisEmbeddings), store);
+        let recalled = crate::ConversationMemory::recall(&memory, "Rust", 1)
+            .await
+            .unwrap();
+        assert_eq!(recalled[0].document.id, "one");
+    }
+}
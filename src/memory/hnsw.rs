@@ -0,0 +1,889 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use super::{MemoryMatch, MetadataFilter, StoredMemory, VectorStore, validate_delete_filter};
+use crate::security::DEFAULT_VECTOR_STORE_CAPACITY;
+use crate::{Result, SdkError};
+
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 200;
+const DEFAULT_EF_SEARCH: usize = 64;
+const DEFAULT_SEED: u64 = 0x5eed_1e55_c0ff_ee00;
+const MAX_LEVEL: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HnswConfig {
+    pub m: usize,
+    pub ef_construction: usize,
+    pub ef_search: usize,
+    pub max_records: usize,
+    pub seed: u64,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HnswConfig {
+    pub fn new() -> Self {
+        Self {
+            m: DEFAULT_M,
+            ef_construction: DEFAULT_EF_CONSTRUCTION,
+            ef_search: DEFAULT_EF_SEARCH,
+            max_records: DEFAULT_VECTOR_STORE_CAPACITY,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    /// Links kept per node on the upper layers; the bottom layer keeps twice as many.
+    pub fn m(mut self, value: usize) -> Self {
+        self.m = value;
+        self
+    }
+
+    pub fn ef_construction(mut self, value: usize) -> Self {
+        self.ef_construction = value;
+        self
+    }
+
+    /// Candidate list size during search. Raised to the requested limit when smaller.
+    pub fn ef_search(mut self, value: usize) -> Self {
+        self.ef_search = value;
+        self
+    }
+
+    pub fn max_records(mut self, value: usize) -> Self {
+        self.max_records = value;
+        self
+    }
+
+    /// Seeds layer assignment so graph construction is reproducible.
+    pub fn seed(mut self, value: u64) -> Self {
+        self.seed = value;
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.m < 2 {
+            return Err(SdkError::Configuration("HNSW m must be at least 2".into()));
+        }
+        if self.ef_construction == 0 || self.ef_search == 0 {
+            return Err(SdkError::Configuration(
+                "HNSW ef_construction and ef_search must be greater than zero".into(),
+            ));
+        }
+        if self.max_records == 0 {
+            return Err(SdkError::Configuration(
+                "HNSW max_records must be greater than zero".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// An approximate nearest-neighbour [`VectorStore`] built on a hierarchical navigable small
+/// world graph over [`QuantizedVector`](super::QuantizedVector) payloads.
+///
+/// Upserting an existing id replaces its node. Deletes unlink the node and reconnect its
+/// neighbours, so the graph does not accumulate tombstones. When `max_records` is reached the
+/// oldest record is evicted, matching [`InMemoryVectorStore`](super::InMemoryVectorStore).
+pub struct HnswVectorStore {
+    config: HnswConfig,
+    graph: RwLock<Graph>,
+}
+
+impl Default for HnswVectorStore {
+    fn default() -> Self {
+        Self::with_graph(HnswConfig::new())
+    }
+}
+
+impl HnswVectorStore {
+    pub fn new(config: HnswConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self::with_graph(config))
+    }
+
+    fn with_graph(config: HnswConfig) -> Self {
+        let graph = Graph::new(config.seed);
+        Self {
+            config,
+            graph: RwLock::new(graph),
+        }
+    }
+
+    pub fn config(&self) -> &HnswConfig {
+        &self.config
+    }
+
+    /// Removes a record by document id, returning whether it was present.
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        let mut graph = self.write()?;
+        let Some(slot) = graph.ids.get(id).copied() else {
+            return Ok(false);
+        };
+        graph.remove(slot, &self.config);
+        Ok(true)
+    }
+
+    pub fn snapshot(&self) -> Result<Vec<StoredMemory>> {
+        let graph = self.read()?;
+        Ok(graph
+            .order
+            .values()
+            .filter_map(|slot| graph.nodes[*slot].as_ref())
+            .map(|node| node.memory.clone())
+            .collect())
+    }
+
+    fn read(&self) -> Result<std::sync::RwLockReadGuard<'_, Graph>> {
+        self.graph
+            .read()
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))
+    }
+
+    fn write(&self) -> Result<std::sync::RwLockWriteGuard<'_, Graph>> {
+        self.graph
+            .write()
+            .map_err(|_| SdkError::Configuration("vector store lock is poisoned".into()))
+    }
+}
+
+#[async_trait]
+impl VectorStore for HnswVectorStore {
+    async fn upsert(&self, memory: StoredMemory) -> Result<()> {
+        let dimensions = memory.embedding.dimensions();
+        if dimensions == 0 || !memory.embedding.scale.is_finite() {
+            return Err(SdkError::Validation(
+                "embedding must contain finite values".into(),
+            ));
+        }
+        let mut graph = self.write()?;
+        let existing = graph.ids.get(&memory.document.id).copied();
+        let replaces_only_record = existing.is_some() && graph.ids.len() == 1;
+        if graph
+            .dimensions
+            .is_some_and(|expected| expected != dimensions && !replaces_only_record)
+        {
+            return Err(SdkError::Validation(
+                "embedding dimensions must match stored vectors".into(),
+            ));
+        }
+        if let Some(slot) = existing {
+            graph.remove(slot, &self.config);
+        }
+        while graph.ids.len() >= self.config.max_records {
+            let Some((_, oldest)) = graph.order.first_key_value().map(|(k, v)| (*k, *v)) else {
+                break;
+            };
+            graph.remove(oldest, &self.config);
+        }
+        graph.insert(memory, &self.config);
+        Ok(())
+    }
+
+    async fn search(&self, query: &[f32], limit: usize) -> Result<Vec<MemoryMatch>> {
+        self.search_filtered(query, limit, &MetadataFilter::default())
+            .await
+    }
+
+    /// Filtered searches widen the candidate list until enough matching records are found, so
+    /// very selective filters approach a full graph traversal.
+    async fn search_filtered(
+        &self,
+        query: &[f32],
+        limit: usize,
+        filter: &MetadataFilter,
+    ) -> Result<Vec<MemoryMatch>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let graph = self.read()?;
+        let Some(dimensions) = graph.dimensions else {
+            return Ok(Vec::new());
+        };
+        if query.len() != dimensions || query.iter().any(|value| !value.is_finite()) {
+            return Err(SdkError::Validation(
+                "query embedding dimensions must match stored vector".into(),
+            ));
+        }
+        let query_norm = query.iter().map(|value| value * value).sum::<f32>().sqrt();
+        let similarity = |slot: usize| {
+            let node = graph.node(slot);
+            if query_norm == 0.0 || node.norm == 0.0 {
+                return 0.0;
+            }
+            let dot = node
+                .memory
+                .embedding
+                .values
+                .iter()
+                .zip(query)
+                .map(|(stored, query)| *stored as f32 * query)
+                .sum::<f32>();
+            dot / (node.norm * query_norm)
+        };
+        let mut ef = self.config.ef_search.max(limit);
+        loop {
+            let found = graph
+                .search(&similarity, ef)
+                .into_iter()
+                .filter(|(_, slot)| filter.matches(&graph.node(*slot).memory.document))
+                .take(limit)
+                .collect::<Vec<_>>();
+            if found.len() == limit || ef >= graph.ids.len() {
+                return Ok(found
+                    .into_iter()
+                    .map(|(score, slot)| MemoryMatch {
+                        document: graph.node(slot).memory.document.clone(),
+                        score,
+                    })
+                    .collect());
+            }
+            ef = ef.saturating_mul(2).min(graph.ids.len());
+        }
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        self.remove(id)
+    }
+
+    async fn delete_where(&self, filter: &MetadataFilter) -> Result<usize> {
+        validate_delete_filter(filter)?;
+        let mut graph = self.write()?;
+        let slots = graph
+            .order
+            .values()
+            .copied()
+            .filter(|slot| filter.matches(&graph.node(*slot).memory.document))
+            .collect::<Vec<_>>();
+        for slot in &slots {
+            graph.remove(*slot, &self.config);
+        }
+        Ok(slots.len())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let mut graph = self.write()?;
+        let rng = graph.rng;
+        *graph = Graph::new(rng);
+        Ok(())
+    }
+
+    async fn len(&self) -> Result<usize> {
+        Ok(self.read()?.ids.len())
+    }
+
+    async fn contains(&self, id: &str) -> Result<bool> {
+        Ok(self.read()?.ids.contains_key(id))
+    }
+}
+
+struct Node {
+    memory: StoredMemory,
+    norm: f32,
+    sequence: u64,
+    neighbors: Vec<Vec<usize>>,
+    incoming: Vec<Vec<usize>>,
+}
+
+struct Graph {
+    nodes: Vec<Option<Node>>,
+    free: Vec<usize>,
+    ids: HashMap<String, usize>,
+    order: BTreeMap<u64, usize>,
+    /// Live slots by top layer, so a removed entry point is replaced without a full scan.
+    tops: Vec<BTreeSet<usize>>,
+    entry: Option<usize>,
+    dimensions: Option<usize>,
+    next_sequence: u64,
+    rng: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, usize);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+impl Graph {
+    fn new(seed: u64) -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            ids: HashMap::new(),
+            order: BTreeMap::new(),
+            tops: vec![BTreeSet::new(); MAX_LEVEL + 1],
+            entry: None,
+            dimensions: None,
+            next_sequence: 0,
+            rng: seed,
+        }
+    }
+
+    fn node(&self, slot: usize) -> &Node {
+        self.nodes[slot]
+            .as_ref()
+            .expect("HNSW links only point at live nodes")
+    }
+
+    fn node_mut(&mut self, slot: usize) -> &mut Node {
+        self.nodes[slot]
+            .as_mut()
+            .expect("HNSW links only point at live nodes")
+    }
+
+    fn level(&self, slot: usize) -> usize {
+        self.node(slot).neighbors.len() - 1
+    }
+
+    fn similarity(&self, left: usize, right: usize) -> f32 {
+        let left = self.node(left);
+        let right = self.node(right);
+        if left.norm == 0.0 || right.norm == 0.0 {
+            return 0.0;
+        }
+        let dot = left
+            .memory
+            .embedding
+            .values
+            .iter()
+            .zip(&right.memory.embedding.values)
+            .map(|(left, right)| i32::from(*left) * i32::from(*right))
+            .sum::<i32>();
+        dot as f32 / (left.norm * right.norm)
+    }
+
+    fn random_level(&mut self, m: usize) -> usize {
+        // splitmix64; layer probabilities follow the usual 1 / ln(m) normalisation.
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.rng;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^= value >> 31;
+        let uniform = ((value >> 11) as f64 + 1.0) / (1_u64 << 53) as f64;
+        let level = -uniform.ln() / (m as f64).ln();
+        (level as usize).min(MAX_LEVEL)
+    }
+
+    fn insert(&mut self, memory: StoredMemory, config: &HnswConfig) {
+        let level = self.random_level(config.m);
+        let norm = memory
+            .embedding
+            .values
+            .iter()
+            .map(|value| (*value as f32) * (*value as f32))
+            .sum::<f32>()
+            .sqrt();
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.dimensions = Some(memory.embedding.dimensions());
+        let id = memory.document.id.clone();
+        let node = Node {
+            memory,
+            norm,
+            sequence,
+            neighbors: vec![Vec::new(); level + 1],
+            incoming: vec![Vec::new(); level + 1],
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.ids.insert(id, slot);
+        self.order.insert(sequence, slot);
+        self.tops[level].insert(slot);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(slot);
+            return;
+        };
+        let top = self.level(entry);
+        let mut entry_points = vec![entry];
+        for layer in (level + 1..=top).rev() {
+            let nearest = self.search_layer(
+                &|other| self.similarity(slot, other),
+                &entry_points,
+                1,
+                layer,
+            );
+            entry_points = nearest.into_iter().map(|Scored(_, slot)| slot).collect();
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(
+                &|other| self.similarity(slot, other),
+                &entry_points,
+                config.ef_construction,
+                layer,
+            );
+            entry_points = candidates.iter().map(|Scored(_, slot)| *slot).collect();
+            let max_links = max_links(config, layer);
+            let selected = self.select_neighbors(slot, entry_points.clone(), max_links);
+            self.set_links(slot, layer, selected.clone());
+            for neighbor in selected {
+                let mut links = self.node(neighbor).neighbors[layer].clone();
+                links.push(slot);
+                if links.len() > max_links {
+                    links = self.select_neighbors(neighbor, links, max_links);
+                }
+                self.set_links(neighbor, layer, links);
+            }
+        }
+        if level > top {
+            self.entry = Some(slot);
+        }
+    }
+
+    /// Unlinks `slot` and reconnects its in-neighbours to its out-neighbours. Links that are
+    /// the last way into a node are never pruned, and out-neighbours left without any inbound
+    /// link are attached to their closest remaining in-neighbour, so deletes cannot strand nodes.
+    fn remove(&mut self, slot: usize, config: &HnswConfig) {
+        let levels = self.node(slot).neighbors.len();
+        for layer in 0..levels {
+            let outgoing = self.node(slot).neighbors[layer].clone();
+            let incoming = self.node(slot).incoming[layer].clone();
+            self.set_links(slot, layer, Vec::new());
+            let max_links = max_links(config, layer);
+            for &source in &incoming {
+                if source == slot {
+                    continue;
+                }
+                let (protected, mut candidates): (Vec<_>, Vec<_>) = self.node(source).neighbors
+                    [layer]
+                    .iter()
+                    .copied()
+                    .filter(|candidate| *candidate != slot)
+                    .partition(|candidate| self.node(*candidate).incoming[layer] == [source]);
+                candidates.extend(
+                    outgoing
+                        .iter()
+                        .copied()
+                        .filter(|candidate| *candidate != source && !protected.contains(candidate)),
+                );
+                let room = max_links.saturating_sub(protected.len());
+                let mut links = protected;
+                links.extend(self.select_neighbors(source, candidates, room));
+                self.set_links(source, layer, links);
+            }
+            for &orphan in &outgoing {
+                if orphan == slot || !self.node(orphan).incoming[layer].is_empty() {
+                    continue;
+                }
+                let host = incoming
+                    .iter()
+                    .chain(&self.node(orphan).neighbors[layer])
+                    .copied()
+                    .filter(|host| *host != slot && *host != orphan)
+                    .max_by(|left, right| {
+                        self.similarity(orphan, *left)
+                            .total_cmp(&self.similarity(orphan, *right))
+                    });
+                if let Some(host) = host {
+                    self.attach(host, orphan, layer, max_links);
+                }
+            }
+        }
+        let node = self.nodes[slot].take().expect("removed slot is live");
+        self.ids.remove(&node.memory.document.id);
+        self.order.remove(&node.sequence);
+        self.tops[levels - 1].remove(&slot);
+        self.free.push(slot);
+        if self.entry == Some(slot) {
+            self.entry = self
+                .tops
+                .iter()
+                .rev()
+                .find_map(|slots| slots.first().copied());
+        }
+        if self.ids.is_empty() {
+            self.dimensions = None;
+        }
+    }
+
+    /// Links `host` to `target` on `layer`. A full link list gives up its least similar link
+    /// whose target stays reachable through another inbound link.
+    fn attach(&mut self, host: usize, target: usize, layer: usize, max_links: usize) {
+        let mut links = self.node(host).neighbors[layer].clone();
+        if links.contains(&target) {
+            return;
+        }
+        if links.len() >= max_links {
+            let evicted = links
+                .iter()
+                .copied()
+                .filter(|link| self.node(*link).incoming[layer].len() > 1)
+                .min_by(|left, right| {
+                    self.similarity(host, *left)
+                        .total_cmp(&self.similarity(host, *right))
+                });
+            if let Some(evicted) = evicted {
+                links.retain(|link| *link != evicted);
+            }
+        }
+        links.push(target);
+        self.set_links(host, layer, links);
+    }
+
+    /// Replaces the outgoing links of `slot` on `layer`, keeping the reverse index in sync.
+    fn set_links(&mut self, slot: usize, layer: usize, links: Vec<usize>) {
+        let previous = std::mem::replace(&mut self.node_mut(slot).neighbors[layer], links.clone());
+        for old in previous.iter().filter(|old| !links.contains(old)) {
+            self.node_mut(*old).incoming[layer].retain(|source| *source != slot);
+        }
+        for new in links.iter().filter(|new| !previous.contains(new)) {
+            self.node_mut(*new).incoming[layer].push(slot);
+        }
+    }
+
+    /// Neighbour selection heuristic: prefer candidates that are closer to `base` than to any
+    /// already selected neighbour, then fill remaining room with the closest pruned ones.
+    fn select_neighbors(
+        &self,
+        base: usize,
+        candidates: Vec<usize>,
+        max_links: usize,
+    ) -> Vec<usize> {
+        let mut scored = candidates
+            .into_iter()
+            .filter(|candidate| *candidate != base)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|candidate| Scored(self.similarity(base, candidate), candidate))
+            .collect::<Vec<_>>();
+        scored.sort_by(|left, right| right.cmp(left));
+        let mut selected = Vec::with_capacity(max_links);
+        let mut pruned = Vec::new();
+        for Scored(score, candidate) in scored {
+            if selected.len() >= max_links {
+                break;
+            }
+            if selected
+                .iter()
+                .all(|chosen| self.similarity(candidate, *chosen) < score)
+            {
+                selected.push(candidate);
+            } else {
+                pruned.push(candidate);
+            }
+        }
+        for candidate in pruned {
+            if selected.len() >= max_links {
+                break;
+            }
+            selected.push(candidate);
+        }
+        selected
+    }
+
+    fn search(&self, similarity: &dyn Fn(usize) -> f32, ef: usize) -> Vec<(f32, usize)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut entry_points = vec![entry];
+        for layer in (1..=self.level(entry)).rev() {
+            entry_points = self
+                .search_layer(similarity, &entry_points, 1, layer)
+                .into_iter()
+                .map(|Scored(_, slot)| slot)
+                .collect();
+        }
+        self.search_layer(similarity, &entry_points, ef, 0)
+            .into_iter()
+            .map(|Scored(score, slot)| (score, slot))
+            .collect()
+    }
+
+    /// Best-first search on one layer, returning up to `ef` nodes sorted by descending
+    /// similarity.
+    fn search_layer(
+        &self,
+        similarity: &dyn Fn(usize) -> f32,
+        entry_points: &[usize],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Scored> {
+        let mut visited = entry_points.iter().copied().collect::<HashSet<_>>();
+        let mut candidates = BinaryHeap::new();
+        let mut results = BinaryHeap::new();
+        for slot in entry_points {
+            let scored = Scored(similarity(*slot), *slot);
+            candidates.push(scored);
+            results.push(Reverse(scored));
+        }
+        while results.len() > ef {
+            results.pop();
+        }
+        while let Some(current) = candidates.pop() {
+            let worst = results.peek().map(|Reverse(worst)| *worst);
+            if results.len() >= ef && worst.is_some_and(|worst| current.0 < worst.0) {
+                break;
+            }
+            for neighbor in &self.node(current.1).neighbors[layer] {
+                if !visited.insert(*neighbor) {
+                    continue;
+                }
+                let scored = Scored(similarity(*neighbor), *neighbor);
+                let worst = results.peek().map(|Reverse(worst)| *worst);
+                if results.len() < ef || worst.is_some_and(|worst| scored > worst) {
+                    candidates.push(scored);
+                    results.push(Reverse(scored));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+        let mut results = results
+            .into_iter()
+            .map(|Reverse(scored)| scored)
+            .collect::<Vec<_>>();
+        results.sort_by(|left, right| right.cmp(left));
+        results
+    }
+}
+
+fn max_links(config: &HnswConfig, layer: usize) -> usize {
+    if layer == 0 { config.m * 2 } else { config.m }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InMemoryVectorStore, MemoryDocument, QuantizedVector};
+
+    struct Vectors(u64);
+
+    impl Vectors {
+        fn next(&mut self, dimensions: usize) -> Vec<f32> {
+            (0..dimensions)
+                .map(|_| {
+                    self.0 = self
+                        .0
+                        .wrapping_mul(6_364_136_223_846_793_005)
+                        .wrapping_add(1_442_695_040_888_963_407);
+                    ((self.0 >> 40) as f32 / (1_u64 << 24) as f32) * 2.0 - 1.0
+                })
+                .collect()
+        }
+    }
+
+    fn memory(id: String, vector: &[f32]) -> StoredMemory {
+        StoredMemory {
+            document: MemoryDocument::new(id, "text"),
+            embedding: QuantizedVector::compress(vector).unwrap(),
+        }
+    }
+
+    /// Every live node is reachable from the entry point on the bottom layer, and the entry sits
+    /// on the highest layer in use.
+    fn assert_connected(store: &HnswVectorStore) {
+        let graph = store.read().unwrap();
+        let Some(entry) = graph.entry else {
+            assert!(graph.ids.is_empty());
+            return;
+        };
+        let top = graph
+            .ids
+            .values()
+            .map(|slot| graph.level(*slot))
+            .max()
+            .unwrap();
+        assert_eq!(graph.level(entry), top);
+        let mut seen = HashSet::from([entry]);
+        let mut queue = vec![entry];
+        while let Some(slot) = queue.pop() {
+            for neighbor in &graph.node(slot).neighbors[0] {
+                if seen.insert(*neighbor) {
+                    queue.push(*neighbor);
+                }
+            }
+        }
+        assert_eq!(seen.len(), graph.ids.len());
+    }
+
+    async fn recall(
+        hnsw: &HnswVectorStore,
+        exact: &InMemoryVectorStore,
+        queries: &[Vec<f32>],
+        limit: usize,
+    ) -> f32 {
+        let mut hits = 0;
+        for query in queries {
+            let expected = exact
+                .search(query, limit)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|found| found.document.id)
+                .collect::<HashSet<_>>();
+            let found = hnsw.search(query, limit).await.unwrap();
+            assert_eq!(found.len(), limit);
+            assert!(found.windows(2).all(|pair| pair[0].score >= pair[1].score));
+            hits += found
+                .iter()
+                .filter(|found| expected.contains(&found.document.id))
+                .count();
+        }
+        hits as f32 / (queries.len() * limit) as f32
+    }
+
+    #[tokio::test]
+    async fn recall_tracks_brute_force_before_and_after_deletes() {
+        let mut vectors = Vectors(7);
+        let hnsw =
+            HnswVectorStore::new(HnswConfig::new().m(8).ef_construction(64).ef_search(48)).unwrap();
+        let exact = InMemoryVectorStore::unbounded();
+        for index in 0..800 {
+            let stored = memory(format!("doc-{index}"), &vectors.next(24));
+            hnsw.upsert(stored.clone()).await.unwrap();
+            exact.upsert(stored).await.unwrap();
+        }
+        let queries = (0..40).map(|_| vectors.next(24)).collect::<Vec<_>>();
+        let before = recall(&hnsw, &exact, &queries, 10).await;
+        assert!(before >= 0.9, "recall before deletes was {before}");
+
+        let mut survivors = InMemoryVectorStore::unbounded();
+        for stored in exact.snapshot().unwrap() {
+            let index = stored.document.id[4..].parse::<usize>().unwrap();
+            if index % 2 == 0 {
+                assert!(hnsw.remove(&stored.document.id).unwrap());
+            } else {
+                survivors.upsert(stored).await.unwrap();
+            }
+        }
+        assert!(!hnsw.remove("doc-0").unwrap());
+        assert_eq!(hnsw.len().await.unwrap(), 400);
+        assert_connected(&hnsw);
+        for _ in 0..5 {
+            let entry = {
+                let graph = hnsw.read().unwrap();
+                graph.node(graph.entry.unwrap()).memory.document.id.clone()
+            };
+            let stored = hnsw
+                .snapshot()
+                .unwrap()
+                .into_iter()
+                .find(|stored| stored.document.id == entry)
+                .unwrap();
+            assert!(hnsw.remove(&entry).unwrap());
+            survivors.delete(&entry).await.unwrap();
+            assert_connected(&hnsw);
+            hnsw.upsert(stored.clone()).await.unwrap();
+            survivors.upsert(stored).await.unwrap();
+        }
+        let after = recall(&hnsw, &survivors, &queries, 10).await;
+        assert!(after >= 0.9, "recall after deletes was {after}");
+
+        survivors = InMemoryVectorStore::unbounded();
+        for index in 0..400 {
+            let stored = memory(format!("new-{index}"), &vectors.next(24));
+            hnsw.upsert(stored.clone()).await.unwrap();
+            survivors.upsert(stored).await.unwrap();
+        }
+        for stored in hnsw.snapshot().unwrap() {
+            if stored.document.id.starts_with("doc-") {
+                survivors.upsert(stored).await.unwrap();
+            }
+        }
+        let refilled = recall(&hnsw, &survivors, &queries, 10).await;
+        assert!(refilled >= 0.9, "recall after refilling was {refilled}");
+    }
+
+    #[tokio::test]
+    async fn filtered_search_and_bulk_delete_respect_metadata() {
+        let mut vectors = Vectors(11);
+        let store = HnswVectorStore::default();
+        for index in 0..300 {
+            let mut stored = memory(format!("doc-{index}"), &vectors.next(8));
+            let tenant = if index % 30 == 0 { "rare" } else { "common" };
+            stored.document = stored.document.metadata("tenant", tenant);
+            store.upsert(stored).await.unwrap();
+        }
+        let rare = MetadataFilter::new().equals("tenant", "rare");
+        let found = store
+            .search_filtered(&vectors.next(8), 20, &rare)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 10);
+        assert!(
+            found
+                .iter()
+                .all(|found| found.document.metadata["tenant"] == "rare")
+        );
+        assert_eq!(store.delete_where(&rare).await.unwrap(), 10);
+        assert!(
+            store
+                .search_filtered(&vectors.next(8), 5, &rare)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(store.delete("doc-1").await.unwrap());
+        assert_eq!(store.len().await.unwrap(), 289);
+        assert!(store.delete_where(&MetadataFilter::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn upserts_replace_evict_and_validate_dimensions() {
+        let store = HnswVectorStore::new(HnswConfig::new().max_records(2)).unwrap();
+        assert!(store.search(&[1.0, 0.0], 1).await.unwrap().is_empty());
+        store.upsert(memory("a".into(), &[1.0, 0.0])).await.unwrap();
+        store.upsert(memory("b".into(), &[0.0, 1.0])).await.unwrap();
+        store.upsert(memory("a".into(), &[0.0, 1.0])).await.unwrap();
+        let found = store.search(&[1.0, 0.0], 2).await.unwrap();
+        assert!(found.iter().all(|found| found.score.abs() < 0.01));
+
+        store.upsert(memory("c".into(), &[1.0, 0.0])).await.unwrap();
+        let ids = store
+            .snapshot()
+            .unwrap()
+            .into_iter()
+            .map(|stored| stored.document.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a", "c"]);
+        let top = store.search(&[1.0, 0.1], 1).await.unwrap();
+        assert_eq!(top[0].document.id, "c");
+
+        assert!(matches!(
+            store.upsert(memory("d".into(), &[1.0, 0.0, 0.0])).await,
+            Err(SdkError::Validation(_))
+        ));
+        assert!(matches!(
+            store.search(&[1.0], 1).await,
+            Err(SdkError::Validation(_))
+        ));
+        store.clear().await.unwrap();
+        assert!(store.is_empty().await.unwrap());
+        store
+            .upsert(memory("d".into(), &[1.0, 0.0, 0.0]))
+            .await
+            .unwrap();
+
+        assert!(HnswVectorStore::new(HnswConfig::new().m(1)).is_err());
+        assert!(HnswVectorStore::new(HnswConfig::new().ef_search(0)).is_err());
+    }
+}
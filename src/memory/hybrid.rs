@@ -0,0 +1,697 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+
+use super::{MemoryDocument, MemoryMatch, MetadataFilter, SemanticMemory};
+use crate::security::DEFAULT_VECTOR_STORE_CAPACITY;
+use crate::{RerankRequest, Result, SdkError, ZhipuClient};
+
+const DEFAULT_K1: f32 = 1.2;
+const DEFAULT_B: f32 = 0.75;
+const DEFAULT_RRF_K: f32 = 60.0;
+const DEFAULT_CANDIDATES: usize = 20;
+
+/// A local BM25 index over [`MemoryDocument::text`].
+///
+/// Text is lowercased and split into alphanumeric runs; CJK characters are indexed one per
+/// token so Chinese and Japanese text matches without a segmenter. Like
+/// [`InMemoryVectorStore`](super::InMemoryVectorStore), the index evicts its oldest document
+/// once `max_records` is reached.
+#[derive(Debug, Clone)]
+pub struct Bm25Index {
+    k1: f32,
+    b: f32,
+    max_records: usize,
+    documents: HashMap<String, IndexedDocument>,
+    postings: HashMap<String, HashSet<String>>,
+    order: BTreeMap<u64, String>,
+    total_terms: usize,
+    next_sequence: u64,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedDocument {
+    document: MemoryDocument,
+    terms: HashMap<String, u32>,
+    length: usize,
+    sequence: u64,
+}
+
+impl Default for Bm25Index {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bm25Index {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_VECTOR_STORE_CAPACITY)
+    }
+
+    pub fn with_capacity(max_records: usize) -> Self {
+        Self {
+            k1: DEFAULT_K1,
+            b: DEFAULT_B,
+            max_records: max_records.max(1),
+            documents: HashMap::new(),
+            postings: HashMap::new(),
+            order: BTreeMap::new(),
+            total_terms: 0,
+            next_sequence: 0,
+        }
+    }
+
+    /// Overrides the term-frequency saturation (`k1`) and length normalisation (`b`) parameters.
+    pub fn parameters(mut self, k1: f32, b: f32) -> Result<Self> {
+        if !k1.is_finite() || k1 < 0.0 || !(0.0..=1.0).contains(&b) {
+            return Err(SdkError::Configuration(
+                "BM25 k1 must be non-negative and b must be within 0..=1".into(),
+            ));
+        }
+        self.k1 = k1;
+        self.b = b;
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn upsert(&mut self, document: MemoryDocument) {
+        self.remove(&document.id);
+        while self.documents.len() >= self.max_records {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+        let tokens = tokenize(&document.text);
+        let mut terms = HashMap::new();
+        for token in &tokens {
+            *terms.entry(token.clone()).or_insert(0) += 1;
+        }
+        for term in terms.keys() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(document.id.clone());
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.total_terms += tokens.len();
+        self.order.insert(sequence, document.id.clone());
+        self.documents.insert(
+            document.id.clone(),
+            IndexedDocument {
+                document,
+                terms,
+                length: tokens.len(),
+                sequence,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        let Some(indexed) = self.documents.remove(id) else {
+            return false;
+        };
+        for term in indexed.terms.keys() {
+            if let Some(ids) = self.postings.get_mut(term) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.order.remove(&indexed.sequence);
+        self.total_terms -= indexed.length;
+        true
+    }
+
+    pub fn remove_where(&mut self, filter: &MetadataFilter) -> usize {
+        let ids = self
+            .documents
+            .values()
+            .filter(|indexed| filter.matches(&indexed.document))
+            .map(|indexed| indexed.document.id.clone())
+            .collect::<Vec<_>>();
+        for id in &ids {
+            self.remove(id);
+        }
+        ids.len()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            k1: self.k1,
+            b: self.b,
+            ..Self::with_capacity(self.max_records)
+        };
+    }
+
+    /// Returns documents that share at least one term with `query`, best BM25 score first.
+    pub fn search(&self, query: &str, limit: usize, filter: &MetadataFilter) -> Vec<MemoryMatch> {
+        if limit == 0 || self.documents.is_empty() {
+            return Vec::new();
+        }
+        let count = self.documents.len() as f32;
+        let average_length = (self.total_terms as f32 / count).max(1.0);
+        let mut scores = HashMap::<&str, f32>::new();
+        let terms = tokenize(query).into_iter().collect::<HashSet<_>>();
+        for term in &terms {
+            let Some(ids) = self.postings.get(term) else {
+                continue;
+            };
+            let frequency = ids.len() as f32;
+            let idf = (1.0 + (count - frequency + 0.5) / (frequency + 0.5)).ln();
+            for id in ids {
+                let indexed = &self.documents[id];
+                if !filter.matches(&indexed.document) {
+                    continue;
+                }
+                let tf = indexed.terms[term] as f32;
+                let norm =
+                    self.k1 * (1.0 - self.b + self.b * indexed.length as f32 / average_length);
+                *scores.entry(id.as_str()).or_insert(0.0) +=
+                    idf * tf * (self.k1 + 1.0) / (tf + norm);
+            }
+        }
+        let mut matches = scores
+            .into_iter()
+            .map(|(id, score)| MemoryMatch {
+                document: self.documents[id].document.clone(),
+                score,
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|left, right| {
+            right
+                .score
+                .total_cmp(&left.score)
+                .then_with(|| left.document.id.cmp(&right.document.id))
+        });
+        matches.truncate(limit);
+        matches
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for character in text.chars() {
+        if is_cjk(character) {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            tokens.push(character.to_string());
+        } else if character.is_alphanumeric() {
+            current.extend(character.to_lowercase());
+        } else if !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn is_cjk(character: char) -> bool {
+    matches!(
+        character as u32,
+        0x3040..=0x30ff | 0x3400..=0x4dbf | 0x4e00..=0x9fff | 0xac00..=0xd7af | 0xf900..=0xfaff
+    )
+}
+
+/// Weights for reciprocal-rank fusion of vector and BM25 results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HybridConfig {
+    pub vector_weight: f32,
+    pub lexical_weight: f32,
+    pub rrf_k: f32,
+    /// Results fetched from each retriever before fusion; raised to the recall limit if smaller.
+    pub candidates: usize,
+    /// Capacity of the lexical index. Set it to the vector store's `max_records` so both evict
+    /// the same documents.
+    pub max_records: usize,
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HybridConfig {
+    pub fn new() -> Self {
+        Self {
+            vector_weight: 1.0,
+            lexical_weight: 1.0,
+            rrf_k: DEFAULT_RRF_K,
+            candidates: DEFAULT_CANDIDATES,
+            max_records: DEFAULT_VECTOR_STORE_CAPACITY,
+        }
+    }
+
+    pub fn vector_weight(mut self, value: f32) -> Self {
+        self.vector_weight = value;
+        self
+    }
+
+    pub fn lexical_weight(mut self, value: f32) -> Self {
+        self.lexical_weight = value;
+        self
+    }
+
+    pub fn rrf_k(mut self, value: f32) -> Self {
+        self.rrf_k = value;
+        self
+    }
+
+    pub fn candidates(mut self, value: usize) -> Self {
+        self.candidates = value;
+        self
+    }
+
+    pub fn max_records(mut self, value: usize) -> Self {
+        self.max_records = value;
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        let weights = [self.vector_weight, self.lexical_weight];
+        if weights
+            .iter()
+            .any(|weight| !weight.is_finite() || *weight < 0.0)
+            || weights.iter().all(|weight| *weight == 0.0)
+        {
+            return Err(SdkError::Configuration(
+                "hybrid weights must be non-negative and at least one must be positive".into(),
+            ));
+        }
+        if !self.rrf_k.is_finite()
+            || self.rrf_k <= 0.0
+            || self.candidates == 0
+            || self.max_records == 0
+        {
+            return Err(SdkError::Configuration(
+                "hybrid rrf_k, candidates, and max_records must be greater than zero".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    /// Returns one relevance score per document, in input order.
+    async fn rerank(&self, query: &str, documents: &[String]) -> Result<Vec<f32>>;
+}
+
+#[derive(Clone)]
+pub struct ZhipuReranker {
+    client: ZhipuClient,
+    model: String,
+}
+
+impl ZhipuReranker {
+    pub fn new(client: ZhipuClient, model: impl Into<String>) -> Self {
+        Self {
+            client,
+            model: model.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Reranker for ZhipuReranker {
+    async fn rerank(&self, query: &str, documents: &[String]) -> Result<Vec<f32>> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
+        let response = self
+            .client
+            .rerank(
+                &RerankRequest::new(&self.model, query, documents.iter().cloned())
+                    .top_n(documents.len() as u32)
+                    .return_documents(false),
+            )
+            .await?;
+        let mut scores = vec![None; documents.len()];
+        for result in response.results {
+            let slot = scores
+                .get_mut(result.index as usize)
+                .ok_or_else(|| SdkError::Decode {
+                    message: "rerank result index is out of range".into(),
+                    body: String::new(),
+                })?;
+            *slot = Some(result.relevance_score as f32);
+        }
+        scores
+            .into_iter()
+            .map(|score| {
+                score.ok_or_else(|| SdkError::Decode {
+                    message: "rerank response did not score every document".into(),
+                    body: String::new(),
+                })
+            })
+            .collect()
+    }
+}
+
+pub(super) struct HybridRecall {
+    pub(super) config: HybridConfig,
+    pub(super) index: RwLock<Bm25Index>,
+}
+
+impl HybridRecall {
+    pub(super) fn index(&self) -> Result<std::sync::RwLockReadGuard<'_, Bm25Index>> {
+        self.index
+            .read()
+            .map_err(|_| SdkError::Configuration("lexical index lock is poisoned".into()))
+    }
+
+    pub(super) fn index_mut(&self) -> Result<std::sync::RwLockWriteGuard<'_, Bm25Index>> {
+        self.index
+            .write()
+            .map_err(|_| SdkError::Configuration("lexical index lock is poisoned".into()))
+    }
+}
+
+impl SemanticMemory {
+    /// Fuses vector recall with a local BM25 index so exact identifiers such as ticket numbers
+    /// and error codes are found even when embeddings miss them.
+    ///
+    /// The index lives in memory only. Over a persistent store, call
+    /// [`SemanticMemory::index_documents`] after opening it, or recall stays vector-only for
+    /// documents stored before the restart. Lexical hits the store no longer holds are dropped
+    /// at recall time when the store implements [`VectorStore::contains`](super::VectorStore::contains).
+    pub fn hybrid(mut self, config: HybridConfig) -> Result<Self> {
+        config.validate()?;
+        self.hybrid = Some(HybridRecall {
+            index: RwLock::new(Bm25Index::with_capacity(config.max_records)),
+            config,
+        });
+        Ok(self)
+    }
+
+    /// Reorders fused candidates with a reranker such as [`ZhipuReranker`] before truncating.
+    pub fn reranker(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.reranker = Some(reranker);
+        self
+    }
+
+    /// Seeds the lexical index with documents already held by a persistent vector store, e.g.
+    /// `store.snapshot()?.into_iter().map(|memory| memory.document)` from a reopened
+    /// [`FileVectorStore`](super::FileVectorStore).
+    pub fn index_documents(
+        &self,
+        documents: impl IntoIterator<Item = MemoryDocument>,
+    ) -> Result<()> {
+        if let Some(hybrid) = &self.hybrid {
+            let mut index = hybrid.index_mut()?;
+            for document in documents {
+                index.upsert(document);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) async fn rank(
+        &self,
+        query: &str,
+        limit: usize,
+        filter: &MetadataFilter,
+        vector: &[f32],
+    ) -> Result<Vec<MemoryMatch>> {
+        let candidates = match &self.hybrid {
+            Some(hybrid) => hybrid.config.candidates.max(limit),
+            None if self.reranker.is_some() => DEFAULT_CANDIDATES.max(limit),
+            None => limit,
+        };
+        let semantic = self
+            .store
+            .search_filtered(vector, candidates, filter)
+            .await?;
+        let mut matches = match &self.hybrid {
+            Some(hybrid) => {
+                let lexical = hybrid.index()?.search(query, candidates, filter);
+                let lexical = self.live_matches(hybrid, &semantic, lexical).await?;
+                reciprocal_rank_fusion(&hybrid.config, semantic, lexical)
+            }
+            None => semantic,
+        };
+        if let Some(reranker) = &self.reranker
+            && !matches.is_empty()
+        {
+            let texts = matches
+                .iter()
+                .map(|found| found.document.text.clone())
+                .collect::<Vec<_>>();
+            let scores = reranker.rerank(query, &texts).await?;
+            if scores.len() != matches.len() {
+                return Err(SdkError::Decode {
+                    message: "reranker returned a different number of scores".into(),
+                    body: String::new(),
+                });
+            }
+            for (found, score) in matches.iter_mut().zip(scores) {
+                found.score = score;
+            }
+            matches.sort_by(|left, right| right.score.total_cmp(&left.score));
+        }
+        matches.truncate(limit);
+        Ok(matches)
+    }
+}
+
+impl SemanticMemory {
+    /// Drops lexical hits whose records the vector store has evicted, and removes them from the
+    /// index so they are not checked again.
+    async fn live_matches(
+        &self,
+        hybrid: &HybridRecall,
+        semantic: &[MemoryMatch],
+        lexical: Vec<MemoryMatch>,
+    ) -> Result<Vec<MemoryMatch>> {
+        let mut live = Vec::with_capacity(lexical.len());
+        for found in lexical {
+            let id = &found.document.id;
+            if semantic.iter().any(|hit| &hit.document.id == id) {
+                live.push(found);
+                continue;
+            }
+            match self.store.contains(id).await {
+                Ok(true) | Err(SdkError::Unsupported(_)) => live.push(found),
+                Ok(false) => {
+                    hybrid.index_mut()?.remove(id);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(live)
+    }
+}
+
+fn reciprocal_rank_fusion(
+    config: &HybridConfig,
+    semantic: Vec<MemoryMatch>,
+    lexical: Vec<MemoryMatch>,
+) -> Vec<MemoryMatch> {
+    let mut fused = HashMap::<String, MemoryMatch>::new();
+    for (weight, results) in [
+        (config.vector_weight, semantic),
+        (config.lexical_weight, lexical),
+    ] {
+        for (rank, found) in results.into_iter().enumerate() {
+            let score = weight / (config.rrf_k + rank as f32 + 1.0);
+            fused
+                .entry(found.document.id.clone())
+                .and_modify(|existing| existing.score += score)
+                .or_insert(MemoryMatch {
+                    document: found.document,
+                    score,
+                });
+        }
+    }
+    let mut fused = fused.into_values().collect::<Vec<_>>();
+    fused.sort_by(|left, right| {
+        right
+            .score
+            .total_cmp(&left.score)
+            .then_with(|| left.document.id.cmp(&right.document.id))
+    });
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConversationMemory, EmbeddingProvider, InMemoryVectorStore, VectorStore};
+
+    /// Embeds every text identically so only the lexical index can tell documents apart.
+    struct FlatEmbeddings;
+
+    #[async_trait]
+    impl EmbeddingProvider for FlatEmbeddings {
+        async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(inputs.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+    }
+
+    struct LengthReranker;
+
+    #[async_trait]
+    impl Reranker for LengthReranker {
+        async fn rerank(&self, _: &str, documents: &[String]) -> Result<Vec<f32>> {
+            Ok(documents
+                .iter()
+                .map(|document| document.len() as f32)
+                .collect())
+        }
+    }
+
+    #[test]
+    fn bm25_ranks_exact_terms_and_handles_cjk() {
+        assert_eq!(
+            tokenize("Error E-1042: 数据库超时"),
+            ["error", "e", "1042", "数", "据", "库", "超", "时"]
+        );
+        let mut index = Bm25Index::with_capacity(3);
+        index.upsert(MemoryDocument::new("a", "ticket 1042 database timeout"));
+        index.upsert(MemoryDocument::new("b", "ticket 7 slow page"));
+        index.upsert(MemoryDocument::new("c", "数据库超时").metadata("lang", "zh"));
+        let found = index.search("1042", 5, &MetadataFilter::new());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].document.id, "a");
+        assert_eq!(index.search("ticket", 5, &MetadataFilter::new()).len(), 2);
+        let zh = MetadataFilter::new().equals("lang", "zh");
+        assert_eq!(index.search("数据库", 5, &zh)[0].document.id, "c");
+        assert!(index.search("ticket", 5, &zh).is_empty());
+
+        index.upsert(MemoryDocument::new("d", "fresh"));
+        assert_eq!(index.len(), 3);
+        assert!(index.search("1042", 5, &MetadataFilter::new()).is_empty());
+        assert!(index.remove("d"));
+        assert_eq!(index.remove_where(&zh), 1);
+        index.clear();
+        assert!(index.is_empty());
+        assert!(Bm25Index::new().parameters(1.2, 2.0).is_err());
+    }
+
+    #[tokio::test]
+    async fn hybrid_recall_fuses_lexical_and_vector_results_offline() {
+        let memory = SemanticMemory::new(
+            Arc::new(FlatEmbeddings),
+            Arc::new(InMemoryVectorStore::new()),
+        )
+        .hybrid(HybridConfig::new().vector_weight(0.5))
+        .unwrap();
+        for (id, text) in [
+            ("a", "deploy notes"),
+            ("b", "incident INC-4821 root cause"),
+            ("c", "weekly sync"),
+        ] {
+            memory
+                .remember(MemoryDocument::new(id, text).metadata("team", "ops"))
+                .await
+                .unwrap();
+        }
+        let found = memory.recall("INC-4821", 2).await.unwrap();
+        assert_eq!(found[0].document.id, "b");
+        assert!(found[0].score > found[1].score);
+
+        assert!(memory.forget("b").await.unwrap());
+        let found = memory.recall("INC-4821", 3).await.unwrap();
+        assert!(found.iter().all(|found| found.document.id != "b"));
+
+        memory.clear().await.unwrap();
+        assert!(memory.recall("INC-4821", 3).await.unwrap().is_empty());
+
+        let store = Arc::new(InMemoryVectorStore::new());
+        SemanticMemory::new(Arc::new(FlatEmbeddings), store.clone())
+            .remember(MemoryDocument::new("z", "INC-4821 restored"))
+            .await
+            .unwrap();
+        store
+            .upsert(crate::StoredMemory {
+                document: MemoryDocument::new("y", "unrelated"),
+                embedding: crate::QuantizedVector::compress(&[1.0, 0.0]).unwrap(),
+            })
+            .await
+            .unwrap();
+        let reopened = SemanticMemory::new(Arc::new(FlatEmbeddings), store.clone())
+            .hybrid(HybridConfig::new())
+            .unwrap();
+        reopened
+            .index_documents(
+                store
+                    .snapshot()
+                    .unwrap()
+                    .into_iter()
+                    .map(|stored| stored.document),
+            )
+            .unwrap();
+        assert_eq!(
+            reopened.recall("INC-4821", 1).await.unwrap()[0].document.id,
+            "z"
+        );
+
+        assert!(
+            SemanticMemory::new(
+                Arc::new(FlatEmbeddings),
+                Arc::new(InMemoryVectorStore::new())
+            )
+            .hybrid(HybridConfig::new().vector_weight(0.0).lexical_weight(0.0))
+            .is_err()
+        );
+        assert!(HybridConfig::new().max_records(0).validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn hybrid_recall_drops_lexical_hits_the_store_evicted() {
+        let memory = SemanticMemory::new(
+            Arc::new(FlatEmbeddings),
+            Arc::new(InMemoryVectorStore::with_capacity(2)),
+        )
+        .hybrid(HybridConfig::new())
+        .unwrap();
+        for (id, text) in [("a", "INC-77 outage"), ("b", "notes"), ("c", "sync")] {
+            memory
+                .remember(MemoryDocument::new(id, text))
+                .await
+                .unwrap();
+        }
+        let found = memory.recall("INC-77", 3).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|found| found.document.id != "a"));
+        assert_eq!(memory.hybrid.as_ref().unwrap().index().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reranker_reorders_fused_candidates() {
+        let memory = SemanticMemory::new(
+            Arc::new(FlatEmbeddings),
+            Arc::new(InMemoryVectorStore::new()),
+        )
+        .hybrid(HybridConfig::new())
+        .unwrap()
+        .reranker(Arc::new(LengthReranker));
+        memory
+            .remember(MemoryDocument::new("short", "cache miss"))
+            .await
+            .unwrap();
+        memory
+            .remember(MemoryDocument::new("long", "cache miss on the hot path"))
+            .await
+            .unwrap();
+        let found = memory.recall("cache", 1).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].document.id, "long");
+        assert_eq!(found[0].score, 26.0);
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use nextjson::NsonSerialize as Serialize;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, Response, StatusCode};
+
+use crate::{Result, SdkError};
+
+/// A request about to be sent, after authentication and default headers are applied.
+#[derive(Debug, Clone)]
+pub struct OutgoingRequest {
+    pub method: Method,
+    /// Path relative to the base URL; validated again after every middleware has run.
+    pub path: String,
+    pub headers: HeaderMap,
+    /// `None` for bodiless requests and for multipart uploads, whose form cannot be inspected.
+    pub body: Option<Bytes>,
+}
+
+/// What a middleware sees once a request attempt has finished.
+#[derive(Debug, Clone)]
+pub struct ResponseInfo {
+    pub method: Method,
+    pub path: String,
+    /// `None` when the attempt failed before any response arrived.
+    pub status: Option<StatusCode>,
+    pub headers: HeaderMap,
+    pub latency: Duration,
+    /// Whether an inner middleware answered instead of the server.
+    pub synthetic: bool,
+}
+
+/// A response produced by a middleware instead of the network.
+#[derive(Debug, Clone)]
+pub struct SyntheticResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl SyntheticResponse {
+    pub fn new(status: StatusCode, body: impl Into<Bytes>) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn json<T: Serialize + ?Sized>(status: StatusCode, value: &T) -> Result<Self> {
+        let body = nextjson::to_vec(value)
+            .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+        Ok(Self::new(status, body)
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json")))
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub(crate) fn into_response(self) -> Response {
+        let mut response = http::Response::new(self.body);
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response.into()
+    }
+}
+
+/// Hooks around every request attempt `Transport` makes, including retries and failover.
+///
+/// Middleware runs in the order it was added to `HttpConfig` on the way out and in reverse on the
+/// way back. Returning a [`SyntheticResponse`] from `on_request` skips the network and every
+/// later middleware; earlier ones still observe the response.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    async fn on_request(
+        &self,
+        _request: &mut OutgoingRequest,
+    ) -> Result<Option<SyntheticResponse>> {
+        Ok(None)
+    }
+
+    async fn on_response(&self, _response: &ResponseInfo) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use nextjson::NsonDeserialize as Deserialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::sleep;
+
+use crate::transport::path_has_prefix;
+use crate::usage::usage_events;
+use crate::{Result, SdkError, Usage};
+
+/// Limits applied to every request, or to requests under one endpoint path.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimits {
+    /// Sustained request rate; up to one second's worth may be sent as a burst.
+    pub requests_per_second: Option<f64>,
+    /// Tokens per minute, estimated from request size and settled against `Usage` when the
+    /// response reports it.
+    pub tokens_per_minute: Option<u64>,
+    pub max_in_flight: Option<usize>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn requests_per_second(mut self, value: f64) -> Self {
+        self.requests_per_second = Some(value);
+        self
+    }
+
+    pub fn tokens_per_minute(mut self, value: u64) -> Self {
+        self.tokens_per_minute = Some(value);
+        self
+    }
+
+    pub fn max_in_flight(mut self, value: usize) -> Self {
+        self.max_in_flight = Some(value);
+        self
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self
+            .requests_per_second
+            .is_some_and(|value| !value.is_finite() || value <= 0.0)
+        {
+            return Err(SdkError::Configuration(
+                "requests_per_second must be a positive number".into(),
+            ));
+        }
+        if self.tokens_per_minute == Some(0) {
+            return Err(SdkError::Configuration(
+                "tokens_per_minute must be greater than zero".into(),
+            ));
+        }
+        if self.max_in_flight == Some(0) {
+            return Err(SdkError::Configuration(
+                "max_in_flight must be greater than zero".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A client-side limiter shared by every clone of the clients and transports built with it.
+///
+/// Requests wait until the global limits and the limits of the longest matching endpoint prefix
+/// all have room, so bursts from many tasks are smoothed out before the server answers 429.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    global: Arc<Lane>,
+    endpoints: Vec<(String, Arc<Lane>)>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Result<Self> {
+        Ok(Self {
+            global: Arc::new(Lane::new(&limits)?),
+            endpoints: Vec::new(),
+        })
+    }
+
+    /// Adds limits for request paths starting with `path_prefix`, such as `"chat/completions"`.
+    pub fn endpoint(mut self, path_prefix: impl Into<String>, limits: RateLimits) -> Result<Self> {
+        let prefix = path_prefix.into().trim_matches('/').to_owned();
+        if prefix.is_empty() {
+            return Err(SdkError::Configuration(
+                "rate limit endpoint prefix cannot be empty".into(),
+            ));
+        }
+        let lane = Arc::new(Lane::new(&limits)?);
+        match self
+            .endpoints
+            .iter_mut()
+            .find(|(value, _)| *value == prefix)
+        {
+            Some((_, existing)) => *existing = lane,
+            None => self.endpoints.push((prefix, lane)),
+        }
+        Ok(self)
+    }
+
+    /// Waits until a request to `path` carrying `body_bytes` may be sent.
+    ///
+    /// Rate buckets are drawn before in-flight slots are taken, so a request waiting for
+    /// bucket refill never holds a slot that a sendable request could use.
+    pub(crate) async fn acquire(&self, path: &str, body_bytes: usize) -> Result<RatePermit> {
+        let lanes = self.lanes(path);
+        let estimate = estimate_tokens(body_bytes);
+        loop {
+            let wait = {
+                let mut guards = Vec::new();
+                for lane in &lanes {
+                    if let Some(bucket) = &lane.requests {
+                        guards.push((lock(bucket)?, 1.0));
+                    }
+                    if let Some(bucket) = &lane.tokens {
+                        guards.push((lock(bucket)?, estimate));
+                    }
+                }
+                let now = Instant::now();
+                let wait = guards
+                    .iter_mut()
+                    .map(|(bucket, cost)| bucket.wait(now, *cost))
+                    .max()
+                    .unwrap_or_default();
+                if wait.is_zero() {
+                    for (bucket, cost) in &mut guards {
+                        bucket.take(*cost);
+                    }
+                    break;
+                }
+                wait
+            };
+            sleep(wait).await;
+        }
+        let mut in_flight = Vec::new();
+        for lane in &lanes {
+            if let Some(semaphore) = &lane.in_flight {
+                in_flight.push(semaphore.clone().acquire_owned().await.map_err(|_| {
+                    SdkError::Configuration("rate limiter semaphore is closed".into())
+                })?);
+            }
+        }
+        Ok(RatePermit {
+            lanes,
+            charged: Mutex::new(estimate),
+            line: Mutex::default(),
+            _in_flight: in_flight,
+        })
+    }
+
+    fn lanes(&self, path: &str) -> Vec<Arc<Lane>> {
+        let mut lanes = vec![self.global.clone()];
+        if let Some((_, lane)) = self
+            .endpoints
+            .iter()
+            .filter(|(prefix, _)| path_has_prefix(path, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+        {
+            lanes.push(lane.clone());
+        }
+        lanes
+    }
+
+    #[cfg(test)]
+    pub(crate) fn available_tokens(&self) -> Option<f64> {
+        let bucket = self.global.tokens.as_ref()?;
+        let mut bucket = bucket.lock().unwrap();
+        bucket.refill(Instant::now());
+        Some(bucket.tokens)
+    }
+}
+
+/// Held for the lifetime of a request; releases its in-flight slots when dropped.
+#[derive(Debug, Default)]
+pub(crate) struct RatePermit {
+    lanes: Vec<Arc<Lane>>,
+    /// Tokens taken from the buckets so far: the estimate until usage is reported.
+    charged: Mutex<f64>,
+    /// Unterminated SSE line carried over between stream chunks.
+    line: Mutex<Vec<u8>>,
+    _in_flight: Vec<OwnedSemaphorePermit>,
+}
+
+impl RatePermit {
+    pub(crate) fn tracks_tokens(&self) -> bool {
+        self.lanes.iter().any(|lane| lane.tokens.is_some())
+    }
+
+    /// Replaces the up-front token estimate with the usage a JSON response reported.
+    pub(crate) fn settle(&self, body: &[u8]) {
+        if !self.tracks_tokens() {
+            return;
+        }
+        if let Ok(UsageProbe { usage: Some(usage) }) = nextjson::from_slice::<UsageProbe>(body) {
+            self.charge(usage.total_tokens as f64);
+        }
+    }
+
+    /// Settles a stream from the SSE events that report `usage`; the last one wins.
+    pub(crate) fn observe(&self, chunk: &[u8]) {
+        if !self.tracks_tokens() {
+            return;
+        }
+        let Ok(mut line) = self.line.lock() else {
+            return;
+        };
+        for data in usage_events(&mut line, chunk) {
+            self.settle(&data);
+        }
+    }
+
+    fn charge(&self, total: f64) {
+        let Ok(mut charged) = self.charged.lock() else {
+            return;
+        };
+        let delta = total - std::mem::replace(&mut *charged, total);
+        for lane in &self.lanes {
+            if let Some(bucket) = &lane.tokens
+                && let Ok(mut bucket) = bucket.lock()
+            {
+                bucket.take(delta);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UsageProbe {
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Debug)]
+struct Lane {
+    requests: Option<Mutex<Bucket>>,
+    tokens: Option<Mutex<Bucket>>,
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+impl Lane {
+    fn new(limits: &RateLimits) -> Result<Self> {
+        limits.validate()?;
+        Ok(Self {
+            requests: limits
+                .requests_per_second
+                .map(|rate| Mutex::new(Bucket::new(rate, rate))),
+            tokens: limits
+                .tokens_per_minute
+                .map(|limit| Mutex::new(Bucket::new(limit as f64, limit as f64 / 60.0))),
+            in_flight: limits
+                .max_in_flight
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: f64, per_second: f64) -> Self {
+        Self {
+            capacity,
+            tokens: capacity,
+            per_second,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Time until `cost` fits. Costs larger than the bucket only wait for a full bucket and then
+    /// run it into debt, so one oversized request cannot block forever.
+    fn wait(&mut self, now: Instant, cost: f64) -> Duration {
+        self.refill(now);
+        let needed = cost.min(self.capacity);
+        if self.tokens >= needed {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((needed - self.tokens) / self.per_second)
+        }
+    }
+
+    fn take(&mut self, cost: f64) {
+        self.tokens = (self.tokens - cost).min(self.capacity);
+    }
+}
+
+fn lock(bucket: &Mutex<Bucket>) -> Result<std::sync::MutexGuard<'_, Bucket>> {
+    bucket
+        .lock()
+        .map_err(|_| SdkError::Configuration("rate limiter lock is poisoned".into()))
+}
+
+/// Roughly four request bytes per token.
+fn estimate_tokens(body_bytes: usize) -> f64 {
+    body_bytes.div_ceil(4) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_refill_and_let_oversized_costs_run_into_debt() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            capacity: 10.0,
+            tokens: 10.0,
+            per_second: 5.0,
+            updated: start,
+        };
+        assert_eq!(bucket.wait(start, 4.0), Duration::ZERO);
+        bucket.take(4.0);
+        assert_eq!(bucket.wait(start, 8.0), Duration::from_millis(400));
+        assert_eq!(
+            bucket.wait(start + Duration::from_millis(400), 8.0),
+            Duration::ZERO
+        );
+        bucket.take(25.0);
+        assert_eq!(bucket.tokens, -17.0);
+        assert_eq!(
+            bucket
+                .wait(start + Duration::from_millis(400), 25.0)
+                .as_millis(),
+            5_400
+        );
+        bucket.take(-100.0);
+        assert_eq!(bucket.tokens, 10.0);
+    }
+
+    #[test]
+    fn rejects_invalid_limits_and_matches_longest_endpoint_prefix() {
+        assert!(RateLimiter::new(RateLimits::new().requests_per_second(0.0)).is_err());
+        assert!(RateLimiter::new(RateLimits::new().requests_per_second(f64::NAN)).is_err());
+        assert!(RateLimiter::new(RateLimits::new().tokens_per_minute(0)).is_err());
+        assert!(RateLimiter::new(RateLimits::new().max_in_flight(0)).is_err());
+        let limiter = RateLimiter::new(RateLimits::new())
+            .unwrap()
+            .endpoint("files", RateLimits::new().max_in_flight(1))
+            .unwrap()
+            .endpoint("/files/content/", RateLimits::new().max_in_flight(2))
+            .unwrap();
+        assert!(limiter.clone().endpoint("/", RateLimits::new()).is_err());
+        assert_eq!(limiter.lanes("chat/completions").len(), 1);
+        assert_eq!(limiter.lanes("filesystem").len(), 1);
+        let lanes = limiter.lanes("/files/content/abc");
+        assert_eq!(lanes.len(), 2);
+        assert!(Arc::ptr_eq(&lanes[1], &limiter.endpoints[1].1));
+        assert!(Arc::ptr_eq(
+            &limiter.lanes("files?page=1")[1],
+            &limiter.endpoints[0].1
+        ));
+    }
+
+    #[tokio::test]
+    async fn clones_share_in_flight_slots_and_settle_usage() {
+        let limiter = RateLimiter::new(RateLimits::new().tokens_per_minute(1_000))
+            .unwrap()
+            .endpoint("files", RateLimits::new().max_in_flight(1))
+            .unwrap();
+        let clone = limiter.clone();
+        let held = limiter.acquire("files/a", 0).await.unwrap();
+        let blocked = tokio::time::timeout(Duration::from_millis(20), clone.acquire("files/b", 0));
+        assert!(blocked.await.is_err());
+        clone.acquire("chat/completions", 0).await.unwrap();
+        drop(held);
+        clone.acquire("files/b", 0).await.unwrap();
+
+        let permit = limiter.acquire("chat/completions", 400).await.unwrap();
+        assert!(limiter.available_tokens().unwrap() < 901.0);
+        permit.settle(br#"{"usage":{"total_tokens":700}}"#);
+        let available = limiter.available_tokens().unwrap();
+        assert!((300.0..310.0).contains(&available), "{available}");
+        permit.settle(b"not json");
+        permit.settle(br#"{"usage":{"total_tokens":700}}"#);
+        assert!(clone.available_tokens().unwrap() < 310.0);
+        assert!(clone.available_tokens().unwrap() > 300.0);
+    }
+
+    #[tokio::test]
+    async fn streams_settle_from_the_final_usage_event() {
+        let limiter = RateLimiter::new(RateLimits::new().tokens_per_minute(1_000)).unwrap();
+        let permit = limiter.acquire("chat/completions", 400).await.unwrap();
+        permit.observe(b"data: {\"choices\":[]}\n\ndata: {\"usage\":{\"total_");
+        assert!(limiter.available_tokens().unwrap() < 901.0);
+        permit.observe(b"tokens\":50}}\n\ndata: {\"usage\":{\"total_tokens\":600}}\n\n");
+        let available = limiter.available_tokens().unwrap();
+        assert!((400.0..410.0).contains(&available), "{available}");
+    }
+
+    #[tokio::test]
+    async fn waits_for_buckets_without_holding_in_flight_slots() {
+        let limiter =
+            RateLimiter::new(RateLimits::new().requests_per_second(1.0).max_in_flight(1)).unwrap();
+        drop(limiter.acquire("chat/completions", 0).await.unwrap());
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire("chat/completions", 0).await.map(drop) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            limiter
+                .global
+                .in_flight
+                .as_ref()
+                .unwrap()
+                .available_permits(),
+            1
+        );
+        waiting.await.unwrap().unwrap();
+    }
+}
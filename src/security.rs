@@ -11,9 +11,9 @@ pub const DEFAULT_MAX_SSE_DATA_LINES: usize = 4_096;
 pub const DEFAULT_MAX_MEMORY_TEXT_BYTES: usize = 8 * 1024;
 pub const DEFAULT_VECTOR_STORE_CAPACITY: usize = 10_000;
 
-#[cfg(feature = "tools")]
+#[cfg(any(feature = "agents", feature = "rag", feature = "tools"))]
 pub const DEFAULT_MAX_TOOL_ARGUMENTS_BYTES: usize = 1024 * 1024;
-#[cfg(feature = "tools")]
+#[cfg(any(feature = "agents", feature = "rag", feature = "tools"))]
 pub const DEFAULT_MAX_PENDING_TOOL_CALLS: usize = 128;
 
 #[cfg(any(feature = "agents", feature = "rag"))]
@@ -23,6 +23,9 @@ pub const DEFAULT_MAX_AGENT_STEPS: u32 = 64;
 #[cfg(any(feature = "agents", feature = "rag"))]
 pub const DEFAULT_MAX_TOOL_EXECUTIONS: usize = 64;
 
+#[cfg(feature = "mcp")]
+pub const DEFAULT_MAX_MCP_STDERR_BYTES: usize = 64 * 1024;
+
 #[cfg(feature = "realtime")]
 pub const DEFAULT_MAX_WS_MESSAGE_BYTES: usize = 8 * 1024 * 1024;
 #[cfg(feature = "realtime")]
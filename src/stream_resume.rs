@@ -0,0 +1,436 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_stream::try_stream;
+use futures_util::{Stream, StreamExt};
+use nextjson::Value;
+use tokio::time::sleep;
+
+use crate::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatMessage, ChatProvider, ChatStream,
+    ResponseContent, Result, SdkError,
+};
+
+const CONTINUE_PROMPT: &str = "Your previous reply was cut off by a network error. Continue it exactly where it stopped, without repeating any text or adding commentary.";
+
+/// How a reissued request asks the model to continue its partial reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamContinuation {
+    /// Appends the partial reply as an assistant message followed by a short user instruction
+    /// to continue it. Works with any chat endpoint.
+    #[default]
+    ContinuePrompt,
+    /// Ends the request with the partial reply as an assistant message flagged `"prefix": true`,
+    /// for endpoints that support assistant prefix completion.
+    AssistantPrefix,
+}
+
+/// Opt-in policy for resuming a chat stream that fails before it finishes.
+#[derive(Debug, Clone)]
+pub struct StreamResumePolicy {
+    pub max_resumes: u32,
+    pub delay: Duration,
+    pub continuation: StreamContinuation,
+}
+
+impl Default for StreamResumePolicy {
+    fn default() -> Self {
+        Self {
+            max_resumes: 2,
+            delay: Duration::from_millis(500),
+            continuation: StreamContinuation::ContinuePrompt,
+        }
+    }
+}
+
+impl StreamResumePolicy {
+    pub fn new(max_resumes: u32) -> Self {
+        Self {
+            max_resumes,
+            ..Self::default()
+        }
+    }
+
+    pub fn delay(mut self, value: Duration) -> Self {
+        self.delay = value;
+        self
+    }
+
+    pub fn continuation(mut self, value: StreamContinuation) -> Self {
+        self.continuation = value;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResilientStreamEvent {
+    Chunk(Box<ChatCompletionChunk>),
+    /// The stream failed and was reissued; later chunks continue the same reply.
+    Resumed {
+        attempt: u32,
+        /// Bytes of assistant text delivered before the failure and sent back as the prefix.
+        resumed_from_bytes: usize,
+        reason: String,
+    },
+}
+
+/// A chat stream that reissues the request with the partial reply as a continuation when a
+/// transport error or timeout interrupts it, or when the stream ends before a finish reason.
+/// Decode and size-limit errors are returned as is, since repeating the request would not
+/// fix them.
+///
+/// Chunks from a resumed request keep the first response id and drop the repeated assistant
+/// role, so consumers see one logical reply. Streams with tool call deltas or more than one
+/// choice are not resumed, since a partial call cannot be continued safely. Each resumed request
+/// reports its own usage.
+pub struct ResilientChatStream {
+    inner: Pin<Box<dyn Stream<Item = Result<ResilientStreamEvent>> + Send>>,
+}
+
+impl ResilientChatStream {
+    /// Opens the first stream. Errors opening it are returned directly; the transport
+    /// `RetryPolicy` covers that request.
+    pub async fn open(
+        provider: Arc<dyn ChatProvider>,
+        request: ChatCompletionRequest,
+        policy: StreamResumePolicy,
+    ) -> Result<Self> {
+        let source = provider.stream(request.clone()).await?;
+        Ok(Self {
+            inner: Box::pin(resilient_stream(provider, request, policy, source)),
+        })
+    }
+
+    /// Drops resume markers and yields plain chunks.
+    pub fn into_chat_stream(self) -> ChatStream {
+        Box::pin(self.filter_map(|event| async move {
+            match event {
+                Ok(ResilientStreamEvent::Chunk(chunk)) => Some(Ok(*chunk)),
+                Ok(ResilientStreamEvent::Resumed { .. }) => None,
+                Err(error) => Some(Err(error)),
+            }
+        }))
+    }
+}
+
+impl Stream for ResilientChatStream {
+    type Item = Result<ResilientStreamEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().inner.as_mut().poll_next(cx)
+    }
+}
+
+#[derive(Debug)]
+struct Stitch {
+    id: Option<String>,
+    text: String,
+    reasoning: String,
+    finished: bool,
+    resumable: bool,
+    resumed: bool,
+}
+
+impl Stitch {
+    fn apply(&mut self, mut chunk: ChatCompletionChunk) -> ChatCompletionChunk {
+        match &self.id {
+            Some(id) => chunk.id.clone_from(id),
+            None if !chunk.id.is_empty() => self.id = Some(chunk.id.clone()),
+            None => {}
+        }
+        for choice in &mut chunk.choices {
+            if choice.index != 0 || !choice.delta.tool_calls.is_empty() {
+                self.resumable = false;
+            }
+            if self.resumed {
+                choice.delta.role = None;
+            }
+            if let Some(text) = choice
+                .delta
+                .content
+                .as_ref()
+                .and_then(ResponseContent::joined_text)
+            {
+                self.text.push_str(&text);
+            }
+            if let Some(reasoning) = &choice.delta.reasoning_content {
+                self.reasoning.push_str(reasoning);
+            }
+            if choice.finish_reason.is_some() {
+                self.finished = true;
+            }
+        }
+        chunk
+    }
+
+    fn can_resume(&self) -> bool {
+        self.resumable && !self.finished
+    }
+
+    fn continuation(
+        &self,
+        request: &ChatCompletionRequest,
+        mode: StreamContinuation,
+    ) -> ChatCompletionRequest {
+        let mut request = request.clone();
+        if self.text.is_empty() && self.reasoning.is_empty() {
+            return request;
+        }
+        let mut partial = ChatMessage::assistant(self.text.clone());
+        if !self.reasoning.is_empty() {
+            partial.reasoning_content = Some(self.reasoning.clone());
+        }
+        match mode {
+            StreamContinuation::ContinuePrompt => {
+                request.messages.push(partial);
+                request.messages.push(ChatMessage::user(CONTINUE_PROMPT));
+            }
+            StreamContinuation::AssistantPrefix => {
+                partial.extra.insert("prefix".into(), Value::Bool(true));
+                request.messages.push(partial);
+            }
+        }
+        request
+    }
+}
+
+fn resilient_stream(
+    provider: Arc<dyn ChatProvider>,
+    request: ChatCompletionRequest,
+    policy: StreamResumePolicy,
+    mut source: ChatStream,
+) -> impl Stream<Item = Result<ResilientStreamEvent>> + Send {
+    try_stream! {
+        let mut stitch = Stitch {
+            id: None,
+            text: String::new(),
+            reasoning: String::new(),
+            finished: false,
+            resumable: true,
+            resumed: false,
+        };
+        let mut attempt = 0;
+        loop {
+            let mut error = match source.next().await {
+                Some(Ok(chunk)) => {
+                    yield ResilientStreamEvent::Chunk(Box::new(stitch.apply(chunk)));
+                    continue;
+                }
+                Some(Err(error)) if stitch.can_resume() && is_interruption(&error) => error,
+                Some(Err(error)) => {
+                    Err(error)?;
+                    unreachable!();
+                }
+                None if stitch.can_resume() => {
+                    SdkError::Stream("chat stream ended before a finish reason".into())
+                }
+                None => break,
+            };
+            loop {
+                if attempt >= policy.max_resumes {
+                    Err(error)?;
+                    unreachable!();
+                }
+                attempt += 1;
+                sleep(policy.delay).await;
+                let continuation = stitch.continuation(&request, policy.continuation);
+                match provider.stream(continuation).await {
+                    Ok(next) => {
+                        source = next;
+                        break;
+                    }
+                    Err(next) if is_interruption(&next) => error = next,
+                    Err(next) => {
+                        Err(next)?;
+                        unreachable!();
+                    }
+                }
+            }
+            stitch.resumed = true;
+            yield ResilientStreamEvent::Resumed {
+                attempt,
+                resumed_from_bytes: stitch.text.len(),
+                reason: error.to_string(),
+            };
+        }
+    }
+}
+
+/// Failures where the connection, not the payload, was at fault.
+fn is_interruption(error: &SdkError) -> bool {
+    matches!(error, SdkError::Transport(_) | SdkError::Timeout(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use nextjson::json;
+
+    use super::*;
+    use crate::{ChatCompletionResponse, MessageRole, ProviderCapabilities};
+
+    struct ScriptedProvider {
+        scripts: Mutex<VecDeque<Vec<Result<ChatCompletionChunk>>>>,
+        requests: Mutex<Vec<ChatCompletionRequest>>,
+    }
+
+    impl ScriptedProvider {
+        fn new(scripts: Vec<Vec<Result<ChatCompletionChunk>>>) -> Arc<Self> {
+            Arc::new(Self {
+                scripts: Mutex::new(scripts.into()),
+                requests: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl ChatProvider for ScriptedProvider {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities::openai_compatible()
+        }
+
+        async fn complete(&self, _: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+            Err(SdkError::Unsupported("complete".into()))
+        }
+
+        async fn stream(&self, request: ChatCompletionRequest) -> Result<ChatStream> {
+            self.requests.lock().unwrap().push(request);
+            let script = self
+                .scripts
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| SdkError::Stream("no script left".into()))?;
+            Ok(Box::pin(futures_util::stream::iter(script)))
+        }
+    }
+
+    fn chunk(id: &str, content: &str, finish: Option<&str>) -> Result<ChatCompletionChunk> {
+        Ok(nextjson::from_value(json!({
+            "id": id,
+            "choices": [{
+                "index": 0,
+                "delta": {"role": "assistant", "content": content},
+                "finish_reason": finish
+            }]
+        }))
+        .unwrap())
+    }
+
+    fn reset() -> Result<ChatCompletionChunk> {
+        Err(SdkError::Timeout("connection reset".into()))
+    }
+
+    async fn collect(
+        provider: Arc<ScriptedProvider>,
+        policy: StreamResumePolicy,
+    ) -> Vec<Result<ResilientStreamEvent>> {
+        let request = ChatCompletionRequest::new("glm").message(ChatMessage::user("hi"));
+        ResilientChatStream::open(provider, request, policy.delay(Duration::ZERO))
+            .await
+            .unwrap()
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn resumes_with_partial_output_and_stitches_chunks() {
+        let provider = ScriptedProvider::new(vec![
+            vec![chunk("a", "Hel", None), chunk("a", "lo ", None), reset()],
+            vec![chunk("b", "world", Some("stop"))],
+        ]);
+        let events = collect(provider.clone(), StreamResumePolicy::default()).await;
+        let events = events.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            &events[2],
+            ResilientStreamEvent::Resumed {
+                attempt: 1,
+                resumed_from_bytes: 6,
+                reason
+            } if reason.contains("connection reset")
+        ));
+        let ResilientStreamEvent::Chunk(last) = &events[3] else {
+            panic!("expected a chunk");
+        };
+        assert_eq!(last.id, "a");
+        assert_eq!(last.choices[0].delta.role, None);
+
+        let requests = provider.requests.lock().unwrap();
+        let messages = &requests[1].messages;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1], ChatMessage::assistant("Hello "));
+        assert_eq!(messages[2].role, MessageRole::User);
+    }
+
+    #[tokio::test]
+    async fn assistant_prefix_marks_the_partial_message() {
+        let provider = ScriptedProvider::new(vec![
+            vec![chunk("a", "Hel", None), reset()],
+            vec![reset()],
+            vec![chunk("c", "lo", Some("stop"))],
+        ]);
+        let policy = StreamResumePolicy::new(2).continuation(StreamContinuation::AssistantPrefix);
+        let events = collect(provider.clone(), policy).await;
+        assert!(events.iter().all(Result::is_ok));
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        let partial = requests[2].messages.last().unwrap();
+        assert_eq!(partial.role, MessageRole::Assistant);
+        assert_eq!(partial.extra.get("prefix"), Some(&Value::Bool(true)));
+    }
+
+    #[tokio::test]
+    async fn does_not_resume_tool_calls_or_past_the_budget() {
+        let tool_call = Ok(nextjson::from_value(json!({
+            "id": "a",
+            "choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "id": "call"}]}}]
+        }))
+        .unwrap());
+        let provider = ScriptedProvider::new(vec![vec![tool_call, reset()]]);
+        let events = collect(provider.clone(), StreamResumePolicy::default()).await;
+        assert!(matches!(events.last(), Some(Err(SdkError::Timeout(_)))));
+        assert_eq!(provider.requests.lock().unwrap().len(), 1);
+
+        let provider =
+            ScriptedProvider::new(vec![vec![chunk("a", "Hel", None), reset()], vec![reset()]]);
+        let events = collect(provider.clone(), StreamResumePolicy::new(1)).await;
+        assert!(matches!(events.last(), Some(Err(SdkError::Timeout(_)))));
+        assert_eq!(provider.requests.lock().unwrap().len(), 2);
+
+        let decode = Err(SdkError::Stream("invalid SSE payload".into()));
+        let provider = ScriptedProvider::new(vec![vec![chunk("a", "Hel", None), decode]]);
+        let events = collect(provider.clone(), StreamResumePolicy::default()).await;
+        assert!(matches!(events.last(), Some(Err(SdkError::Stream(_)))));
+        assert_eq!(provider.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resumes_a_stream_that_ends_without_a_finish_reason() {
+        let provider = ScriptedProvider::new(vec![
+            vec![chunk("a", "Hel", None)],
+            vec![chunk("b", "lo", Some("stop"))],
+        ]);
+        let events = collect(provider.clone(), StreamResumePolicy::default()).await;
+        let events = events.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            &events[1],
+            ResilientStreamEvent::Resumed { reason, .. } if reason.contains("finish reason")
+        ));
+        assert_eq!(provider.requests.lock().unwrap().len(), 2);
+
+        let provider = ScriptedProvider::new(vec![vec![chunk("a", "Hel", None)]]);
+        let events = collect(provider, StreamResumePolicy::new(0)).await;
+        assert!(matches!(events.last(), Some(Err(SdkError::Stream(_)))));
+    }
+}
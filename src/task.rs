@@ -0,0 +1,571 @@
+//! Polling for asynchronous tasks: async chat, image and video generation, official agents, and
+//! file parsing.
+
+use std::fmt;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures_util::future::select;
+use nextjson::Value;
+#[cfg(feature = "files")]
+use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize};
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+#[cfg(feature = "files")]
+use crate::ExtraFields;
+#[cfg(feature = "images")]
+use crate::GeneratedImage;
+#[cfg(feature = "video")]
+use crate::VideoResult;
+#[cfg(feature = "agents")]
+use crate::{AgentAsyncResultRequest, AgentAsyncResultResponse};
+use crate::{
+    AsyncTaskResponse, AsyncTaskResult, ChatCompletionResponse, Result, SdkError, TaskError,
+    ZhipuClient,
+};
+
+/// The state of an asynchronous task, normalized across the endpoints' status vocabularies
+/// (`PROCESSING`/`SUCCESS`/`FAIL`, `pending`/`success`/`failed`, `processing`/`succeeded`).
+/// Other terminal words such as `cancelled`, `expired`, or `timeout` count as `Failed`, so
+/// waiting on them stops.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TaskStatus {
+    Processing,
+    Succeeded,
+    /// A terminal failure, with the status word the endpoint sent (`FAIL`, `expired`, ...).
+    Failed(String),
+    /// A status this SDK does not know, kept verbatim. It is polled like `Processing`.
+    Other(String),
+}
+
+impl TaskStatus {
+    pub fn parse(value: &str) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "processing" | "pending" | "running" | "in_progress" | "queued" => Self::Processing,
+            "success" | "succeeded" | "completed" => Self::Succeeded,
+            "fail" | "failed" | "failure" | "error" | "cancelled" | "canceled" | "expired"
+            | "timeout" | "timed_out" | "rejected" | "aborted" | "terminated" => {
+                Self::Failed(value.to_owned())
+            }
+            _ => Self::Other(value.to_owned()),
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed(_))
+    }
+}
+
+impl fmt::Display for TaskStatus {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Processing => formatter.write_str("processing"),
+            Self::Succeeded => formatter.write_str("succeeded"),
+            Self::Failed(value) | Self::Other(value) => formatter.write_str(value),
+        }
+    }
+}
+
+/// One poll of an [`AsyncTask`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskPoll<T> {
+    Pending(TaskStatus),
+    Ready(T),
+    Failed {
+        status: TaskStatus,
+        message: Option<String>,
+    },
+}
+
+/// A task that [`ZhipuClient::wait_for`] can poll to a typed result.
+#[async_trait]
+pub trait AsyncTask: Send + Sync {
+    type Output: Send;
+
+    fn task_id(&self) -> &str;
+
+    async fn poll(&self, client: &ZhipuClient) -> Result<TaskPoll<Self::Output>>;
+}
+
+/// Cancels a [`ZhipuClient::wait_for`] from another task. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct TaskCancellation {
+    inner: Arc<(AtomicBool, Notify)>,
+}
+
+impl TaskCancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.0.store(true, Ordering::SeqCst);
+        self.inner.1.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.0.load(Ordering::SeqCst)
+    }
+}
+
+/// How [`ZhipuClient::wait_for`] polls: the first interval, a backoff factor applied after each
+/// poll up to `max_interval`, an optional overall deadline, and optional cancellation.
+#[derive(Debug, Clone)]
+pub struct TaskPollPolicy {
+    pub interval: Duration,
+    pub max_interval: Duration,
+    pub backoff: f32,
+    pub deadline: Option<Duration>,
+    pub cancellation: Option<TaskCancellation>,
+}
+
+impl Default for TaskPollPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(2),
+            max_interval: Duration::from_secs(30),
+            backoff: 1.5,
+            deadline: Some(Duration::from_secs(30 * 60)),
+            cancellation: None,
+        }
+    }
+}
+
+impl TaskPollPolicy {
+    pub fn interval(mut self, value: Duration) -> Self {
+        self.interval = value;
+        self
+    }
+
+    pub fn max_interval(mut self, value: Duration) -> Self {
+        self.max_interval = value;
+        self
+    }
+
+    /// Multiplies the interval after each poll; `1.0` polls at a fixed rate.
+    pub fn backoff(mut self, value: f32) -> Self {
+        self.backoff = value;
+        self
+    }
+
+    pub fn deadline(mut self, value: Duration) -> Self {
+        self.deadline = Some(value);
+        self
+    }
+
+    pub fn no_deadline(mut self) -> Self {
+        self.deadline = None;
+        self
+    }
+
+    pub fn cancellation(mut self, value: TaskCancellation) -> Self {
+        self.cancellation = Some(value);
+        self
+    }
+}
+
+impl ZhipuClient {
+    /// Polls `task` until it succeeds or fails and returns its typed result.
+    ///
+    /// A failed task, a missed deadline, and cancellation are reported as [`TaskError`]s carrying
+    /// the task id and its last status. Poll requests themselves go through the usual transport
+    /// retries; an error that survives them ends the wait.
+    pub async fn wait_for<T: AsyncTask>(
+        &self,
+        task: T,
+        policy: &TaskPollPolicy,
+    ) -> Result<T::Output> {
+        if !policy.backoff.is_finite() || policy.backoff < 1.0 || policy.interval.is_zero() {
+            return Err(SdkError::Validation(
+                "task polling needs a positive interval and a backoff of at least 1".into(),
+            ));
+        }
+        let started = Instant::now();
+        let mut interval = policy.interval;
+        let mut status = TaskStatus::Processing;
+        loop {
+            if policy
+                .cancellation
+                .as_ref()
+                .is_some_and(TaskCancellation::is_cancelled)
+            {
+                return Err(TaskError::Cancelled {
+                    task_id: task.task_id().to_owned(),
+                    status,
+                }
+                .into());
+            }
+            match task.poll(self).await? {
+                TaskPoll::Ready(output) => return Ok(output),
+                TaskPoll::Failed { status, message } => {
+                    return Err(TaskError::Failed {
+                        task_id: task.task_id().to_owned(),
+                        status,
+                        message,
+                    }
+                    .into());
+                }
+                TaskPoll::Pending(current) => status = current,
+            }
+            let mut delay = interval;
+            if let Some(deadline) = policy.deadline {
+                let remaining = deadline.saturating_sub(started.elapsed());
+                if remaining.is_zero() {
+                    return Err(TaskError::DeadlineExceeded {
+                        task_id: task.task_id().to_owned(),
+                        status,
+                    }
+                    .into());
+                }
+                delay = delay.min(remaining);
+            }
+            match &policy.cancellation {
+                Some(cancellation) => {
+                    let mut notified = pin!(cancellation.inner.1.notified());
+                    notified.as_mut().enable();
+                    if !cancellation.is_cancelled() {
+                        let _ = select(notified, pin!(sleep(delay))).await;
+                    }
+                }
+                None => sleep(delay).await,
+            }
+            interval = next_interval(interval, policy);
+        }
+    }
+}
+
+/// Grows `interval` by the backoff factor, saturating at `max_interval` instead of overflowing.
+fn next_interval(interval: Duration, policy: &TaskPollPolicy) -> Duration {
+    Duration::try_from_secs_f64(interval.as_secs_f64() * f64::from(policy.backoff))
+        .map_or(policy.max_interval, |next| next.min(policy.max_interval))
+}
+
+/// An `async/chat/completions` task, finishing with the chat response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatTask(pub String);
+
+impl From<&AsyncTaskResponse> for ChatTask {
+    fn from(value: &AsyncTaskResponse) -> Self {
+        Self(value.id.clone())
+    }
+}
+
+#[async_trait]
+impl AsyncTask for ChatTask {
+    type Output = ChatCompletionResponse;
+
+    fn task_id(&self) -> &str {
+        &self.0
+    }
+
+    async fn poll(&self, client: &ZhipuClient) -> Result<TaskPoll<Self::Output>> {
+        Ok(poll_async_result(client, &self.0)
+            .await?
+            .map(|result| ChatCompletionResponse {
+                id: result.id,
+                request_id: result.request_id,
+                model: result.model.unwrap_or_default(),
+                choices: result.choices,
+                usage: result.usage,
+                video_result: result.video_result,
+                extra: result.extra,
+                ..ChatCompletionResponse::default()
+            }))
+    }
+}
+
+/// An `async/images/generations` task, finishing with the image URLs.
+#[cfg(feature = "images")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageTask(pub String);
+
+#[cfg(feature = "images")]
+impl From<&AsyncTaskResponse> for ImageTask {
+    fn from(value: &AsyncTaskResponse) -> Self {
+        Self(value.id.clone())
+    }
+}
+
+#[cfg(feature = "images")]
+#[async_trait]
+impl AsyncTask for ImageTask {
+    type Output = Vec<String>;
+
+    fn task_id(&self) -> &str {
+        &self.0
+    }
+
+    async fn poll(&self, client: &ZhipuClient) -> Result<TaskPoll<Self::Output>> {
+        Ok(poll_async_result(client, &self.0).await?.map(|result| {
+            result
+                .image_result
+                .into_iter()
+                .filter_map(|image: GeneratedImage| image.url)
+                .collect()
+        }))
+    }
+}
+
+/// A `videos/generations` task, finishing with the video and cover URLs.
+#[cfg(feature = "video")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoTask(pub String);
+
+#[cfg(feature = "video")]
+impl From<&AsyncTaskResponse> for VideoTask {
+    fn from(value: &AsyncTaskResponse) -> Self {
+        Self(value.id.clone())
+    }
+}
+
+#[cfg(feature = "video")]
+#[async_trait]
+impl AsyncTask for VideoTask {
+    type Output = Vec<VideoResult>;
+
+    fn task_id(&self) -> &str {
+        &self.0
+    }
+
+    async fn poll(&self, client: &ZhipuClient) -> Result<TaskPoll<Self::Output>> {
+        Ok(poll_async_result(client, &self.0)
+            .await?
+            .map(|result| result.video_result))
+    }
+}
+
+/// An official agent's async run, polled through `official_agent_async_result`.
+#[cfg(feature = "agents")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentTask(pub AgentAsyncResultRequest);
+
+#[cfg(feature = "agents")]
+#[async_trait]
+impl AsyncTask for AgentTask {
+    type Output = AgentAsyncResultResponse;
+
+    fn task_id(&self) -> &str {
+        &self.0.async_id
+    }
+
+    async fn poll(&self, client: &ZhipuClient) -> Result<TaskPoll<Self::Output>> {
+        let result = client.official_agent_async_result(&self.0).await?;
+        Ok(match TaskStatus::parse(result.status.as_str()) {
+            TaskStatus::Succeeded => TaskPoll::Ready(result),
+            status @ TaskStatus::Failed(_) => TaskPoll::Failed {
+                status,
+                message: None,
+            },
+            status => TaskPoll::Pending(status),
+        })
+    }
+}
+
+/// A finished file parsing task.
+#[cfg(feature = "files")]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct ParsedDocument {
+    #[serde(default)]
+    pub task_id: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// The parsed text, for `format_type` `text`.
+    #[serde(default)]
+    pub content: Option<String>,
+    /// A download link for the result, for `format_type` `download_link`.
+    #[serde(default)]
+    pub parsing_result_url: Option<String>,
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
+/// A `files/parser/create` task, polled through `file_parse_result` in `format_type`.
+#[cfg(feature = "files")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileParseTask {
+    pub task_id: String,
+    pub format_type: String,
+}
+
+#[cfg(feature = "files")]
+impl FileParseTask {
+    pub fn new(task_id: impl Into<String>, format_type: impl Into<String>) -> Self {
+        Self {
+            task_id: task_id.into(),
+            format_type: format_type.into(),
+        }
+    }
+}
+
+#[cfg(feature = "files")]
+#[async_trait]
+impl AsyncTask for FileParseTask {
+    type Output = ParsedDocument;
+
+    fn task_id(&self) -> &str {
+        &self.task_id
+    }
+
+    async fn poll(&self, client: &ZhipuClient) -> Result<TaskPoll<Self::Output>> {
+        let value: Value = client
+            .file_parse_result(&self.task_id, &self.format_type)
+            .await?;
+        let document: ParsedDocument =
+            nextjson::from_value(value.clone()).map_err(|error| SdkError::Decode {
+                message: error.to_string(),
+                body: value.to_string(),
+            })?;
+        Ok(match TaskStatus::parse(&document.status) {
+            TaskStatus::Succeeded => TaskPoll::Ready(document),
+            status @ TaskStatus::Failed(_) => TaskPoll::Failed {
+                status,
+                message: document.message,
+            },
+            status => TaskPoll::Pending(status),
+        })
+    }
+}
+
+impl<T> TaskPoll<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> TaskPoll<U> {
+        match self {
+            Self::Pending(status) => TaskPoll::Pending(status),
+            Self::Ready(output) => TaskPoll::Ready(f(output)),
+            Self::Failed { status, message } => TaskPoll::Failed { status, message },
+        }
+    }
+}
+
+async fn poll_async_result(client: &ZhipuClient, id: &str) -> Result<TaskPoll<AsyncTaskResult>> {
+    let result = client.async_result(id).await?;
+    Ok(match TaskStatus::parse(&result.task_status) {
+        TaskStatus::Succeeded => TaskPoll::Ready(result),
+        status @ TaskStatus::Failed(_) => TaskPoll::Failed {
+            status,
+            message: result
+                .extra
+                .get("error")
+                .and_then(|error| error.get("message"))
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        },
+        status => TaskPoll::Pending(status),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Scripted {
+        polls: std::sync::Mutex<Vec<TaskPoll<u32>>>,
+    }
+
+    impl Scripted {
+        fn new(mut polls: Vec<TaskPoll<u32>>) -> Self {
+            polls.reverse();
+            Self {
+                polls: std::sync::Mutex::new(polls),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AsyncTask for Scripted {
+        type Output = u32;
+
+        fn task_id(&self) -> &str {
+            "task-1"
+        }
+
+        async fn poll(&self, _client: &ZhipuClient) -> Result<TaskPoll<u32>> {
+            let mut polls = self.polls.lock().unwrap();
+            Ok(polls
+                .pop()
+                .unwrap_or(TaskPoll::Pending(TaskStatus::Processing)))
+        }
+    }
+
+    #[test]
+    fn status_normalizes_every_endpoint_vocabulary() {
+        assert_eq!(TaskStatus::parse("PROCESSING"), TaskStatus::Processing);
+        assert_eq!(TaskStatus::parse("SUCCESS"), TaskStatus::Succeeded);
+        assert_eq!(TaskStatus::parse("succeeded"), TaskStatus::Succeeded);
+        assert_eq!(TaskStatus::parse("FAIL"), TaskStatus::Failed("FAIL".into()));
+        assert_eq!(TaskStatus::parse("pending"), TaskStatus::Processing);
+        assert!(!TaskStatus::parse("paused").is_terminal());
+        for word in ["cancelled", "CANCELED", "expired", "timeout", "rejected"] {
+            assert_eq!(TaskStatus::parse(word), TaskStatus::Failed(word.into()));
+        }
+    }
+
+    #[test]
+    fn huge_backoff_saturates_at_the_max_interval() {
+        let policy = TaskPollPolicy::default()
+            .backoff(1e30)
+            .max_interval(Duration::MAX);
+        assert_eq!(
+            next_interval(Duration::from_secs(1), &policy),
+            Duration::MAX
+        );
+        let capped = policy.max_interval(Duration::from_secs(30));
+        assert_eq!(
+            next_interval(Duration::from_secs(20), &capped),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_for_returns_results_and_typed_failures() {
+        let client = ZhipuClient::new("key").unwrap();
+        let fast = TaskPollPolicy::default().interval(Duration::from_millis(1));
+        let ready = Scripted::new(vec![
+            TaskPoll::Pending(TaskStatus::Processing),
+            TaskPoll::Ready(7),
+        ]);
+        assert_eq!(client.wait_for(ready, &fast).await.unwrap(), 7);
+
+        let failed = Scripted::new(vec![TaskPoll::Failed {
+            status: TaskStatus::parse("expired"),
+            message: Some("bad prompt".into()),
+        }]);
+        let error = client.wait_for(failed, &fast).await.unwrap_err();
+        assert!(error.to_string().contains("ended with status expired"));
+        assert!(matches!(
+            error,
+            SdkError::Task(TaskError::Failed { status: TaskStatus::Failed(status), message: Some(message), .. })
+                if status == "expired" && message == "bad prompt"
+        ));
+
+        let late = fast.clone().deadline(Duration::from_millis(20));
+        assert!(matches!(
+            client.wait_for(Scripted::new(Vec::new()), &late).await,
+            Err(SdkError::Task(TaskError::DeadlineExceeded {
+                status: TaskStatus::Processing,
+                ..
+            }))
+        ));
+
+        let cancellation = TaskCancellation::new();
+        let cancellable = TaskPollPolicy::default()
+            .interval(Duration::from_secs(60))
+            .cancellation(cancellation.clone());
+        let waiting = tokio::spawn(async move {
+            client
+                .wait_for(Scripted::new(Vec::new()), &cancellable)
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        cancellation.cancel();
+        assert!(matches!(
+            waiting.await.unwrap(),
+            Err(SdkError::Task(TaskError::Cancelled { .. }))
+        ));
+    }
+}
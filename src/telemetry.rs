@@ -0,0 +1,367 @@
+//! Optional `tracing` instrumentation following the OpenTelemetry GenAI semantic conventions.
+//!
+//! Without the `tracing` feature every type here is zero-sized and every call is a no-op, so
+//! call sites do not need their own `cfg` attributes.
+
+use std::borrow::Cow;
+use std::future::Future;
+
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+
+use crate::{Result, SdkError, Usage};
+
+#[cfg(feature = "tracing")]
+pub(crate) use enabled::*;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::*;
+
+/// Runs `future` inside `span`, marking the span as failed when the future returns an error.
+#[cfg_attr(not(any(feature = "agents", feature = "rag")), allow(dead_code))]
+pub(crate) async fn traced<T>(
+    span: SpanHandle,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    #[cfg(feature = "tracing")]
+    {
+        use tracing::Instrument;
+        let result = future.instrument(span.clone()).await;
+        if let Err(error) = &result {
+            span.record("error.type", error_type(error).as_ref());
+            span.record("otel.status_code", "ERROR");
+        }
+        result
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let SpanHandle = span;
+        future.await
+    }
+}
+
+/// Low-cardinality `error.type` value: the HTTP status for API errors, otherwise the error kind.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+fn error_type(error: &SdkError) -> Cow<'static, str> {
+    Cow::Borrowed(match error {
+        SdkError::Api(error) => return Cow::Owned(error.status.as_str().to_owned()),
+        SdkError::Configuration(_) => "configuration",
+        SdkError::Validation(_) => "validation",
+        SdkError::Transport(_) => "transport",
+        SdkError::Timeout(_) => "timeout",
+        SdkError::Decode { .. } => "decode",
+        SdkError::PayloadTooLarge { .. } => "payload_too_large",
+        SdkError::Stream(_) => "stream",
+        SdkError::Unsupported(_) => "unsupported",
+        SdkError::Agent(_) => "agent",
+        SdkError::Tool(_) => "tool",
+        SdkError::Budget(_) => "budget",
+        SdkError::Task(_) => "task",
+        // Feature-gated variants; unreachable when every optional feature is off.
+        #[allow(unreachable_patterns)]
+        _ => "other",
+    })
+}
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    use nextjson::NsonDeserialize as Deserialize;
+    use tracing::field::Empty;
+    use tracing::{Level, Span, event, info_span};
+
+    use super::*;
+    use crate::security::mask_sensitive;
+
+    pub(crate) type SpanHandle = Span;
+
+    /// One span per `Transport` call, covering every retry and, for streams, the whole stream.
+    #[derive(Debug)]
+    pub(crate) struct CallSpan {
+        span: Span,
+        operation: &'static str,
+        model: Option<String>,
+        started: Instant,
+        first_chunk: OnceLock<()>,
+        error_type: OnceLock<Cow<'static, str>>,
+    }
+
+    #[derive(Deserialize, Default)]
+    struct RequestProbe {
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default)]
+        request_id: Option<String>,
+    }
+
+    #[derive(Deserialize, Default)]
+    struct ResponseProbe {
+        #[serde(default)]
+        id: Option<String>,
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default)]
+        usage: Option<Usage>,
+    }
+
+    impl CallSpan {
+        pub(crate) fn start(
+            method: &Method,
+            base_url: &str,
+            path: &str,
+            body: Option<&[u8]>,
+        ) -> Self {
+            let probe = body
+                .and_then(|body| nextjson::from_slice::<RequestProbe>(body).ok())
+                .unwrap_or_default();
+            let path = mask_sensitive(path.split('?').next().unwrap_or_default(), &[]);
+            let operation = operation_name(&path);
+            let server = base_url
+                .split_once("://")
+                .map_or(base_url, |(_, rest)| rest)
+                .split(['/', ':'])
+                .next()
+                .unwrap_or_default();
+            let name = match &probe.model {
+                Some(model) => format!("{operation} {model}"),
+                None => operation.to_owned(),
+            };
+            let span = info_span!(
+                "gen_ai.client",
+                otel.name = %name,
+                otel.kind = "client",
+                otel.status_code = Empty,
+                gen_ai.operation.name = operation,
+                gen_ai.request.model = probe.model.as_deref(),
+                gen_ai.response.id = Empty,
+                gen_ai.response.model = Empty,
+                gen_ai.usage.input_tokens = Empty,
+                gen_ai.usage.output_tokens = Empty,
+                gen_ai.server.time_to_first_token = Empty,
+                server.address = server,
+                http.request.method = %method,
+                url.path = %path,
+                http.request.resend_count = Empty,
+                http.response.status_code = Empty,
+                zhipu.request_id = probe.request_id.as_deref(),
+                error.type = Empty,
+            );
+            Self {
+                span,
+                operation,
+                model: probe.model,
+                started: Instant::now(),
+                first_chunk: OnceLock::new(),
+                error_type: OnceLock::new(),
+            }
+        }
+
+        /// Runs `future` with this span entered, so spans from the HTTP stack nest under it.
+        pub(crate) async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+            use tracing::Instrument;
+            future.instrument(self.span.clone()).await
+        }
+
+        pub(crate) fn resend(&self, count: u32) {
+            self.span.record("http.request.resend_count", count);
+        }
+
+        pub(crate) fn response(&self, status: StatusCode, headers: &HeaderMap) {
+            self.span
+                .record("http.response.status_code", status.as_u16());
+            if let Some(request_id) = headers
+                .get("x-request-id")
+                .or_else(|| headers.get("x-zhipu-request-id"))
+                .and_then(|value| value.to_str().ok())
+            {
+                self.span.record("zhipu.request_id", request_id);
+            }
+        }
+
+        /// Records response id, model, and usage from a JSON response body.
+        pub(crate) fn body(&self, body: &[u8]) {
+            let Ok(probe) = nextjson::from_slice::<ResponseProbe>(body) else {
+                return;
+            };
+            if let Some(id) = probe.id.as_deref().filter(|id| !id.is_empty()) {
+                self.span.record("gen_ai.response.id", id);
+            }
+            if let Some(model) = probe.model.as_deref().filter(|model| !model.is_empty()) {
+                self.span.record("gen_ai.response.model", model);
+            }
+            if let Some(usage) = &probe.usage {
+                self.usage(usage);
+            }
+        }
+
+        pub(crate) fn usage(&self, usage: &Usage) {
+            self.span
+                .record("gen_ai.usage.input_tokens", usage.prompt_tokens);
+            self.span
+                .record("gen_ai.usage.output_tokens", usage.completion_tokens);
+            for (kind, tokens) in [
+                ("input", usage.prompt_tokens),
+                ("output", usage.completion_tokens),
+            ] {
+                event!(target: "rustglm::metrics", parent: &self.span, Level::INFO,
+                    histogram.gen_ai.client.token.usage = tokens,
+                    gen_ai.token.type = kind,
+                    gen_ai.operation.name = self.operation,
+                    gen_ai.request.model = self.model.as_deref(),
+                );
+            }
+        }
+
+        pub(crate) fn first_chunk(&self) {
+            if self.first_chunk.set(()).is_err() {
+                return;
+            }
+            let seconds = self.started.elapsed().as_secs_f64();
+            self.span
+                .record("gen_ai.server.time_to_first_token", seconds);
+            event!(target: "rustglm::metrics", parent: &self.span, Level::INFO,
+                histogram.gen_ai.server.time_to_first_token = seconds,
+                gen_ai.operation.name = self.operation,
+                gen_ai.request.model = self.model.as_deref(),
+            );
+        }
+
+        /// Marks the call as failed. `redact` scrubs credentials from the recorded message.
+        pub(crate) fn failed(&self, error: &SdkError, redact: impl FnOnce(&str) -> String) {
+            if self.error_type.set(error_type(error)).is_err() {
+                return;
+            }
+            self.span
+                .record("error.type", self.error_type.get().map(AsRef::as_ref));
+            self.span.record("otel.status_code", "ERROR");
+            if let SdkError::Api(error) = error {
+                self.span
+                    .record("http.response.status_code", error.status.as_u16());
+            }
+            let message = redact(&error.to_string());
+            event!(target: "rustglm", parent: &self.span, Level::INFO, error.message = %message, "request failed");
+        }
+    }
+
+    impl Drop for CallSpan {
+        fn drop(&mut self) {
+            event!(target: "rustglm::metrics", parent: &self.span, Level::INFO,
+                histogram.gen_ai.client.operation.duration = self.started.elapsed().as_secs_f64(),
+                gen_ai.operation.name = self.operation,
+                gen_ai.request.model = self.model.as_deref(),
+                error.type = self.error_type.get().map(AsRef::<str>::as_ref),
+            );
+        }
+    }
+
+    fn operation_name(path: &str) -> &'static str {
+        let path = path.trim_start_matches('/');
+        if path.ends_with("chat/completions") || path.ends_with("chat") {
+            "chat"
+        } else if path.starts_with("embeddings") {
+            "embeddings"
+        } else if path.starts_with("v1/agents") || path.starts_with("zrag/agent") {
+            "invoke_agent"
+        } else {
+            path.split('/')
+                .next()
+                .map_or("http", |segment| match segment {
+                    "images" => "images",
+                    "videos" => "videos",
+                    "audio" => "audio",
+                    "files" => "files",
+                    "batches" => "batches",
+                    "rerank" => "rerank",
+                    "tokenizer" => "tokenizer",
+                    "moderations" => "moderations",
+                    _ => "http",
+                })
+        }
+    }
+
+    pub(crate) fn agent_span(agent: &str, model: &str) -> Span {
+        info_span!(
+            "gen_ai.agent",
+            otel.name = %format!("invoke_agent {agent}"),
+            otel.status_code = Empty,
+            gen_ai.operation.name = "invoke_agent",
+            gen_ai.agent.name = agent,
+            gen_ai.request.model = model,
+            error.type = Empty,
+        )
+    }
+
+    pub(crate) fn step_span(model: &str, step: u32) -> Span {
+        info_span!(
+            "gen_ai.agent.step",
+            otel.name = %format!("chat {model}"),
+            otel.status_code = Empty,
+            gen_ai.operation.name = "chat",
+            gen_ai.request.model = model,
+            rustglm.agent.step = step,
+            error.type = Empty,
+        )
+    }
+
+    pub(crate) fn tool_span(name: &str, call_id: &str) -> Span {
+        info_span!(
+            "gen_ai.tool",
+            otel.name = %format!("execute_tool {name}"),
+            otel.status_code = Empty,
+            gen_ai.operation.name = "execute_tool",
+            gen_ai.tool.name = name,
+            gen_ai.tool.call.id = call_id,
+            error.type = Empty,
+        )
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct SpanHandle;
+
+    #[derive(Debug)]
+    pub(crate) struct CallSpan;
+
+    impl CallSpan {
+        pub(crate) fn start(_: &Method, _: &str, _: &str, _: Option<&[u8]>) -> Self {
+            Self
+        }
+
+        pub(crate) async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+            future.await
+        }
+
+        pub(crate) fn resend(&self, _: u32) {}
+
+        pub(crate) fn response(&self, _: StatusCode, _: &HeaderMap) {}
+
+        pub(crate) fn body(&self, _: &[u8]) {}
+
+        pub(crate) fn usage(&self, _: &Usage) {}
+
+        pub(crate) fn first_chunk(&self) {}
+
+        pub(crate) fn failed(&self, _: &SdkError, _: impl FnOnce(&str) -> String) {}
+    }
+
+    #[cfg_attr(not(any(feature = "agents", feature = "rag")), allow(dead_code))]
+    pub(crate) fn agent_span(_: &str, _: &str) -> SpanHandle {
+        SpanHandle
+    }
+
+    #[cfg_attr(not(any(feature = "agents", feature = "rag")), allow(dead_code))]
+    pub(crate) fn step_span(_: &str, _: u32) -> SpanHandle {
+        SpanHandle
+    }
+
+    #[cfg_attr(not(any(feature = "agents", feature = "rag")), allow(dead_code))]
+    pub(crate) fn tool_span(_: &str, _: &str) -> SpanHandle {
+        SpanHandle
+    }
+}
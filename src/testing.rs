@@ -0,0 +1,799 @@
+//! Test doubles for code built on this crate: a scriptable [`MockProvider`] for anything that
+//! takes a `ChatProvider`, and a [`ZhipuStubServer`] that speaks the Zhipu REST and SSE wire
+//! format so a real `ZhipuClient` can be pointed at it.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use nextjson::NsonSerialize as Serialize;
+use nextjson::{Value, json};
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, StatusCode};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use crate::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, ChatProvider, ChatStream,
+    ProviderCapabilities, Result, SdkError, ZhipuClient, ZhipuConfig,
+};
+
+/// What a [`MockProvider`] answers with next.
+///
+/// Either kind of reply serves both `complete` and `stream`: responses are split into one chunk
+/// per choice, and streams are folded back into a response.
+#[derive(Debug)]
+pub enum MockReply {
+    Response(Box<ChatCompletionResponse>),
+    Stream(Vec<MockStreamItem>),
+    Error(SdkError),
+}
+
+#[derive(Debug)]
+pub enum MockStreamItem {
+    Chunk(Box<ChatCompletionChunk>),
+    Delay(Duration),
+    /// Ends the stream with this error.
+    Error(SdkError),
+}
+
+impl MockReply {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Response(response(json!({
+            "id": "mock",
+            "model": "mock",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": text.into()},
+                "finish_reason": "stop"
+            }]
+        })))
+    }
+
+    /// An assistant turn that calls one function tool with `arguments`.
+    pub fn tool_call(id: impl Into<String>, name: impl Into<String>, arguments: &Value) -> Self {
+        Self::Response(response(json!({
+            "id": "mock",
+            "model": "mock",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "tool_calls": [{
+                        "id": id.into(),
+                        "type": "function",
+                        "function": {
+                            "name": name.into(),
+                            "arguments": nextjson::to_string(arguments).unwrap_or_default()
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        })))
+    }
+
+    /// Streams `pieces` as content deltas, waiting `delay` before each one.
+    pub fn text_stream<I>(pieces: I, delay: Duration) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut items = Vec::new();
+        for piece in pieces {
+            if !delay.is_zero() {
+                items.push(MockStreamItem::Delay(delay));
+            }
+            items.push(MockStreamItem::Chunk(chunk(json!({
+                "id": "mock",
+                "model": "mock",
+                "choices": [{"index": 0, "delta": {"content": piece.into()}}]
+            }))));
+        }
+        items.push(MockStreamItem::Chunk(chunk(json!({
+            "id": "mock",
+            "model": "mock",
+            "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]
+        }))));
+        Self::Stream(items)
+    }
+
+    pub fn error(error: SdkError) -> Self {
+        Self::Error(error)
+    }
+
+    fn into_stream_items(self) -> Vec<MockStreamItem> {
+        match self {
+            Self::Stream(items) => items,
+            Self::Error(error) => vec![MockStreamItem::Error(error)],
+            Self::Response(response) => {
+                let choices = response
+                    .choices
+                    .iter()
+                    .map(|choice| {
+                        let tool_calls = choice
+                            .message
+                            .tool_calls
+                            .iter()
+                            .enumerate()
+                            .map(|(index, call)| {
+                                json!({
+                                    "index": index,
+                                    "id": call.id,
+                                    "type": call.kind,
+                                    "function": call.function
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        json!({
+                            "index": choice.index,
+                            "delta": {
+                                "role": "assistant",
+                                "content": choice.message.content,
+                                "reasoning_content": choice.message.reasoning_content,
+                                "tool_calls": tool_calls
+                            },
+                            "finish_reason": choice.finish_reason
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                vec![MockStreamItem::Chunk(chunk(json!({
+                    "id": response.id,
+                    "model": response.model,
+                    "choices": choices,
+                    "usage": response.usage
+                })))]
+            }
+        }
+    }
+
+    fn into_response(self) -> Result<ChatCompletionResponse> {
+        let items = match self {
+            Self::Response(response) => return Ok(*response),
+            Self::Error(error) => return Err(error),
+            Self::Stream(items) => items,
+        };
+        let mut id = String::new();
+        let mut model = String::new();
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        let mut finish_reason = None;
+        let mut usage = None;
+        let mut calls: Vec<(String, String, String)> = Vec::new();
+        for item in items {
+            let chunk = match item {
+                MockStreamItem::Chunk(chunk) => chunk,
+                MockStreamItem::Delay(_) => continue,
+                MockStreamItem::Error(error) => return Err(error),
+            };
+            if id.is_empty() {
+                id = chunk.id;
+            }
+            if model.is_empty() {
+                model = chunk.model;
+            }
+            usage = chunk.usage.or(usage);
+            for choice in chunk.choices.into_iter().filter(|choice| choice.index == 0) {
+                let delta = choice.delta;
+                content.push_str(
+                    &delta
+                        .content
+                        .and_then(|content| content.joined_text())
+                        .unwrap_or_default(),
+                );
+                reasoning.push_str(delta.reasoning_content.as_deref().unwrap_or_default());
+                for call in delta.tool_calls {
+                    let index = call.index.unwrap_or_default() as usize;
+                    if calls.len() <= index {
+                        calls.resize_with(index + 1, Default::default);
+                    }
+                    let (id, name, arguments) = &mut calls[index];
+                    if let Some(value) = call.id {
+                        *id = value;
+                    }
+                    if let Some(function) = call.function {
+                        name.push_str(function.name.as_deref().unwrap_or_default());
+                        arguments.push_str(function.arguments.as_deref().unwrap_or_default());
+                    }
+                }
+                finish_reason = choice.finish_reason.or(finish_reason);
+            }
+        }
+        let calls = calls
+            .into_iter()
+            .map(|(id, name, arguments)| {
+                json!({
+                    "id": id,
+                    "type": "function",
+                    "function": {"name": name, "arguments": arguments}
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(*response(json!({
+            "id": id,
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": (!content.is_empty()).then_some(content),
+                    "reasoning_content": (!reasoning.is_empty()).then_some(reasoning),
+                    "tool_calls": calls
+                },
+                "finish_reason": finish_reason
+            }],
+            "usage": usage
+        })))
+    }
+}
+
+/// A `ChatProvider` that answers from a queue of [`MockReply`] values and records every request.
+///
+/// Clones share the queue and the recorded requests, so keep one clone for assertions after
+/// handing another to the code under test.
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    name: String,
+    capabilities: ProviderCapabilities,
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    replies: VecDeque<MockReply>,
+    requests: Vec<ChatCompletionRequest>,
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self {
+            name: "mock".into(),
+            capabilities: ProviderCapabilities::openai_compatible(),
+            state: Arc::default(),
+        }
+    }
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn provider_name(mut self, value: impl Into<String>) -> Self {
+        self.name = value.into();
+        self
+    }
+
+    pub fn provider_capabilities(mut self, value: ProviderCapabilities) -> Self {
+        self.capabilities = value;
+        self
+    }
+
+    /// Queues the next reply.
+    pub fn reply(&self, value: MockReply) -> &Self {
+        self.lock().replies.push_back(value);
+        self
+    }
+
+    pub fn requests(&self) -> Vec<ChatCompletionRequest> {
+        self.lock().requests.clone()
+    }
+
+    pub fn last_request(&self) -> Option<ChatCompletionRequest> {
+        self.lock().requests.last().cloned()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.lock().replies.len()
+    }
+
+    /// Panics unless every queued reply has been used.
+    #[track_caller]
+    pub fn assert_consumed(&self) {
+        let remaining = self.remaining();
+        assert!(
+            remaining == 0,
+            "mock provider still has {remaining} queued replies"
+        );
+    }
+
+    fn next(&self, request: ChatCompletionRequest) -> Result<MockReply> {
+        let mut state = self.lock();
+        state.requests.push(request);
+        state
+            .replies
+            .pop_front()
+            .ok_or_else(|| SdkError::Configuration("mock provider has no queued reply".into()))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl ChatProvider for MockProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities
+    }
+
+    async fn complete(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+        self.next(request)?.into_response()
+    }
+
+    async fn stream(&self, request: ChatCompletionRequest) -> Result<ChatStream> {
+        let items = match self.next(request)? {
+            MockReply::Error(error) => return Err(error),
+            reply => reply.into_stream_items(),
+        };
+        let stream = async_stream::try_stream! {
+            for item in items {
+                match item {
+                    MockStreamItem::Chunk(chunk) => yield *chunk,
+                    MockStreamItem::Delay(delay) => sleep(delay).await,
+                    MockStreamItem::Error(error) => Err(error)?,
+                }
+            }
+        };
+        Ok(Box::pin(stream))
+    }
+}
+
+/// A request received by [`ZhipuStubServer`].
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    pub method: Method,
+    /// Request target without the leading slash, including any query string.
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl StubRequest {
+    pub fn json(&self) -> Result<Value> {
+        nextjson::from_slice(&self.body).map_err(|error| SdkError::Decode {
+            message: error.to_string(),
+            body: String::from_utf8_lossy(&self.body).into_owned(),
+        })
+    }
+}
+
+/// A canned HTTP response for [`ZhipuStubServer`].
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: StubBody,
+}
+
+#[derive(Debug, Clone)]
+enum StubBody {
+    Full(Bytes),
+    Events(Vec<(Duration, String)>),
+}
+
+impl StubResponse {
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        let body = nextjson::to_vec(value)
+            .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+        Ok(Self {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: StubBody::Full(body.into()),
+        }
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json")))
+    }
+
+    /// A Zhipu error envelope: `{"error": {"code": ..., "message": ...}}`.
+    pub fn error(status: StatusCode, code: &str, message: &str) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body: StubBody::Full(
+                nextjson::to_vec(&json!({"error": {"code": code, "message": message}}))
+                    .unwrap_or_default()
+                    .into(),
+            ),
+        }
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+    }
+
+    /// Server-sent events, one `data:` line per value, `delay` apart, ending with `[DONE]`.
+    pub fn sse<T: Serialize>(events: impl IntoIterator<Item = T>, delay: Duration) -> Result<Self> {
+        Self::events(events.into_iter().map(|event| (delay, event)))
+    }
+
+    /// A chat completion body for `reply`, or its chunks as SSE when `stream` is set.
+    ///
+    /// Stream delays carry over to the SSE frames; a stream error is returned here instead.
+    pub fn chat(reply: MockReply, stream: bool) -> Result<Self> {
+        if !stream {
+            return Self::json(&reply.into_response()?);
+        }
+        let mut events = Vec::new();
+        let mut delay = Duration::ZERO;
+        for item in reply.into_stream_items() {
+            match item {
+                MockStreamItem::Chunk(chunk) => {
+                    events.push((delay, *chunk));
+                    delay = Duration::ZERO;
+                }
+                MockStreamItem::Delay(value) => delay += value,
+                MockStreamItem::Error(error) => return Err(error),
+            }
+        }
+        Self::events(events)
+    }
+
+    fn events<T: Serialize>(events: impl IntoIterator<Item = (Duration, T)>) -> Result<Self> {
+        let mut frames = Vec::new();
+        for (delay, event) in events {
+            let data = nextjson::to_string(&event)
+                .map_err(|error| SdkError::Validation(error.to_string().into()))?;
+            frames.push((delay, format!("data: {data}\n\n")));
+        }
+        frames.push((Duration::ZERO, "data: [DONE]\n\n".to_owned()));
+        Ok(Self {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: StubBody::Events(frames),
+        }
+        .header(CONTENT_TYPE, HeaderValue::from_static("text/event-stream")))
+    }
+
+    pub fn status(mut self, value: StatusCode) -> Self {
+        self.status = value;
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+}
+
+/// A loopback HTTP server that answers queued [`StubResponse`]s per method and path.
+///
+/// Requests without a queued response get a 404 Zhipu error. The server stops when dropped.
+pub struct ZhipuStubServer {
+    base_url: String,
+    state: Arc<Mutex<StubState>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct StubState {
+    routes: Vec<(Method, String, VecDeque<StubResponse>)>,
+    requests: Vec<StubRequest>,
+}
+
+impl ZhipuStubServer {
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|error| SdkError::io("stub server listener", error))?;
+        let address = listener
+            .local_addr()
+            .map_err(|error| SdkError::io("stub server listener", error))?;
+        let base_url = format!("http://{address}");
+        let state = Arc::new(Mutex::new(StubState::default()));
+        let shared = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, shared.clone()));
+            }
+        });
+        Ok(Self {
+            base_url,
+            state,
+            task,
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Queues `response` for the next `method` request to `path`, such as `"chat/completions"`.
+    pub fn route(&self, method: Method, path: &str, response: StubResponse) -> &Self {
+        let path = path.trim_matches('/').to_owned();
+        let mut state = lock(&self.state);
+        match state
+            .routes
+            .iter_mut()
+            .find(|(route_method, route_path, _)| *route_method == method && *route_path == path)
+        {
+            Some((_, _, queue)) => queue.push_back(response),
+            None => state
+                .routes
+                .push((method, path, VecDeque::from([response]))),
+        }
+        self
+    }
+
+    pub fn requests(&self) -> Vec<StubRequest> {
+        lock(&self.state).requests.clone()
+    }
+
+    /// A config with both base URLs pointed at this server and a placeholder API key.
+    pub fn config(&self) -> ZhipuConfig {
+        ZhipuConfig::new("stub-id.stub-secret")
+            .base_url(&self.base_url)
+            .agent_base_url(&self.base_url)
+    }
+
+    pub fn client(&self) -> Result<ZhipuClient> {
+        self.config().build()
+    }
+}
+
+impl Drop for ZhipuStubServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(mut socket: TcpStream, state: Arc<Mutex<StubState>>) {
+    let Ok(Some(request)) = read_request(&mut socket).await else {
+        return;
+    };
+    let route = request
+        .path
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+    let response = {
+        let mut state = lock(&state);
+        state.requests.push(request.clone());
+        state
+            .routes
+            .iter_mut()
+            .find(|(method, path, _)| *method == request.method && *path == route)
+            .and_then(|(_, _, queue)| queue.pop_front())
+    }
+    .unwrap_or_else(|| {
+        StubResponse::error(
+            StatusCode::NOT_FOUND,
+            "stub_not_found",
+            &format!("no stub response for {} {route}", request.method),
+        )
+    });
+    let _ = write_response(&mut socket, response).await;
+}
+
+async fn read_request(socket: &mut TcpStream) -> std::io::Result<Option<StubRequest>> {
+    let mut buffer = Vec::new();
+    let mut read = [0u8; 8192];
+    let header_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        let count = socket.read(&mut read).await?;
+        if count == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&read[..count]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Ok(None);
+    };
+    let Ok(method) = Method::from_bytes(method.as_bytes()) else {
+        return Ok(None);
+    };
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':')
+            && let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.trim().as_bytes()),
+                HeaderValue::from_str(value.trim()),
+            )
+        {
+            headers.append(name, value);
+        }
+    }
+    let chunked = headers
+        .get("transfer-encoding")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+    let length = headers
+        .get("content-length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = buffer.split_off(header_end + 4);
+    let body = loop {
+        if chunked {
+            if let Some(decoded) = decode_chunked(&body) {
+                break decoded;
+            }
+        } else if body.len() >= length {
+            body.truncate(length);
+            break body;
+        }
+        let count = socket.read(&mut read).await?;
+        if count == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&read[..count]);
+    };
+    Ok(Some(StubRequest {
+        method,
+        path: target.trim_start_matches('/').to_owned(),
+        headers,
+        body: body.into(),
+    }))
+}
+
+/// Decodes a complete chunked body, or returns `None` while more input is needed.
+fn decode_chunked(mut input: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = input.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&input[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        input = &input[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        if input.len() < size + 2 {
+            return None;
+        }
+        body.extend_from_slice(&input[..size]);
+        input = &input[size + 2..];
+    }
+}
+
+async fn write_response(socket: &mut TcpStream, response: StubResponse) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+        response.status.as_u16(),
+        response.status.canonical_reason().unwrap_or_default()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!(
+            "{name}: {}\r\n",
+            value.to_str().unwrap_or_default()
+        ));
+    }
+    match response.body {
+        StubBody::Full(body) => {
+            head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(&body).await?;
+        }
+        StubBody::Events(frames) => {
+            head.push_str("\r\n");
+            socket.write_all(head.as_bytes()).await?;
+            for (delay, frame) in frames {
+                if !delay.is_zero() {
+                    sleep(delay).await;
+                }
+                socket.write_all(frame.as_bytes()).await?;
+                socket.flush().await?;
+            }
+        }
+    }
+    socket.shutdown().await
+}
+
+fn lock(state: &Mutex<StubState>) -> std::sync::MutexGuard<'_, StubState> {
+    state
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+fn response(value: Value) -> Box<ChatCompletionResponse> {
+    Box::new(nextjson::from_value(value).unwrap_or_default())
+}
+
+fn chunk(value: Value) -> Box<ChatCompletionChunk> {
+    Box::new(nextjson::from_value(value).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::ChatMessage;
+
+    fn request() -> ChatCompletionRequest {
+        ChatCompletionRequest::new("glm-5").message(ChatMessage::user("hi"))
+    }
+
+    #[tokio::test]
+    async fn mock_provider_serves_queued_replies_either_way() {
+        let provider = MockProvider::new();
+        provider
+            .reply(MockReply::text("hello"))
+            .reply(MockReply::text_stream(
+                ["he", "llo"],
+                Duration::from_millis(1),
+            ))
+            .reply(MockReply::tool_call("call-1", "lookup", &json!({"q":"x"})))
+            .reply(MockReply::error(SdkError::Configuration("boom".into())));
+
+        let mut stream = provider.stream(request()).await.unwrap();
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.choices[0].finish_reason.as_deref(), Some("stop"));
+        let folded = provider.complete(request()).await.unwrap();
+        assert_eq!(folded.joined_text().as_deref(), Some("hello"));
+        let tools = provider
+            .stream(request())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        let delta = &tools[0].as_ref().unwrap().choices[0].delta;
+        assert_eq!(delta.tool_calls[0].id.as_deref(), Some("call-1"));
+        assert!(provider.complete(request()).await.is_err());
+        assert!(provider.complete(request()).await.is_err());
+        assert_eq!(provider.requests().len(), 5);
+        assert_eq!(provider.last_request().unwrap().model, "glm-5");
+        provider.assert_consumed();
+    }
+
+    #[tokio::test]
+    async fn stub_server_speaks_rest_and_sse_to_the_real_client() {
+        let server = ZhipuStubServer::start().await.unwrap();
+        server
+            .route(
+                Method::POST,
+                "chat/completions",
+                StubResponse::chat(MockReply::text("rest"), false).unwrap(),
+            )
+            .route(
+                Method::POST,
+                "/chat/completions/",
+                StubResponse::chat(MockReply::text_stream(["s", "se"], Duration::ZERO), true)
+                    .unwrap(),
+            )
+            .route(
+                Method::POST,
+                "embeddings",
+                StubResponse::error(StatusCode::TOO_MANY_REQUESTS, "1302", "slow down"),
+            );
+        let client = server.client().unwrap();
+        let response = client.chat_completion(&request()).await.unwrap();
+        assert_eq!(response.joined_text().as_deref(), Some("rest"));
+        let chunks = client
+            .chat_completion_stream(&request())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(chunks.len(), 3);
+        let error = client
+            .embedding(&crate::EmbeddingRequest::new("embedding-3", "x"))
+            .await
+            .unwrap_err();
+        match error {
+            SdkError::Api(error) => {
+                assert_eq!(error.status, StatusCode::TOO_MANY_REQUESTS);
+                assert_eq!(error.code.as_deref(), Some("1302"));
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+        assert!(client.chat_completion(&request()).await.is_err());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].json().unwrap()["model"].as_str(), Some("glm-5"));
+        assert!(requests[0].headers.contains_key("authorization"));
+        assert_eq!(decode_chunked(b"3\r\nabc\r\n0\r\n\r\n").unwrap(), b"abc");
+        assert!(decode_chunked(b"3\r\nab").is_none());
+    }
+}
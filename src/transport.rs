@@ -1,18 +1,34 @@
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use bytes::Bytes;
 use nextjson::NsonDeserialize as Deserialize;
 use nextjson::NsonSerialize as Serialize;
 use nextjson::Value;
-use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::header::{
+    ACCEPT, AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, USER_AGENT,
+};
+use reqwest::multipart::Form;
 use reqwest::{Client, Method, Response, StatusCode};
 use tokio::time::sleep;
 
 use crate::auth::AuthenticationProvider;
+use crate::failover::Endpoints;
+use crate::rate_limit::RatePermit;
 use crate::security::{
     DEFAULT_MAX_ERROR_BODY_BYTES, DEFAULT_MAX_RESPONSE_BYTES, mask_sensitive, validate_http_url,
 };
-use crate::{ApiError, Result, SdkError};
+use crate::telemetry::CallSpan;
+use crate::usage::UsageMeter;
+use crate::{
+    ApiError, Cassette, CassetteMode, FailoverConfig, Middleware, OutgoingRequest, RateLimiter,
+    ResponseInfo, Result, SdkError, Usage, UsageLedger,
+};
+
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+static IDEMPOTENCY_SEQUENCE: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Debug, Clone)]
 pub struct RetryPolicy {
@@ -20,6 +36,39 @@ pub struct RetryPolicy {
     pub initial_delay: Duration,
     pub max_delay: Duration,
     pub retry_statuses: Vec<StatusCode>,
+    /// POST endpoints retried like idempotent requests because they carry an idempotency key.
+    pub idempotent_endpoints: Vec<IdempotentEndpoint>,
+}
+
+/// A POST endpoint whose requests get a stable key that is reused on every retry and failover.
+///
+/// The key is sent as an `Idempotency-Key` header. With `key_field`, it is also the JSON body
+/// field of that name: a value the caller already set is reused, otherwise a generated key is
+/// written there. A failed request reports its key through [`SdkError::idempotency_key`], so a
+/// resend can carry the same one. Multipart uploads under the prefix are still sent only once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotentEndpoint {
+    pub path_prefix: String,
+    pub key_field: Option<String>,
+}
+
+impl RetryPolicy {
+    /// Makes POSTs under `path_prefix` safe to retry after connection failures and to re-send to
+    /// fallback base URLs.
+    pub fn idempotent_endpoint(
+        mut self,
+        path_prefix: impl Into<String>,
+        key_field: Option<&str>,
+    ) -> Self {
+        let path_prefix = path_prefix.into().trim_matches('/').to_owned();
+        self.idempotent_endpoints
+            .retain(|endpoint| endpoint.path_prefix != path_prefix);
+        self.idempotent_endpoints.push(IdempotentEndpoint {
+            path_prefix,
+            key_field: key_field.map(str::to_owned),
+        });
+        self
+    }
 }
 
 impl Default for RetryPolicy {
@@ -36,6 +85,7 @@ impl Default for RetryPolicy {
                 StatusCode::SERVICE_UNAVAILABLE,
                 StatusCode::GATEWAY_TIMEOUT,
             ],
+            idempotent_endpoints: Vec::new(),
         }
     }
 }
@@ -50,6 +100,10 @@ pub struct HttpConfig {
     pub user_agent: String,
     pub default_headers: HeaderMap,
     pub retry: RetryPolicy,
+    pub rate_limiter: Option<RateLimiter>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    pub cassette: Option<Cassette>,
+    pub usage_ledger: Option<UsageLedger>,
     pub http_client: Option<Client>,
 }
 
@@ -58,6 +112,29 @@ impl HttpConfig {
         self.allow_insecure = value;
         self
     }
+
+    pub fn rate_limiter(mut self, value: RateLimiter) -> Self {
+        self.rate_limiter = Some(value);
+        self
+    }
+
+    /// Appends a middleware; see [`Middleware`] for ordering.
+    pub fn middleware(mut self, value: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(value);
+        self
+    }
+
+    /// Records traffic to, or replays it from, a [`Cassette`] instead of the network.
+    pub fn cassette(mut self, value: Cassette) -> Self {
+        self.cassette = Some(value);
+        self
+    }
+
+    /// Records token and unit usage from every response into `value` and enforces its budgets.
+    pub fn usage_ledger(mut self, value: UsageLedger) -> Self {
+        self.usage_ledger = Some(value);
+        self
+    }
 }
 
 impl Default for HttpConfig {
@@ -71,6 +148,10 @@ impl Default for HttpConfig {
             user_agent: format!("RustGLM/{}", env!("CARGO_PKG_VERSION")),
             default_headers: HeaderMap::new(),
             retry: RetryPolicy::default(),
+            rate_limiter: None,
+            middleware: Vec::new(),
+            cassette: None,
+            usage_ledger: None,
             http_client: None,
         }
     }
@@ -79,18 +160,87 @@ impl Default for HttpConfig {
 #[derive(Clone)]
 pub(crate) struct Transport {
     client: Client,
-    base_url: String,
+    endpoints: Arc<Endpoints>,
     authentication: AuthenticationProvider,
     headers: HeaderMap,
     retry: RetryPolicy,
+    limiter: Option<RateLimiter>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    cassette: Option<Cassette>,
+    ledger: Option<UsageLedger>,
     max_response_bytes: usize,
 }
 
+/// A streaming response that holds its admission and call span until it is dropped.
+pub(crate) struct StreamResponse {
+    response: Response,
+    span: CallSpan,
+    admission: Admission,
+    /// The credentials to filter out of mid-stream failures, as `Transport::redact` does.
+    secrets: Vec<String>,
+}
+
+impl StreamResponse {
+    pub(crate) async fn chunk(&mut self) -> Result<Option<Bytes>> {
+        match self.response.chunk().await {
+            Ok(chunk) => {
+                if let Some(chunk) = &chunk {
+                    self.span.first_chunk();
+                    self.admission.observe(chunk);
+                }
+                Ok(chunk)
+            }
+            Err(error) => {
+                let error = error.into();
+                let secrets = self.secrets.iter().map(String::as_str).collect::<Vec<_>>();
+                self.span
+                    .failed(&error, |text| mask_sensitive(text, &secrets));
+                Err(error)
+            }
+        }
+    }
+
+    pub(crate) fn record_usage(&self, usage: &Usage) {
+        self.span.usage(usage);
+    }
+}
+
+/// The rate-limit permit and usage meter held for the lifetime of one request.
+struct Admission {
+    rate: RatePermit,
+    usage: Option<UsageMeter>,
+}
+
+impl Admission {
+    fn settle(&self, body: &[u8]) {
+        self.rate.settle(body);
+        if let Some(usage) = &self.usage {
+            usage.settle(body);
+        }
+    }
+
+    fn observe(&self, chunk: &[u8]) {
+        self.rate.observe(chunk);
+        if let Some(usage) = &self.usage {
+            usage.observe(chunk);
+        }
+    }
+}
+
 impl Transport {
     pub(crate) fn new(
         base_url: String,
         authentication: AuthenticationProvider,
         config: HttpConfig,
+    ) -> Result<Self> {
+        Self::with_failover(base_url, authentication, config, &FailoverConfig::default())
+    }
+
+    pub(crate) fn with_failover(
+        base_url: String,
+        authentication: AuthenticationProvider,
+        config: HttpConfig,
+        failover: &FailoverConfig,
     ) -> Result<Self> {
         if config.max_response_bytes == 0 {
             return Err(SdkError::Configuration(
@@ -98,6 +248,15 @@ impl Transport {
             ));
         }
         let base_url = normalize_base_url(base_url, config.allow_insecure)?;
+        let endpoints = if failover.is_enabled() {
+            let mut urls = vec![base_url];
+            for url in &failover.base_urls {
+                urls.push(normalize_base_url(url.clone(), config.allow_insecure)?);
+            }
+            Endpoints::new(urls, failover)?
+        } else {
+            Endpoints::single(base_url)
+        };
         let mut headers = config.default_headers;
         if !headers.contains_key(USER_AGENT) {
             headers.insert(
@@ -116,16 +275,20 @@ impl Transport {
         };
         Ok(Self {
             client,
-            base_url,
+            endpoints,
             authentication,
             headers,
             retry: config.retry,
+            limiter: config.rate_limiter,
+            middleware: config.middleware,
+            cassette: config.cassette,
+            ledger: config.usage_ledger,
             max_response_bytes: config.max_response_bytes,
         })
     }
 
     pub(crate) fn base_url(&self) -> &str {
-        &self.base_url
+        self.endpoints.primary()
     }
 
     pub(crate) async fn post_json<T, R>(&self, path: &str, body: &T) -> Result<R>
@@ -133,31 +296,46 @@ impl Transport {
         T: Serialize + ?Sized,
         R: for<'de> Deserialize<'de>,
     {
+        let body = encode(body)?;
+        let span = self.span(&Method::POST, path, Some(&body));
+        let permit = self.admit(&Method::POST, path, Some(&body)).await?;
         let response = self
             .send_bytes(
                 Method::POST,
                 path,
-                encode(body)?,
+                body,
                 "application/json",
                 "application/json",
+                &span,
             )
             .await?;
-        self.decode_json(response).await
+        self.decode_json(response, &permit, &span).await
     }
 
     pub(crate) async fn post_stream<T: Serialize + ?Sized>(
         &self,
         path: &str,
         body: &T,
-    ) -> Result<Response> {
-        self.send_bytes(
-            Method::POST,
-            path,
-            encode(body)?,
-            "application/json",
-            "text/event-stream",
-        )
-        .await
+    ) -> Result<StreamResponse> {
+        let body = encode(body)?;
+        let span = self.span(&Method::POST, path, Some(&body));
+        let permit = self.admit(&Method::POST, path, Some(&body)).await?;
+        let response = self
+            .send_bytes(
+                Method::POST,
+                path,
+                body,
+                "application/json",
+                "text/event-stream",
+                &span,
+            )
+            .await?;
+        Ok(StreamResponse {
+            response,
+            span,
+            admission: permit,
+            secrets: self.secrets(),
+        })
     }
 
     #[cfg_attr(not(feature = "rag"), allow(dead_code))]
@@ -166,16 +344,29 @@ impl Transport {
         path: &str,
         body: &T,
         headers: HeaderMap,
-    ) -> Result<Response> {
-        self.send_bytes_with_headers(
+    ) -> Result<StreamResponse> {
+        let body = encode(body)?;
+        let span = self.span(&Method::POST, path, Some(&body));
+        let permit = self.admit(&Method::POST, path, Some(&body)).await?;
+        let mut request = self.body_request(
             Method::POST,
             path,
-            encode(body)?,
+            body,
             "application/json",
             "text/event-stream",
-            headers,
-        )
-        .await
+        )?;
+        for (name, value) in &headers {
+            if name != CONTENT_TYPE {
+                request.headers.insert(name.clone(), value.clone());
+            }
+        }
+        let response = self.send_with(request, &span).await?;
+        Ok(StreamResponse {
+            response,
+            span,
+            admission: permit,
+            secrets: self.secrets(),
+        })
     }
 
     #[cfg_attr(not(feature = "audio"), allow(dead_code))]
@@ -185,37 +376,46 @@ impl Transport {
         body: &T,
         accept: &str,
     ) -> Result<Bytes> {
+        let body = encode(body)?;
+        let span = self.span(&Method::POST, path, Some(&body));
+        let permit = self.admit(&Method::POST, path, Some(&body)).await?;
         let mut response = self
-            .send_bytes(
-                Method::POST,
-                path,
-                encode(body)?,
-                "application/json",
-                accept,
-            )
+            .send_bytes(Method::POST, path, body, "application/json", accept, &span)
             .await?;
-        self.read_body(&mut response, "binary").await
+        let body = self.read_body(&mut response, "binary", &span).await?;
+        // Binary bodies carry no usage; this only counts the call's billable unit.
+        permit.settle(&[]);
+        Ok(body)
     }
 
     pub(crate) async fn get_json<R: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<R> {
+        let span = self.span(&Method::GET, path, None);
+        let permit = self.admit(&Method::GET, path, None).await?;
         let response = self
-            .send_empty(Method::GET, path, "application/json")
+            .send_empty(Method::GET, path, "application/json", &span)
             .await?;
-        self.decode_json(response).await
+        self.decode_json(response, &permit, &span).await
     }
 
     #[cfg_attr(not(feature = "files"), allow(dead_code))]
     pub(crate) async fn get_binary(&self, path: &str) -> Result<Bytes> {
-        let mut response = self.send_empty(Method::GET, path, "*/*").await?;
-        self.read_body(&mut response, "binary").await
+        let span = self.span(&Method::GET, path, None);
+        let permit = self.admit(&Method::GET, path, None).await?;
+        let mut response = self.send_empty(Method::GET, path, "*/*", &span).await?;
+        let body = self.read_body(&mut response, "binary", &span).await?;
+        // Binary bodies carry no usage; this only counts the call's billable unit.
+        permit.settle(&[]);
+        Ok(body)
     }
 
     #[cfg_attr(not(any(feature = "files", feature = "rag")), allow(dead_code))]
     pub(crate) async fn delete_json<R: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<R> {
+        let span = self.span(&Method::DELETE, path, None);
+        let permit = self.admit(&Method::DELETE, path, None).await?;
         let response = self
-            .send_empty(Method::DELETE, path, "application/json")
+            .send_empty(Method::DELETE, path, "application/json", &span)
             .await?;
-        self.decode_json(response).await
+        self.decode_json(response, &permit, &span).await
     }
 
     #[cfg_attr(
@@ -227,18 +427,26 @@ impl Transport {
         path: &str,
         form: reqwest::multipart::Form,
     ) -> Result<R> {
-        let url = self.url(path)?;
-        let response = self
-            .client
-            .post(url)
-            .headers(self.headers.clone())
-            .header(AUTHORIZATION, self.authentication.header_value()?)
-            .header(ACCEPT, "application/json")
-            .multipart(form)
-            .send()
-            .await?;
-        let response = self.ensure_success(response).await?;
-        self.decode_json(response).await
+        validate_path(path)?;
+        let span = self.span(&Method::POST, path, None);
+        let permit = self.admit(&Method::POST, path, None).await?;
+        // Forms cannot be replayed, so uploads go to one endpoint without failover.
+        let endpoint = self.endpoints.select(&[]).unwrap_or(0);
+        let request = self.outgoing(Method::POST, path, "application/json", None)?;
+        let response = span
+            .run(async {
+                let (response, synthetic) = self.dispatch(endpoint, request, Some(form)).await?;
+                if !synthetic {
+                    self.endpoints
+                        .record(endpoint, !is_endpoint_failure(&response));
+                }
+                let response = response?;
+                span.response(response.status(), response.headers());
+                self.ensure_success(response).await
+            })
+            .await
+            .inspect_err(|error| span.failed(error, |text| self.redact(text)))?;
+        self.decode_json(response, &permit, &span).await
     }
 
     pub(crate) async fn request_json<T, R>(
@@ -251,20 +459,44 @@ impl Transport {
         T: Serialize + ?Sized,
         R: for<'de> Deserialize<'de>,
     {
+        let body = body.map(encode).transpose()?;
+        let span = self.span(&method, path, body.as_deref());
+        let permit = self.admit(&method, path, body.as_deref()).await?;
         let response = match body {
             Some(body) => {
                 self.send_bytes(
                     method,
                     path,
-                    encode(body)?,
+                    body,
                     "application/json",
                     "application/json",
+                    &span,
                 )
                 .await?
             }
-            None => self.send_empty(method, path, "application/json").await?,
+            None => {
+                self.send_empty(method, path, "application/json", &span)
+                    .await?
+            }
         };
-        self.decode_json(response).await
+        self.decode_json(response, &permit, &span).await
+    }
+
+    fn span(&self, method: &Method, path: &str, body: Option<&[u8]>) -> CallSpan {
+        CallSpan::start(method, self.base_url(), path, body)
+    }
+
+    /// Checks usage budgets, then waits for the rate limiter.
+    async fn admit(&self, method: &Method, path: &str, body: Option<&[u8]>) -> Result<Admission> {
+        let usage = match &self.ledger {
+            Some(ledger) => UsageMeter::start(ledger, *method == Method::POST, path, body)?,
+            None => None,
+        };
+        let rate = match &self.limiter {
+            Some(limiter) => limiter.acquire(path, body.map_or(0, <[u8]>::len)).await?,
+            None => RatePermit::default(),
+        };
+        Ok(Admission { rate, usage })
     }
 
     async fn send_bytes(
@@ -274,34 +506,99 @@ impl Transport {
         body: Bytes,
         content_type: &str,
         accept: &str,
+        span: &CallSpan,
+    ) -> Result<Response> {
+        let request = self.body_request(method, path, body, content_type, accept)?;
+        self.send_with(request, span).await
+    }
+
+    async fn send_empty(
+        &self,
+        method: Method,
+        path: &str,
+        accept: &str,
+        span: &CallSpan,
     ) -> Result<Response> {
-        self.send_bytes_with_headers(method, path, body, content_type, accept, HeaderMap::new())
+        self.send_with(self.outgoing(method, path, accept, None)?, span)
             .await
     }
 
-    async fn send_bytes_with_headers(
+    fn body_request(
         &self,
         method: Method,
         path: &str,
         body: Bytes,
         content_type: &str,
         accept: &str,
-        extra_headers: HeaderMap,
+    ) -> Result<OutgoingRequest> {
+        let mut request = self.outgoing(method, path, accept, Some(body))?;
+        request
+            .headers
+            .insert(CONTENT_TYPE, header_value(content_type)?);
+        Ok(request)
+    }
+
+    fn outgoing(
+        &self,
+        method: Method,
+        path: &str,
+        accept: &str,
+        body: Option<Bytes>,
+    ) -> Result<OutgoingRequest> {
+        let mut headers = self.headers.clone();
+        headers.insert(ACCEPT, header_value(accept)?);
+        Ok(OutgoingRequest {
+            method,
+            path: path.to_owned(),
+            headers,
+            body,
+        })
+    }
+
+    /// Sends with retries, moving to the next healthy endpoint after a failure when the request
+    /// is idempotent or its path is marked retry-safe.
+    async fn send_with(&self, request: OutgoingRequest, span: &CallSpan) -> Result<Response> {
+        let (request, key) = self.idempotency_key(request)?;
+        span.run(self.send_attempts(request, key.is_some(), span))
+            .await
+            .map_err(|error| match &key {
+                Some(key) => error.with_idempotency_key(key),
+                None => error,
+            })
+            .inspect_err(|error| span.failed(error, |text| self.redact(text)))
+    }
+
+    async fn send_attempts(
+        &self,
+        request: OutgoingRequest,
+        keyed: bool,
+        span: &CallSpan,
     ) -> Result<Response> {
-        let url = self.url(path)?;
+        validate_path(&request.path)?;
+        let method = request.method.clone();
+        let replayable = keyed || is_idempotent(&method);
+        let can_fail_over = replayable || self.endpoints.retry_safe(&request.path);
+        let mut tried = Vec::new();
+        let mut endpoint = self.endpoints.select(&tried).unwrap_or(0);
         let mut attempt = 0;
+        let mut resends = 0;
         loop {
-            let response = self
-                .client
-                .request(method.clone(), &url)
-                .headers(self.headers.clone())
-                .headers(extra_headers.clone())
-                .header(AUTHORIZATION, self.authentication.header_value()?)
-                .header(CONTENT_TYPE, content_type)
-                .header(ACCEPT, accept)
-                .body(body.clone())
-                .send()
-                .await;
+            if resends > 0 {
+                span.resend(resends);
+            }
+            resends += 1;
+            let (response, synthetic) = self.dispatch(endpoint, request.clone(), None).await?;
+            let failed = !synthetic && is_endpoint_failure(&response);
+            if !synthetic {
+                self.endpoints.record(endpoint, !failed);
+            }
+            if failed && can_fail_over {
+                tried.push(endpoint);
+                if let Some(next) = self.endpoints.select(&tried) {
+                    endpoint = next;
+                    continue;
+                }
+            }
             match response {
                 Ok(response)
                     if self.retry.retry_statuses.contains(&response.status())
@@ -312,10 +609,13 @@ impl Transport {
                     sleep(delay).await;
                     attempt += 1;
                 }
-                Ok(response) => return self.ensure_success(response).await,
+                Ok(response) => {
+                    span.response(response.status(), response.headers());
+                    return self.ensure_success(response).await;
+                }
                 Err(error)
                     if (error.is_connect() || error.is_timeout())
-                        && is_idempotent(&method)
+                        && replayable
                         && attempt < self.retry.max_retries =>
                 {
                     sleep(backoff(&self.retry, attempt)).await;
@@ -323,43 +623,123 @@ impl Transport {
                 }
                 Err(error) => return Err(error.into()),
             }
+            if can_fail_over {
+                tried.clear();
+                endpoint = self.endpoints.select(&tried).unwrap_or(0);
+            }
         }
     }
 
-    async fn send_empty(&self, method: Method, path: &str, accept: &str) -> Result<Response> {
-        let url = self.url(path)?;
-        let mut attempt = 0;
-        loop {
-            let response = self
-                .client
-                .request(method.clone(), &url)
-                .headers(self.headers.clone())
-                .header(AUTHORIZATION, self.authentication.header_value()?)
-                .header(ACCEPT, accept)
-                .send()
-                .await;
-            match response {
-                Ok(response)
-                    if self.retry.retry_statuses.contains(&response.status())
-                        && attempt < self.retry.max_retries =>
-                {
-                    let delay = retry_delay(&response, &self.retry, attempt);
-                    drop(response);
-                    sleep(delay).await;
-                    attempt += 1;
-                }
-                Ok(response) => return self.ensure_success(response).await,
-                Err(error)
-                    if (error.is_connect() || error.is_timeout())
-                        && is_idempotent(&method)
-                        && attempt < self.retry.max_retries =>
-                {
-                    sleep(backoff(&self.retry, attempt)).await;
-                    attempt += 1;
+    /// Attaches the idempotency key for POSTs under an idempotent endpoint and returns it.
+    fn idempotency_key(
+        &self,
+        mut request: OutgoingRequest,
+    ) -> Result<(OutgoingRequest, Option<String>)> {
+        let Some(endpoint) = self
+            .retry
+            .idempotent_endpoints
+            .iter()
+            .find(|endpoint| path_has_prefix(&request.path, &endpoint.path_prefix))
+            .filter(|_| !is_idempotent(&request.method))
+        else {
+            return Ok((request, None));
+        };
+        let mut key = request
+            .headers
+            .get(IDEMPOTENCY_KEY)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        if let (Some(field), Some(body)) = (&endpoint.key_field, &request.body)
+            && let Ok(Value::Object(mut object)) = nextjson::from_slice::<Value>(body)
+        {
+            match object.get(field).and_then(Value::as_str) {
+                Some(value) => key = Some(value.to_owned()),
+                None => {
+                    let value = key.clone().map_or_else(generate_idempotency_key, Ok)?;
+                    object.insert(field.clone(), Value::String(value.clone()));
+                    request.body = Some(encode(&Value::Object(object))?);
+                    key = Some(value);
                 }
-                Err(error) => return Err(error.into()),
             }
         }
+        let key = key.map_or_else(generate_idempotency_key, Ok)?;
+        request.headers.insert(IDEMPOTENCY_KEY, header_value(&key)?);
+        Ok((request, Some(key)))
+    }
+
+    /// Runs one attempt through the middleware chain. The flag reports whether a middleware
+    /// answered instead of the server.
+    async fn dispatch(
+        &self,
+        endpoint: usize,
+        mut request: OutgoingRequest,
+        form: Option<Form>,
+    ) -> Result<(reqwest::Result<Response>, bool)> {
+        request
+            .headers
+            .insert(AUTHORIZATION, self.authentication.header_value()?);
+        let started = Instant::now();
+        let mut answered = None;
+        for (index, middleware) in self.middleware.iter().enumerate() {
+            if let Some(response) = middleware.on_request(&mut request).await? {
+                answered = Some((index, response));
+                break;
+            }
+        }
+        let (observers, response, synthetic) = match answered {
+            Some((index, response)) => (index, Ok(response.into_response()), true),
+            None => {
+                validate_path(&request.path)?;
+                let response = match &self.cassette {
+                    Some(cassette) if cassette.mode() == CassetteMode::Replay => {
+                        Ok(cassette.replay_response(&request, &self.secrets())?)
+                    }
+                    cassette => {
+                        let mut builder = self
+                            .client
+                            .request(
+                                request.method.clone(),
+                                self.endpoint_url(endpoint, &request.path),
+                            )
+                            .headers(request.headers.clone());
+                        if let Some(body) = request.body.clone() {
+                            builder = builder.body(body);
+                        }
+                        if let Some(form) = form {
+                            builder = builder.multipart(form);
+                        }
+                        match (cassette, builder.send().await) {
+                            (Some(cassette), Ok(response)) => Ok(cassette.record_response(
+                                &request,
+                                response,
+                                started,
+                                self.secrets(),
+                            )),
+                            (_, response) => response,
+                        }
+                    }
+                };
+                (self.middleware.len(), response, false)
+            }
+        };
+        if observers > 0 {
+            let (status, headers) = match &response {
+                Ok(response) => (Some(response.status()), response.headers().clone()),
+                Err(error) => (error.status(), HeaderMap::new()),
+            };
+            let info = ResponseInfo {
+                method: request.method,
+                path: request.path,
+                status,
+                headers,
+                latency: started.elapsed(),
+                synthetic,
+            };
+            for middleware in self.middleware[..observers].iter().rev() {
+                middleware.on_response(&info).await?;
+            }
+        }
+        Ok((response, synthetic))
     }
 
     async fn ensure_success(&self, response: Response) -> Result<Response> {
@@ -413,18 +793,33 @@ impl Transport {
         }))
     }
 
-    async fn decode_json<R: for<'de> Deserialize<'de>>(&self, response: Response) -> Result<R> {
+    async fn decode_json<R: for<'de> Deserialize<'de>>(
+        &self,
+        response: Response,
+        permit: &Admission,
+        span: &CallSpan,
+    ) -> Result<R> {
         let mut response = response;
-        let body = self.read_body(&mut response, "json").await?;
-        nextjson::from_slice(&body).map_err(|error| SdkError::Decode {
-            message: error.to_string(),
-            body: self.redact(&String::from_utf8_lossy(&body)),
-        })
+        let body = self.read_body(&mut response, "json", span).await?;
+        permit.settle(&body);
+        span.body(&body);
+        nextjson::from_slice(&body)
+            .map_err(|error| SdkError::Decode {
+                message: error.to_string(),
+                body: self.redact(&String::from_utf8_lossy(&body)),
+            })
+            .inspect_err(|error| span.failed(error, |text| self.redact(text)))
     }
 
-    async fn read_body(&self, response: &mut Response, kind: &'static str) -> Result<Bytes> {
+    async fn read_body(
+        &self,
+        response: &mut Response,
+        kind: &'static str,
+        span: &CallSpan,
+    ) -> Result<Bytes> {
         self.read_body_limited(response, kind, self.max_response_bytes)
             .await
+            .inspect_err(|error| span.failed(error, |text| self.redact(text)))
     }
 
     async fn read_body_limited(
@@ -449,6 +844,10 @@ impl Transport {
         Ok(Bytes::from(body))
     }
 
+    fn secrets(&self) -> Vec<String> {
+        self.authentication.token().into_iter().collect()
+    }
+
     fn redact(&self, text: &str) -> String {
         match self.authentication.token() {
             Ok(token) => mask_sensitive(text, &[&token]),
@@ -456,19 +855,19 @@ impl Transport {
         }
     }
 
-    fn url(&self, path: &str) -> Result<String> {
-        validate_path(path)?;
-        Ok(format!(
+    fn endpoint_url(&self, endpoint: usize, path: &str) -> String {
+        format!(
             "{}/{}",
-            self.base_url,
+            self.endpoints.url(endpoint),
             path.trim_start_matches('/')
-        ))
+        )
     }
 
     #[cfg(test)]
     #[allow(dead_code)]
     pub(crate) fn url_for_test(&self, path: &str) -> Result<String> {
-        self.url(path)
+        validate_path(path)?;
+        Ok(self.endpoint_url(0, path))
     }
 }
 
@@ -500,6 +899,22 @@ fn validate_path(path: &str) -> Result<()> {
     Ok(())
 }
 
+/// Whether `path` is `prefix` or lies below it; query strings are ignored.
+pub(crate) fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    path.trim_start_matches('/')
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?']))
+}
+
+fn generate_idempotency_key() -> Result<String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| SdkError::Configuration("system clock is before Unix epoch".into()))?
+        .as_nanos();
+    let sequence = IDEMPOTENCY_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    Ok(format!("rustglm-{timestamp:x}-{sequence}"))
+}
+
 fn value_to_string(value: &Value) -> Option<String> {
     match value {
         Value::String(value) => Some(value.clone()),
@@ -526,6 +941,19 @@ fn backoff(retry: &RetryPolicy, attempt: u32) -> Duration {
         .min(retry.max_delay)
 }
 
+fn header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|_| SdkError::Validation(format!("invalid header value {value:?}").into()))
+}
+
+/// Connection failures and 5xx responses count against an endpoint's circuit; 4xx answers,
+/// including 429, mean the endpoint itself is up.
+fn is_endpoint_failure(response: &reqwest::Result<Response>) -> bool {
+    response
+        .as_ref()
+        .map_or(true, |response| response.status().is_server_error())
+}
+
 fn is_idempotent(method: &Method) -> bool {
     matches!(
         *method,
@@ -672,6 +1100,237 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn cloned_transports_share_the_rate_limiter() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: application/json\r\n",
+                body: r#"{"usage":{"total_tokens":500}}"#,
+            },
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: application/json\r\n",
+                body: "{}",
+            },
+        ])
+        .await;
+        let limiter = RateLimiter::new(
+            crate::RateLimits::new()
+                .tokens_per_minute(6_000)
+                .max_in_flight(1),
+        )
+        .unwrap();
+        let client = Transport::new(
+            base_url,
+            AuthenticationProvider::bearer("secret").unwrap(),
+            HttpConfig::default().rate_limiter(limiter.clone()),
+        )
+        .unwrap();
+        let _: Value = client
+            .clone()
+            .post_json("chat/completions", &json!({"model": "glm"}))
+            .await
+            .unwrap();
+        let available = limiter.available_tokens().unwrap();
+        assert!((5_500.0..5_510.0).contains(&available), "{available}");
+        let _: Value = client.get_json("models").await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn usage_ledger_meters_json_and_streams_and_refuses_spent_budgets() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: application/json\r\n",
+                body: r#"{"model":"glm-5","usage":{"prompt_tokens":6,"completion_tokens":2,"total_tokens":8,"prompt_tokens_details":{"cached_tokens":4}}}"#,
+            },
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: text/event-stream\r\n",
+                body: "data: {\"model\":\"glm-5\",\"usage\":{\"total_tokens\":5}}\n\ndata: [DONE]\n\n",
+            },
+        ])
+        .await;
+        let ledger =
+            crate::UsageLedger::new().user_budget("u-1", crate::UsageBudget::new().max_tokens(8));
+        let client = Transport::new(
+            base_url,
+            AuthenticationProvider::bearer("secret").unwrap(),
+            HttpConfig::default().usage_ledger(ledger.tagged("batch")),
+        )
+        .unwrap();
+        let body = json!({"model": "glm-5", "user_id": "u-1"});
+        let _: Value = client.post_json("chat/completions", &body).await.unwrap();
+        let error = client
+            .post_json::<_, Value>("chat/completions", &body)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            SdkError::Budget(crate::BudgetError::Tokens { limit: 8, .. })
+        ));
+        let mut stream = client
+            .post_stream("chat/completions", &json!({"model": "glm-5"}))
+            .await
+            .unwrap();
+        while stream.chunk().await.unwrap().is_some() {}
+        drop(stream);
+
+        let report = ledger.report();
+        assert_eq!(report.total.requests, 2);
+        assert_eq!(report.total.total_tokens, 13);
+        assert_eq!(report.by_user["u-1"].cached_tokens, 4);
+        assert_eq!(report.by_tag["batch"].requests, 2);
+        assert_eq!(server.await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_over_retry_safe_requests_and_skips_open_circuits() {
+        let unavailable = || MockResponse {
+            status: "503 Service Unavailable",
+            headers: "Content-Type: application/json\r\n",
+            body: r#"{"error":{"message":"down"}}"#,
+        };
+        let ok = || MockResponse {
+            status: "200 OK",
+            headers: "Content-Type: application/json\r\n",
+            body: "{}",
+        };
+        let (primary, primary_server) = mock_server(vec![unavailable()]).await;
+        let (mirror, mirror_server) = mock_server(vec![ok(), ok()]).await;
+        let failover = FailoverConfig {
+            base_urls: vec![mirror],
+            circuit_breaker: crate::CircuitBreakerConfig::default().consecutive_failures(1),
+            retry_safe_paths: Vec::new(),
+        };
+        let client = Transport::with_failover(
+            primary,
+            AuthenticationProvider::bearer("secret").unwrap(),
+            HttpConfig::default(),
+            &failover,
+        )
+        .unwrap();
+        let _: Value = client.get_json("models").await.unwrap();
+        let _: Value = client
+            .post_json("chat/completions", &json!({}))
+            .await
+            .unwrap();
+        assert_eq!(primary_server.await.unwrap().len(), 1);
+        let mirrored = mirror_server.await.unwrap();
+        assert!(mirrored[0].starts_with("GET /models "));
+        assert!(mirrored[1].starts_with("POST /chat/completions "));
+
+        let (primary, primary_server) = mock_server(vec![unavailable()]).await;
+        let (mirror, mirror_server) = mock_server(Vec::new()).await;
+        let client = Transport::with_failover(
+            primary,
+            AuthenticationProvider::bearer("secret").unwrap(),
+            HttpConfig::default(),
+            &FailoverConfig {
+                base_urls: vec![mirror],
+                ..FailoverConfig::default()
+            },
+        )
+        .unwrap();
+        let error = client
+            .post_json::<_, Value>("chat/completions", &json!({}))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SdkError::Api(_)));
+        assert_eq!(primary_server.await.unwrap().len(), 1);
+        assert!(mirror_server.await.unwrap().is_empty());
+    }
+
+    struct Recorder {
+        seen: std::sync::Mutex<Vec<ResponseInfo>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for Recorder {
+        async fn on_request(
+            &self,
+            request: &mut OutgoingRequest,
+        ) -> Result<Option<crate::SyntheticResponse>> {
+            assert!(!format!("{request:?}").contains("secret"));
+            request
+                .headers
+                .insert("x-tenant", HeaderValue::from_static("acme"));
+            Ok(None)
+        }
+
+        async fn on_response(&self, response: &ResponseInfo) -> Result<()> {
+            self.seen.lock().unwrap().push(response.clone());
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Stub {
+        observed: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for Stub {
+        async fn on_request(
+            &self,
+            request: &mut OutgoingRequest,
+        ) -> Result<Option<crate::SyntheticResponse>> {
+            if request.path == "stub" {
+                return crate::SyntheticResponse::json(StatusCode::OK, &json!({"stubbed": true}))
+                    .map(Some);
+            }
+            request.path = "models/rewritten".into();
+            Ok(None)
+        }
+
+        async fn on_response(&self, _: &ResponseInfo) -> Result<()> {
+            self.observed
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn middleware_mutates_observes_and_short_circuits() {
+        let (base_url, server) = mock_server(vec![MockResponse {
+            status: "200 OK",
+            headers: "Content-Type: application/json\r\nX-Request-Id: req-9\r\n",
+            body: "{}",
+        }])
+        .await;
+        let recorder = Arc::new(Recorder {
+            seen: std::sync::Mutex::new(Vec::new()),
+        });
+        let stub = Arc::new(Stub::default());
+        let client = Transport::new(
+            base_url,
+            AuthenticationProvider::bearer("secret").unwrap(),
+            HttpConfig::default()
+                .middleware(recorder.clone())
+                .middleware(stub.clone()),
+        )
+        .unwrap();
+        let stubbed: Value = client.get_json("stub").await.unwrap();
+        assert_eq!(stubbed, json!({"stubbed": true}));
+        let _: Value = client.get_json("models").await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("GET /models/rewritten "));
+        assert!(requests[0].contains("x-tenant: acme"));
+        assert!(requests[0].contains("authorization: Bearer secret"));
+        let seen = recorder.seen.lock().unwrap();
+        assert!(seen[0].synthetic);
+        assert_eq!(seen[0].path, "stub");
+        assert!(!seen[1].synthetic);
+        assert_eq!(seen[1].status, Some(StatusCode::OK));
+        assert_eq!(seen[1].headers["x-request-id"], "req-9");
+        // The stub answered the first request itself, so it only observed the second.
+        assert_eq!(stub.observed.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
     #[tokio::test]
     async fn decodes_nested_root_and_invalid_api_responses() {
         let (base_url, server) = mock_server(vec![
@@ -734,6 +1393,111 @@ mod tests {
         assert_eq!(server.await.unwrap().len(), 4);
     }
 
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct SpanRecorder {
+        fields: std::sync::Mutex<Vec<(String, String)>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for &SpanRecorder {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.fields
+                .lock()
+                .unwrap()
+                .push((field.name().to_owned(), format!("{value:?}")));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for &'static SpanRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            span.record(&mut &**self);
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            values.record(&mut &**self);
+        }
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            event.record(&mut &**self);
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn records_gen_ai_span_fields_and_metrics() {
+        let recorder: &'static SpanRecorder = Box::leak(Box::default());
+        let _guard = tracing::subscriber::set_default(recorder);
+        let (base_url, server) = mock_server(vec![
+            MockResponse {
+                status: "429 Too Many Requests",
+                headers: "Retry-After: 0\r\n",
+                body: "retry",
+            },
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: application/json\r\nX-Request-Id: req-1\r\n",
+                body: r#"{"id":"resp-1","model":"glm-5","usage":{"prompt_tokens":3,"completion_tokens":5,"total_tokens":8}}"#,
+            },
+            MockResponse {
+                status: "401 Unauthorized",
+                headers: "",
+                body: r#"{"error":{"message":"bad key secret"}}"#,
+            },
+        ])
+        .await;
+        let client = transport(
+            base_url,
+            RetryPolicy {
+                max_retries: 1,
+                initial_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+                ..RetryPolicy::default()
+            },
+        );
+        let _: Value = client
+            .post_json("chat/completions", &json!({"model":"glm-5","messages":[]}))
+            .await
+            .unwrap();
+        assert!(client.get_json::<Value>("files").await.is_err());
+        server.await.unwrap();
+
+        let fields = recorder.fields.lock().unwrap().clone();
+        let has = |name: &str, value: &str| {
+            fields
+                .iter()
+                .any(|(field, recorded)| field == name && recorded.trim_matches('"') == value)
+        };
+        assert!(has("gen_ai.operation.name", "chat"));
+        assert!(has("gen_ai.request.model", "glm-5"));
+        assert!(has("gen_ai.response.id", "resp-1"));
+        assert!(has("http.request.resend_count", "1"));
+        assert!(has("http.response.status_code", "200"));
+        assert!(has("zhipu.request_id", "req-1"));
+        assert!(has("gen_ai.usage.input_tokens", "3"));
+        assert!(has("gen_ai.usage.output_tokens", "5"));
+        assert!(has("gen_ai.token.type", "output"));
+        assert!(has("error.type", "401"));
+        assert!(
+            fields
+                .iter()
+                .any(|(field, _)| field == "histogram.gen_ai.client.operation.duration")
+        );
+        assert!(!fields.iter().any(|(_, value)| value.contains("secret")));
+    }
+
     #[tokio::test]
     async fn retries_statuses_for_body_and_empty_requests() {
         let (base_url, server) = mock_server(vec![
@@ -840,6 +1604,168 @@ mod tests {
         assert!(!is_idempotent(&Method::PATCH));
     }
 
+    #[tokio::test]
+    async fn keyed_posts_reuse_their_key_across_retries_and_failover() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse {
+                status: "503 Service Unavailable",
+                headers: "Retry-After: 0\r\n",
+                body: "retry",
+            },
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: application/json\r\n",
+                body: r#"{"ok":true}"#,
+            },
+            MockResponse {
+                status: "401 Unauthorized",
+                headers: "",
+                body: "denied",
+            },
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: application/json\r\n",
+                body: r#"{"ok":true}"#,
+            },
+        ])
+        .await;
+        let retry = RetryPolicy {
+            max_retries: 1,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..RetryPolicy::default()
+        }
+        .idempotent_endpoint("/chat/completions", Some("request_id"))
+        .idempotent_endpoint("batches", None);
+        let client = transport(base_url.clone(), retry.clone());
+        let _: Value = client
+            .post_json("chat/completions", &json!({"model":"glm-5"}))
+            .await
+            .unwrap();
+        let error = client
+            .post_json::<_, Value>("chat/completions", &json!({"request_id":"caller-key"}))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SdkError::Api(_)));
+        assert_eq!(error.idempotency_key(), Some("caller-key"));
+
+        let unreachable = transport("http://127.0.0.1:1".into(), retry.clone());
+        let error = unreachable
+            .post_json::<_, Value>("batches", &json!({}))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SdkError::Transport(_)));
+        assert!(error.idempotency_key().unwrap().starts_with("rustglm-"));
+        assert!(error.to_string().contains(error.idempotency_key().unwrap()));
+        let unkeyed = unreachable.post_json::<_, Value>("files", &json!({})).await;
+        assert!(matches!(unkeyed, Err(SdkError::Transport(_))));
+        assert_eq!(unkeyed.unwrap_err().idempotency_key(), None);
+
+        let failover = Transport::with_failover(
+            "http://127.0.0.1:1".into(),
+            AuthenticationProvider::bearer("secret").unwrap(),
+            HttpConfig {
+                retry,
+                ..HttpConfig::default()
+            },
+            &FailoverConfig {
+                base_urls: vec![base_url],
+                ..FailoverConfig::default()
+            },
+        )
+        .unwrap();
+        let _: Value = failover.post_json("batches", &json!({})).await.unwrap();
+
+        let requests = server.await.unwrap();
+        let key = |request: &str| {
+            request
+                .lines()
+                .find_map(|line| line.strip_prefix("idempotency-key: "))
+                .map(str::to_owned)
+        };
+        let first = key(&requests[0]).unwrap();
+        assert_eq!(key(&requests[1]), Some(first.clone()));
+        assert!(requests[0].contains(&format!(r#""request_id":"{first}""#)));
+        assert!(requests[1].contains(&format!(r#""request_id":"{first}""#)));
+        assert_eq!(key(&requests[2]).as_deref(), Some("caller-key"));
+        assert!(key(&requests[3]).is_some());
+    }
+
+    #[tokio::test]
+    async fn cassettes_record_and_replay_without_the_network() {
+        let (base_url, server) = mock_server(vec![
+            MockResponse {
+                status: "200 OK",
+                headers: "Content-Type: text/event-stream\r\nSet-Cookie: session=abc\r\n",
+                body: "data: {\"echo\":\"secret\"}\n\ndata: [DONE]\n\n",
+            },
+            MockResponse {
+                status: "429 Too Many Requests",
+                headers: "",
+                body: r#"{"error":{"message":"slow down"}}"#,
+            },
+        ])
+        .await;
+        let directory = crate::test_support::TempDir::new("cassette");
+        let path = directory.join("cassette.json");
+        let config = |cassette| HttpConfig::default().cassette(cassette);
+        let cassette = Cassette::record(&path).unwrap();
+        let recorder = Transport::new(
+            base_url,
+            AuthenticationProvider::bearer("secret").unwrap(),
+            config(cassette.clone()),
+        )
+        .unwrap();
+        let mut stream = recorder
+            .post_stream("chat/completions", &json!({"model":"glm-5","stream":true}))
+            .await
+            .unwrap();
+        let mut recorded = Vec::new();
+        while let Some(chunk) = stream.chunk().await.unwrap() {
+            recorded.extend_from_slice(&chunk);
+        }
+        assert!(recorder.get_json::<Value>("models").await.is_err());
+        server.await.unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("at_ms"));
+        drop(recorder);
+        drop(cassette);
+        let file = std::fs::read_to_string(&path).unwrap();
+        assert!(!file.contains("secret") && !file.contains("session=abc"));
+        assert!(file.contains("at_ms"));
+
+        let player = Transport::new(
+            "http://127.0.0.1:1".into(),
+            AuthenticationProvider::bearer("secret").unwrap(),
+            config(Cassette::replay(&path).unwrap()),
+        )
+        .unwrap();
+        assert!(matches!(
+            player.get_json::<Value>("models").await,
+            Err(SdkError::Api(error)) if error.message == "slow down"
+        ));
+        let mut stream = player
+            .post_stream("chat/completions", &json!({"stream":true,"model":"glm-5"}))
+            .await
+            .unwrap();
+        let mut replayed = Vec::new();
+        while let Some(chunk) = stream.chunk().await.unwrap() {
+            replayed.extend_from_slice(&chunk);
+        }
+        assert_eq!(
+            String::from_utf8(replayed).unwrap(),
+            String::from_utf8(recorded)
+                .unwrap()
+                .replace("secret", "[FILTERED]")
+        );
+        assert!(player.get_json::<Value>("models").await.is_err());
+        assert!(
+            player
+                .post_json::<_, Value>("chat/completions", &json!({"model":"glm-4"}))
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn rejects_oversized_responses_before_buffering() {
         let (base_url, server) = mock_server(vec![MockResponse {
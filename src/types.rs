@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use nextjson::{Map, Value};
 use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize};
 
-use crate::wire_enum;
+use crate::{MemoryDocument, wire_enum};
 
 pub type ExtraFields = Map;
 
@@ -334,11 +334,7 @@ impl WebSearchTool {
     }
 
     pub fn count(mut self, value: u8) -> crate::Result<Self> {
-        if !(1..=50).contains(&value) {
-            return Err(crate::SdkError::Validation(
-                "web search count must be between 1 and 50".into(),
-            ));
-        }
+        validate_search_count(value)?;
         self.count = Some(value);
         Ok(self)
     }
@@ -900,6 +896,334 @@ pub struct WebSearchResult {
     pub refer: Option<String>,
 }
 
+impl WebSearchResult {
+    /// The result as a memory document keyed by its link, with title, link, media, and publish
+    /// date as metadata.
+    pub fn to_memory_document(&self) -> MemoryDocument {
+        let id = self
+            .link
+            .as_deref()
+            .or(self.title.as_deref())
+            .unwrap_or_default();
+        let text = self.content.as_deref().unwrap_or_default();
+        let mut document = MemoryDocument::new(id, text);
+        for (key, value) in [
+            ("title", &self.title),
+            ("link", &self.link),
+            ("media", &self.media),
+            ("publish_date", &self.publish_date),
+        ] {
+            if let Some(value) = value {
+                document = document.metadata(key, value.as_str());
+            }
+        }
+        document
+    }
+}
+
+/// A request to the standalone `web_search` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WebSearchRequest {
+    pub search_query: String,
+    pub search_engine: WebSearchEngine,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_intent: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_domain_filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_recency_filter: Option<WebSearchRecency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_size: Option<WebSearchContentSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Fields this crate does not model yet, sent as-is.
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
+impl WebSearchRequest {
+    pub fn new(search_query: impl Into<String>, search_engine: WebSearchEngine) -> Self {
+        Self {
+            search_query: search_query.into(),
+            search_engine,
+            search_intent: None,
+            count: None,
+            search_domain_filter: None,
+            search_recency_filter: None,
+            content_size: None,
+            request_id: None,
+            user_id: None,
+            extra: ExtraFields::new(),
+        }
+    }
+
+    /// Lets the engine decide whether the query needs a search; the decision is reported in
+    /// `WebSearchResponse::search_intent`.
+    pub fn search_intent(mut self, enabled: bool) -> Self {
+        self.search_intent = Some(enabled);
+        self
+    }
+
+    pub fn count(mut self, value: u8) -> crate::Result<Self> {
+        validate_search_count(value)?;
+        self.count = Some(value);
+        Ok(self)
+    }
+
+    /// Restricts results to one domain, such as `www.example.com`.
+    pub fn domain_filter(mut self, value: impl Into<String>) -> crate::Result<Self> {
+        let value = value.into();
+        validate_search_domain(&value)?;
+        self.search_domain_filter = Some(value);
+        Ok(self)
+    }
+
+    pub fn recency(mut self, value: WebSearchRecency) -> crate::Result<Self> {
+        validate_search_recency(&value)?;
+        self.search_recency_filter = Some(value);
+        Ok(self)
+    }
+
+    pub fn content_size(mut self, value: WebSearchContentSize) -> Self {
+        self.content_size = Some(value);
+        self
+    }
+
+    pub fn request_id(mut self, value: impl Into<String>) -> Self {
+        self.request_id = Some(value.into());
+        self
+    }
+
+    pub fn user_id(mut self, value: impl Into<String>) -> Self {
+        self.user_id = Some(value.into());
+        self
+    }
+}
+
+pub(crate) fn validate_search_count(value: u8) -> crate::Result<()> {
+    if !(1..=50).contains(&value) {
+        return Err(crate::SdkError::Validation(
+            "web search count must be between 1 and 50".into(),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_search_domain(value: &str) -> crate::Result<()> {
+    let valid = value.contains('.')
+        && !value.starts_with(['.', '-'])
+        && !value.ends_with(['.', '-'])
+        && value
+            .chars()
+            .all(|character| character.is_alphanumeric() || matches!(character, '.' | '-'));
+    if !valid {
+        return Err(crate::SdkError::Validation(
+            format!("web search domain filter must be a bare domain, got {value:?}").into(),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_search_recency(value: &WebSearchRecency) -> crate::Result<()> {
+    if let WebSearchRecency::Other(value) = value {
+        return Err(crate::SdkError::Validation(
+            format!("unknown web search recency filter {value:?}").into(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct WebSearchIntent {
+    #[serde(default)]
+    pub query: String,
+    /// `SEARCH_ALL`, `SEARCH_NONE`, or `SEARCH_ALWAYS`.
+    #[serde(default)]
+    pub intent: String,
+    #[serde(default)]
+    pub keywords: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct WebSearchResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub created: Option<i64>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub search_intent: Vec<WebSearchIntent>,
+    #[serde(default)]
+    pub search_result: Vec<WebSearchResult>,
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
+impl WebSearchResponse {
+    /// Numbered results (`[1] title (link)` followed by the content) for use in a prompt.
+    pub fn to_context(&self) -> String {
+        let mut context = String::new();
+        for (index, result) in self.search_result.iter().enumerate() {
+            if index > 0 {
+                context.push_str("\n\n");
+            }
+            context.push_str(&format!(
+                "[{}] {}",
+                index + 1,
+                result.title.as_deref().unwrap_or_default()
+            ));
+            if let Some(link) = &result.link {
+                context.push_str(&format!(" ({link})"));
+            }
+            if let Some(content) = &result.content {
+                context.push('\n');
+                context.push_str(content);
+            }
+        }
+        context
+    }
+}
+
+wire_enum! {
+    /// Web reader output format.
+    pub enum WebReaderFormat {
+        Markdown => "markdown",
+        Text => "text",
+    }
+}
+
+/// A request to the standalone `reader` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WebReaderRequest {
+    pub url: String,
+    /// Seconds to wait for the page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_cache: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub return_format: Option<WebReaderFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retain_images: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_gfm: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_img_data_url: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with_images_summary: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with_links_summary: Option<bool>,
+    /// Fields this crate does not model yet, sent as-is.
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
+impl WebReaderRequest {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            timeout: None,
+            no_cache: None,
+            return_format: None,
+            retain_images: None,
+            no_gfm: None,
+            keep_img_data_url: None,
+            with_images_summary: None,
+            with_links_summary: None,
+            extra: ExtraFields::new(),
+        }
+    }
+
+    pub fn timeout(mut self, seconds: u32) -> crate::Result<Self> {
+        if seconds == 0 {
+            return Err(crate::SdkError::Validation(
+                "web reader timeout must be positive".into(),
+            ));
+        }
+        self.timeout = Some(seconds);
+        Ok(self)
+    }
+
+    pub fn no_cache(mut self, enabled: bool) -> Self {
+        self.no_cache = Some(enabled);
+        self
+    }
+
+    pub fn return_format(mut self, value: WebReaderFormat) -> Self {
+        self.return_format = Some(value);
+        self
+    }
+
+    pub fn retain_images(mut self, enabled: bool) -> Self {
+        self.retain_images = Some(enabled);
+        self
+    }
+
+    pub fn with_links_summary(mut self, enabled: bool) -> Self {
+        self.with_links_summary = Some(enabled);
+        self
+    }
+
+    pub fn with_images_summary(mut self, enabled: bool) -> Self {
+        self.with_images_summary = Some(enabled);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct WebReaderResult {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub content: String,
+    /// Page metadata such as `keywords` and Open Graph tags.
+    #[serde(default)]
+    pub metadata: Map,
+    /// Linked resources, such as stylesheets, when the page lists them.
+    #[serde(default)]
+    pub external: Option<Value>,
+}
+
+impl WebReaderResult {
+    /// The page as a memory document keyed by its URL, with title and description as metadata.
+    pub fn to_memory_document(&self) -> MemoryDocument {
+        let mut document =
+            MemoryDocument::new(self.url.as_deref().unwrap_or_default(), &*self.content);
+        if let Some(title) = &self.title {
+            document = document.metadata("title", title.as_str());
+        }
+        if let Some(description) = &self.description {
+            document = document.metadata("description", description.as_str());
+        }
+        document
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct WebReaderResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub created: Option<i64>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub reader_result: WebReaderResult,
+    #[serde(flatten, default)]
+    pub extra: ExtraFields,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
 pub struct VideoResult {
     #[serde(default)]
@@ -1502,6 +1826,16 @@ impl Default for BatchStatus {
     }
 }
 
+impl BatchStatus {
+    /// Whether the batch has stopped and will not change status again.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            Self::Completed | Self::Failed | Self::Expired | Self::Cancelled
+        )
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct BatchCreateRequest {
     pub input_file_id: String,
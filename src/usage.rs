@@ -0,0 +1,669 @@
+//! Usage accounting: token and unit totals per model, tag, and user, with price-table cost
+//! estimates and budgets that stop further requests once spent.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use nextjson::{NsonDeserialize as Deserialize, NsonSerialize as Serialize, Value};
+
+use crate::transport::path_has_prefix;
+use crate::{BudgetError, Result, SdkError, Usage};
+
+const IMAGE_PATH: &str = "images/generations";
+const VIDEO_PATH: &str = "videos/generations";
+const AUDIO_PATHS: [&str; 2] = ["audio/speech", "audio/transcriptions"];
+/// Counting tokens is free, so tokenizer calls are neither recorded nor budgeted.
+const UNMETERED_PATHS: [&str; 1] = ["tokenizer"];
+
+/// One billable call, as recorded by [`UsageLedger::record`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageRecord {
+    pub model: String,
+    pub tag: Option<String>,
+    pub user_id: Option<String>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Prompt tokens served from the provider's context cache; a subset of `prompt_tokens`.
+    pub cached_tokens: u64,
+    pub total_tokens: u64,
+    pub images: u64,
+    pub videos: u64,
+    /// Speech and transcription calls.
+    pub audio: u64,
+}
+
+impl UsageRecord {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn from_usage(model: impl Into<String>, usage: &Usage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cached_tokens: usage
+                .prompt_tokens_details
+                .as_ref()
+                .map_or(0, |details| details.cached_tokens),
+            total_tokens: usage.total_tokens,
+            ..Self::new(model)
+        }
+    }
+
+    pub fn tag(mut self, value: impl Into<String>) -> Self {
+        self.tag = Some(value.into());
+        self
+    }
+
+    pub fn user_id(mut self, value: impl Into<String>) -> Self {
+        self.user_id = Some(value.into());
+        self
+    }
+
+    pub fn images(mut self, value: u64) -> Self {
+        self.images = value;
+        self
+    }
+
+    pub fn videos(mut self, value: u64) -> Self {
+        self.videos = value;
+        self
+    }
+
+    pub fn audio(mut self, value: u64) -> Self {
+        self.audio = value;
+        self
+    }
+}
+
+/// Running totals for one model, tag, or user.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    #[serde(default)]
+    pub requests: u64,
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub cached_tokens: u64,
+    #[serde(default)]
+    pub total_tokens: u64,
+    #[serde(default)]
+    pub images: u64,
+    #[serde(default)]
+    pub videos: u64,
+    #[serde(default)]
+    pub audio: u64,
+    /// Estimated from the ledger's [`PriceTable`]; zero for models without a price.
+    #[serde(default)]
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord, cost: f64) {
+        self.requests += 1;
+        self.prompt_tokens += record.prompt_tokens;
+        self.completion_tokens += record.completion_tokens;
+        self.cached_tokens += record.cached_tokens;
+        self.total_tokens += record.total_tokens;
+        self.images += record.images;
+        self.videos += record.videos;
+        self.audio += record.audio;
+        self.cost += cost;
+    }
+}
+
+/// Prices for one model in any currency: tokens per million, other units per item.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    #[serde(default)]
+    pub input_per_million: f64,
+    /// Defaults to `input_per_million` when unset.
+    #[serde(default)]
+    pub cached_input_per_million: Option<f64>,
+    #[serde(default)]
+    pub output_per_million: f64,
+    #[serde(default)]
+    pub per_image: f64,
+    #[serde(default)]
+    pub per_video: f64,
+    #[serde(default)]
+    pub per_audio: f64,
+}
+
+impl ModelPrice {
+    pub fn tokens(input_per_million: f64, output_per_million: f64) -> Self {
+        Self {
+            input_per_million,
+            output_per_million,
+            ..Self::default()
+        }
+    }
+
+    pub fn cached_input(mut self, per_million: f64) -> Self {
+        self.cached_input_per_million = Some(per_million);
+        self
+    }
+
+    pub fn per_image(mut self, value: f64) -> Self {
+        self.per_image = value;
+        self
+    }
+
+    pub fn per_video(mut self, value: f64) -> Self {
+        self.per_video = value;
+        self
+    }
+
+    pub fn per_audio(mut self, value: f64) -> Self {
+        self.per_audio = value;
+        self
+    }
+
+    /// Responses that only report `total_tokens`, such as embeddings, are priced as input.
+    pub fn cost(&self, record: &UsageRecord) -> f64 {
+        let prompt = if record.prompt_tokens == 0 && record.completion_tokens == 0 {
+            record.total_tokens
+        } else {
+            record.prompt_tokens
+        };
+        let cached = record.cached_tokens.min(prompt);
+        let cached_price = self
+            .cached_input_per_million
+            .unwrap_or(self.input_per_million);
+        ((prompt - cached) as f64 * self.input_per_million
+            + cached as f64 * cached_price
+            + record.completion_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+            + record.images as f64 * self.per_image
+            + record.videos as f64 * self.per_video
+            + record.audio as f64 * self.per_audio
+    }
+}
+
+/// Per-model prices, looked up by exact model name with an optional fallback.
+///
+/// Serializes as `{"models": {"glm-5": {...}}, "fallback": {...}}`, so a table can live in a
+/// config file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PriceTable {
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelPrice>,
+    #[serde(default)]
+    pub fallback: Option<ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn model(mut self, name: impl Into<String>, price: ModelPrice) -> Self {
+        self.models.insert(name.into(), price);
+        self
+    }
+
+    pub fn fallback(mut self, price: ModelPrice) -> Self {
+        self.fallback = Some(price);
+        self
+    }
+
+    pub fn price(&self, model: &str) -> Option<&ModelPrice> {
+        self.models.get(model).or(self.fallback.as_ref())
+    }
+}
+
+/// Limits on a ledger scope. A request is refused once any limit has been reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageBudget {
+    pub max_tokens: Option<u64>,
+    pub max_cost: Option<f64>,
+}
+
+impl UsageBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_tokens(mut self, value: u64) -> Self {
+        self.max_tokens = Some(value);
+        self
+    }
+
+    pub fn max_cost(mut self, value: f64) -> Self {
+        self.max_cost = Some(value);
+        self
+    }
+
+    fn check(&self, scope: &str, totals: Option<&UsageTotals>) -> Result<()> {
+        let totals = totals.cloned().unwrap_or_default();
+        if let Some(limit) = self.max_tokens
+            && totals.total_tokens >= limit
+        {
+            return Err(BudgetError::Tokens {
+                scope: scope.to_owned(),
+                limit,
+                spent: totals.total_tokens,
+            }
+            .into());
+        }
+        if let Some(limit) = self.max_cost
+            && totals.cost >= limit
+        {
+            return Err(BudgetError::Cost {
+                scope: scope.to_owned(),
+                limit,
+                spent: totals.cost,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// A snapshot of a [`UsageLedger`], as exported by [`UsageLedger::to_json`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    #[serde(default)]
+    pub total: UsageTotals,
+    #[serde(default)]
+    pub by_model: BTreeMap<String, UsageTotals>,
+    #[serde(default)]
+    pub by_tag: BTreeMap<String, UsageTotals>,
+    #[serde(default)]
+    pub by_user: BTreeMap<String, UsageTotals>,
+}
+
+/// Adds up usage from every call made through clients and agent runtimes it is attached to.
+///
+/// Attach it with `HttpConfig::usage_ledger` to count every API response that reports usage,
+/// image, video, or audio units, or with `AgentRuntime::usage_ledger` to count agent steps on
+/// any `ChatProvider`. Attach it to one or the other for the same calls, not both. Clones and
+/// [`tagged`](Self::tagged) handles share one set of totals. Budgets are checked before each
+/// request, so requests already in flight can overshoot a limit.
+#[derive(Clone, Default)]
+pub struct UsageLedger {
+    state: Arc<Mutex<LedgerState>>,
+    tag: Option<String>,
+}
+
+#[derive(Default)]
+struct LedgerState {
+    prices: PriceTable,
+    budget: Option<UsageBudget>,
+    tag_budgets: HashMap<String, UsageBudget>,
+    user_budgets: HashMap<String, UsageBudget>,
+    report: UsageReport,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prices(self, value: PriceTable) -> Self {
+        self.lock().prices = value;
+        self
+    }
+
+    pub fn budget(self, value: UsageBudget) -> Self {
+        self.lock().budget = Some(value);
+        self
+    }
+
+    pub fn tag_budget(self, tag: impl Into<String>, value: UsageBudget) -> Self {
+        self.lock().tag_budgets.insert(tag.into(), value);
+        self
+    }
+
+    pub fn user_budget(self, user_id: impl Into<String>, value: UsageBudget) -> Self {
+        self.lock().user_budgets.insert(user_id.into(), value);
+        self
+    }
+
+    /// A handle on the same totals that tags everything it records with `tag`.
+    pub fn tagged(&self, tag: impl Into<String>) -> Self {
+        Self {
+            state: self.state.clone(),
+            tag: Some(tag.into()),
+        }
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Adds `record`, tagged with this handle's tag unless it carries its own.
+    pub fn record(&self, mut record: UsageRecord) {
+        if record.tag.is_none() {
+            record.tag = self.tag.clone();
+        }
+        let mut state = self.lock();
+        let cost = state
+            .prices
+            .price(&record.model)
+            .map_or(0.0, |price| price.cost(&record));
+        let report = &mut state.report;
+        report.total.add(&record, cost);
+        report
+            .by_model
+            .entry(record.model.clone())
+            .or_default()
+            .add(&record, cost);
+        if let Some(tag) = &record.tag {
+            report
+                .by_tag
+                .entry(tag.clone())
+                .or_default()
+                .add(&record, cost);
+        }
+        if let Some(user_id) = &record.user_id {
+            report
+                .by_user
+                .entry(user_id.clone())
+                .or_default()
+                .add(&record, cost);
+        }
+    }
+
+    /// Fails with [`SdkError::Budget`] when the global budget, this handle's tag budget, or the
+    /// budget for `user_id` has been used up.
+    pub fn check_budget(&self, user_id: Option<&str>) -> Result<()> {
+        let state = self.lock();
+        let report = &state.report;
+        if let Some(budget) = &state.budget {
+            budget.check("ledger", Some(&report.total))?;
+        }
+        if let Some(tag) = &self.tag
+            && let Some(budget) = state.tag_budgets.get(tag)
+        {
+            budget.check(&format!("tag {tag}"), report.by_tag.get(tag))?;
+        }
+        if let Some(user_id) = user_id
+            && let Some(budget) = state.user_budgets.get(user_id)
+        {
+            budget.check(&format!("user {user_id}"), report.by_user.get(user_id))?;
+        }
+        Ok(())
+    }
+
+    pub fn report(&self) -> UsageReport {
+        self.lock().report.clone()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        nextjson::to_string_pretty(&self.report())
+            .map_err(|error| SdkError::Validation(error.to_string().into()))
+    }
+
+    /// Clears the totals; prices and budgets stay.
+    pub fn reset(&self) {
+        self.lock().report = UsageReport::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LedgerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Tracks one HTTP call for a ledger and records it when dropped, if the response reported usage
+/// or the endpoint bills by unit. Failed calls that never produced a body record nothing.
+pub(crate) struct UsageMeter {
+    ledger: UsageLedger,
+    path: String,
+    model: Option<String>,
+    user_id: Option<String>,
+    state: Mutex<MeterState>,
+}
+
+#[derive(Default)]
+struct MeterState {
+    model: Option<String>,
+    usage: Option<Value>,
+    units: u64,
+    /// Unterminated SSE line carried over between stream chunks.
+    line: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct RequestProbe {
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    user_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseProbe {
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct DataProbe {
+    #[serde(default)]
+    data: Vec<Value>,
+}
+
+impl UsageMeter {
+    /// Starts metering a call to `path`, or refuses it when a budget is spent. Only POSTs are
+    /// refused; reads such as `async-result` polls are still recorded.
+    pub(crate) fn start(
+        ledger: &UsageLedger,
+        post: bool,
+        path: &str,
+        body: Option<&[u8]>,
+    ) -> Result<Option<Self>> {
+        let path = path.trim_matches('/');
+        if UNMETERED_PATHS
+            .iter()
+            .any(|prefix| path_has_prefix(path, prefix))
+        {
+            return Ok(None);
+        }
+        let probe = body
+            .filter(|body| !body.is_empty())
+            .and_then(|body| nextjson::from_slice::<RequestProbe>(body).ok());
+        let (model, user_id) = probe.map_or((None, None), |probe| (probe.model, probe.user_id));
+        if post {
+            ledger.check_budget(user_id.as_deref())?;
+        }
+        Ok(Some(Self {
+            ledger: ledger.clone(),
+            path: path.to_owned(),
+            model,
+            user_id,
+            state: Mutex::default(),
+        }))
+    }
+
+    /// Reads usage and billable units from a complete response body.
+    pub(crate) fn settle(&self, body: &[u8]) {
+        let mut state = self.lock();
+        if let Ok(probe) = nextjson::from_slice::<ResponseProbe>(body) {
+            state.model = probe.model.filter(|model| !model.is_empty());
+            state.usage = probe.usage;
+        }
+        state.units = if path_has_prefix(&self.path, IMAGE_PATH) {
+            nextjson::from_slice::<DataProbe>(body)
+                .map_or(1, |probe| probe.data.len().max(1) as u64)
+        } else if path_has_prefix(&self.path, VIDEO_PATH)
+            || AUDIO_PATHS
+                .iter()
+                .any(|prefix| path_has_prefix(&self.path, prefix))
+        {
+            1
+        } else {
+            0
+        };
+    }
+
+    /// Scans streamed SSE bytes for events that carry `usage`; the last one wins.
+    pub(crate) fn observe(&self, chunk: &[u8]) {
+        let mut state = self.lock();
+        let state = &mut *state;
+        for data in usage_events(&mut state.line, chunk) {
+            if let Ok(probe) = nextjson::from_slice::<ResponseProbe>(&data)
+                && probe.usage.is_some()
+            {
+                state.usage = probe.usage;
+                state.model = probe
+                    .model
+                    .filter(|model| !model.is_empty())
+                    .or(state.model.take());
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MeterState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Appends `chunk` to the carried-over SSE `line` and returns the `data:` payloads of the
+/// completed lines that mention `usage`.
+pub(crate) fn usage_events(line: &mut Vec<u8>, chunk: &[u8]) -> Vec<Vec<u8>> {
+    line.extend_from_slice(chunk);
+    let Some(end) = line.iter().rposition(|byte| *byte == b'\n') else {
+        return Vec::new();
+    };
+    let complete = line.drain(..=end).collect::<Vec<_>>();
+    complete
+        .split(|byte| *byte == b'\n')
+        .filter_map(|line| line.strip_prefix(b"data:"))
+        .filter(|data| data.windows(7).any(|window| window == b"\"usage\""))
+        .map(|data| data.trim_ascii().to_vec())
+        .collect()
+}
+
+impl Drop for UsageMeter {
+    fn drop(&mut self) {
+        let state = std::mem::take(&mut *self.lock());
+        let usage = state.usage.filter(Value::is_object);
+        if usage.is_none() && state.units == 0 {
+            return;
+        }
+        let count = |field: &str| {
+            usage
+                .as_ref()
+                .and_then(|usage| usage.get(field))
+                .and_then(Value::as_u64)
+                .unwrap_or(0)
+        };
+        let cached_tokens = usage
+            .as_ref()
+            .and_then(|usage| usage.get("prompt_tokens_details"))
+            .and_then(|details| details.get("cached_tokens"))
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        let model = state
+            .model
+            .or(self.model.take())
+            .unwrap_or_else(|| self.path.clone());
+        let mut record = UsageRecord {
+            prompt_tokens: count("prompt_tokens"),
+            completion_tokens: count("completion_tokens"),
+            cached_tokens,
+            total_tokens: count("total_tokens"),
+            user_id: self.user_id.take(),
+            ..UsageRecord::new(model)
+        };
+        if path_has_prefix(&self.path, IMAGE_PATH) {
+            record.images = state.units;
+        } else if path_has_prefix(&self.path, VIDEO_PATH) {
+            record.videos = state.units;
+        } else {
+            record.audio = state.units;
+        }
+        self.ledger.record(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ledger_aggregates_prices_and_enforces_budgets() {
+        let prices: PriceTable = nextjson::from_str(
+            r#"{"models":{"glm-5":{"input_per_million":2.0,"cached_input_per_million":0.5,"output_per_million":8.0}},"fallback":{"per_image":0.1}}"#,
+        )
+        .unwrap();
+        let ledger = UsageLedger::new()
+            .prices(prices)
+            .tag_budget("eval", UsageBudget::new().max_cost(0.01))
+            .user_budget("u-1", UsageBudget::new().max_tokens(2_000_000));
+        let eval = ledger.tagged("eval");
+        eval.record(UsageRecord {
+            prompt_tokens: 1_000,
+            cached_tokens: 400,
+            completion_tokens: 500,
+            total_tokens: 1_500,
+            ..UsageRecord::new("glm-5").user_id("u-1")
+        });
+        ledger.record(UsageRecord::new("cogview-4").images(2));
+
+        let report = ledger.report();
+        assert_eq!(report.total.requests, 2);
+        assert_eq!(report.by_model["glm-5"].cached_tokens, 400);
+        assert!((report.by_model["glm-5"].cost - 0.0054).abs() < 1e-9);
+        assert!((report.by_model["cogview-4"].cost - 0.2).abs() < 1e-9);
+        assert_eq!(report.by_tag["eval"].requests, 1);
+        assert_eq!(report.by_user["u-1"].total_tokens, 1_500);
+        assert!(ledger.check_budget(Some("u-1")).is_ok());
+
+        eval.record(UsageRecord {
+            prompt_tokens: 5_000,
+            total_tokens: 5_000,
+            ..UsageRecord::new("glm-5")
+        });
+        assert!(matches!(
+            eval.check_budget(None),
+            Err(SdkError::Budget(BudgetError::Cost { .. }))
+        ));
+        assert!(ledger.check_budget(None).is_ok());
+        let exported: UsageReport = nextjson::from_str(&ledger.to_json().unwrap()).unwrap();
+        assert_eq!(exported, ledger.report());
+        ledger.reset();
+        assert_eq!(ledger.report(), UsageReport::default());
+    }
+
+    #[test]
+    fn meter_reads_usage_from_bodies_and_split_sse_chunks() {
+        let ledger = UsageLedger::new();
+        let meter = UsageMeter::start(
+            &ledger,
+            true,
+            "chat/completions",
+            Some(br#"{"model":"glm-5","user_id":"u-2"}"#),
+        )
+        .unwrap()
+        .unwrap();
+        meter.observe(b"data: {\"choices\":[]}\n\ndata: {\"usage\":{\"prompt_to");
+        meter.observe(b"kens\":3,\"completion_tokens\":4,\"total_tokens\":7}}\n\ndata: [DONE]\n\n");
+        drop(meter);
+        let meter = UsageMeter::start(&ledger, true, "images/generations", None)
+            .unwrap()
+            .unwrap();
+        meter.settle(br#"{"data":[{"url":"a"},{"url":"b"}]}"#);
+        drop(meter);
+        drop(UsageMeter::start(&ledger, true, "embeddings", None).unwrap());
+        assert!(
+            UsageMeter::start(&ledger, true, "tokenizer", None)
+                .unwrap()
+                .is_none()
+        );
+
+        let report = ledger.report();
+        assert_eq!(report.total.requests, 2);
+        assert_eq!(report.by_model["glm-5"].total_tokens, 7);
+        assert_eq!(report.by_user["u-2"].completion_tokens, 4);
+        assert_eq!(report.by_model["images/generations"].images, 2);
+    }
+}